@@ -1,37 +1,51 @@
 use eframe::egui;
-use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
+use egui_commonmark::{Alert, AlertBundle, CommonMarkCache, CommonMarkViewer};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
 use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+use crate::backend::{Backend, ViewOptions};
+use crate::core::diff::{self, ChangeKind};
+use crate::core::document::Document;
+use crate::core::export::{self, DiagramFormat};
+use crate::core::history::{self, History, LinkTarget};
+use crate::core::markdown::{alert_style, ALERT_TYPES};
+use crate::core::math::{self, math_blocks_to_dollars};
+use crate::core::mermaid::{self, preprocess_mermaid_for_egui, Pending, Theme};
+use crate::core::raster::{self, file_to_data_uri, svg_file_to_png_data_uri};
+use crate::core::watcher::{FileWatcher, TreeWatcher};
+use crate::core::workspace::{TreeEntry, Workspace};
+
+/// How long sections changed by a reload stay tinted.
+const CHANGE_MARK_DURATION: Duration = Duration::from_secs(5);
+
+/// Native GUI window.
+pub struct Egui;
+
+impl Backend for Egui {
+    fn run(&self, document: Document, options: ViewOptions) -> Result<(), Box<dyn std::error::Error>> {
+        run(document, options)
+    }
+}
 
-use crate::core::mermaid::preprocess_mermaid_for_egui;
-use crate::core::toc::{self, TocEntry};
-
-pub fn run(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    let canonical_file = std::fs::canonicalize(&file_path)
-        .unwrap_or_else(|_| {
-            std::env::current_dir()
-                .map(|cwd| cwd.join(&file_path))
-                .unwrap_or_else(|_| file_path.clone())
-        });
-    let base_dir = canonical_file.parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
-    let raw_markdown = std::fs::read_to_string(&file_path)
-        .unwrap_or_else(|e| format!("# Error\nCould not read `{}`: {}", file_path.display(), e));
-
-    let toc_entries = toc::extract_toc(&raw_markdown);
-    let markdown = preprocess_mermaid_for_egui(&raw_markdown);
-    let markdown = resolve_local_image_paths(&markdown, &base_dir);
-    let (has_preamble, sections) = split_by_headings(&markdown);
-
-    let watcher_rx = crate::core::watcher::watch_file(&file_path)?;
+fn run(document: Document, ViewOptions { workspace, follow }: ViewOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut watcher, watcher_rx) = FileWatcher::new(document.path())?;
+    watcher.watch_assets(document.asset_paths());
+    let tree_watcher = match &workspace {
+        Some(ws) => Some(TreeWatcher::new(ws.root())?),
+        None => None,
+    };
 
     let (icon_rgba, icon_w, icon_h) = crate::core::icon::load_icon_rgba();
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1100.0, 900.0])
-            .with_title(format!("mdr - {}", file_path.display()))
+            .with_title(format!("mdr - {}", document.header()))
             .with_icon(egui::IconData {
                 rgba: icon_rgba,
                 width: icon_w,
@@ -40,31 +54,153 @@ pub fn run(file_path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
         ..Default::default()
     };
 
-    let file_path_clone = file_path.clone();
     eframe::run_native(
         "mdr",
         options,
-        Box::new(move |_cc| {
-            Ok(Box::new(MdrApp {
-                markdown,
-                sections,
-                has_preamble,
+        Box::new(move |cc| {
+            let mut app = MdrApp {
+                markdown: String::new(),
+                sections: Vec::new(),
+                has_preamble: false,
                 caches: Vec::new(),
-                file_path: file_path_clone,
-                base_dir,
+                theme: diagram_theme(&cc.egui_ctx),
+                pending: Rc::new(RefCell::new(Pending::default())),
+                math_images: Rc::new(RefCell::new(HashMap::new())),
+                history: History::new(document.path().to_path_buf()),
+                document,
+                watcher,
                 watcher_rx,
-                toc_entries,
+                tree: workspace.as_ref().map(Workspace::tree).unwrap_or_default(),
+                workspace,
+                tree_watcher,
                 scroll_to_section: None,
                 search_active: false,
                 search_query: String::new(),
                 search_section_matches: Vec::new(),
                 current_match: 0,
-            }))
+                scroll_offset: 0.0,
+                pending_scroll: None,
+                status: None,
+                saved_diagram: None,
+                changed_sections: Vec::new(),
+                changes_until: None,
+                change_cursor: 0,
+                follow,
+                at_bottom: false,
+            };
+            app.set_content();
+            Ok(Box::new(app))
         }),
     )
     .map_err(|e| e.to_string().into())
 }
 
+/// Colours to draw diagrams in, to match the current egui theme.
+fn diagram_theme(ctx: &egui::Context) -> Theme {
+    if ctx.style().visuals.dark_mode { Theme::Dark } else { Theme::Light }
+}
+
+/// Background for a section changed by a reload: green for new, amber for edited.
+fn change_tint(kind: ChangeKind) -> egui::Color32 {
+    match kind {
+        ChangeKind::Inserted => egui::Color32::from_rgba_unmultiplied(46, 160, 67, 40),
+        ChangeKind::Modified => egui::Color32::from_rgba_unmultiplied(210, 153, 34, 40),
+    }
+}
+
+/// GitHub alerts (`> [!NOTE]`) with the icons and colours the other backends use.
+fn alert_bundle(dark_mode: bool) -> AlertBundle {
+    AlertBundle::from_alerts(ALERT_TYPES.into_iter()
+        .map(|alert_type| {
+            let style = alert_style(alert_type);
+            let [r, g, b] = if dark_mode { style.dark } else { style.light };
+            Alert {
+                accent_color: egui::Color32::from_rgb(r, g, b),
+                icon: style.icon,
+                identifier: style.label.to_uppercase(),
+                identifier_rendered: style.label.to_string(),
+            }
+        })
+        .collect())
+}
+
+/// A formula rasterized for display: its image URI, PNG bytes and size in points.
+type MathImage = (String, Arc<[u8]>, egui::Vec2);
+/// Rendered formulas by source, display mode and text color; `None` if the
+/// formula could not be rendered.
+type MathImages = RefCell<HashMap<(String, bool, egui::Color32), Option<MathImage>>>;
+
+/// Draw TeX math as an image rendered by [`math::to_svg`] in the current text
+/// color, or as red source if it cannot be rendered.
+fn show_math(ui: &mut egui::Ui, images: &MathImages, tex: &str, inline: bool) {
+    let color = ui.visuals().text_color();
+    let font_px = ui.style().text_styles.get(&egui::TextStyle::Body).map_or(14.0, |font| font.size);
+    let mut images = images.borrow_mut();
+    let image = images.entry((tex.to_string(), inline, color)).or_insert_with(|| {
+        let paint = format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b());
+        let svg = math::to_svg(tex, !inline, font_px, &paint).ok()?;
+        // Rendered at 2x so formulas stay sharp on high-DPI screens
+        let png = raster::svg_to_png(&svg.markup, 2.0).ok()?;
+        let uri = format!("bytes://math/{}.png", egui::Id::new((tex, inline, color)).value());
+        Some((uri, Arc::from(png), egui::vec2(svg.width, svg.height)))
+    });
+    match image {
+        Some((uri, png, size)) => {
+            ui.add(egui::Image::from_bytes(uri.clone(), Arc::clone(png)).fit_to_exact_size(*size));
+        }
+        None => {
+            ui.label(egui::RichText::new(tex).code().color(ui.visuals().error_fg_color));
+        }
+    }
+}
+
+/// A diagram picked from its context menu to be saved: its number in the
+/// document, source, format and PNG scale.
+type DiagramSave = (usize, String, DiagramFormat, f32);
+
+/// Draw an HTML block: a Mermaid diagram left by [`preprocess_mermaid_for_egui`],
+/// with a context menu to save it as SVG or PNG (or a placeholder while it
+/// renders, or its error), or other HTML as text.
+fn show_html(ui: &mut egui::Ui, html: &str, theme: Theme, pending: &RefCell<Pending>, save: &RefCell<Option<DiagramSave>>) {
+    let Some((number, source)) = mermaid::egui_diagram(html) else {
+        ui.label(html.trim_end());
+        return;
+    };
+    let png = match mermaid::egui_diagram_png(&source, theme, &mut pending.borrow_mut()) {
+        Some(Ok(png)) => png,
+        Some(Err(e)) => {
+            ui.colored_label(ui.visuals().error_fg_color, format!("◇ Mermaid error: {}", e));
+            ui.monospace(mermaid::with_caret(&source, &e));
+            return;
+        }
+        None => {
+            ui.weak("◇ Mermaid Diagram (rendering…)");
+            ui.monospace(&source);
+            return;
+        }
+    };
+    let uri = format!("bytes://mermaid/{}.png", egui::Id::new((&source, theme.name())).value());
+    let image = egui::Image::from_bytes(uri, png)
+        .fit_to_original_size(1.0)
+        .max_width(ui.available_width())
+        .sense(egui::Sense::click());
+    ui.add(image).context_menu(|ui| {
+        let mut choice = None;
+        if ui.button("Save as SVG").clicked() {
+            choice = Some((DiagramFormat::Svg, 1.0));
+        }
+        for scale in [1.0, 2.0, 4.0] {
+            if ui.button(format!("Save as PNG ({}×)", scale)).clicked() {
+                choice = Some((DiagramFormat::Png, scale));
+            }
+        }
+        if let Some((format, scale)) = choice {
+            *save.borrow_mut() = Some((number, source.clone(), format, scale));
+            ui.close();
+        }
+    });
+}
+
 /// Split markdown into sections at heading boundaries.
 /// Returns (has_preamble, sections) where has_preamble is true if there's
 /// content before the first heading (which means headings start at index 1).
@@ -104,15 +240,197 @@ struct MdrApp {
     sections: Vec<String>,
     has_preamble: bool,
     caches: Vec<CommonMarkCache>,
-    file_path: PathBuf,
-    base_dir: PathBuf,
+    /// Theme the diagrams are drawn in, following light or dark mode.
+    theme: Theme,
+    /// Diagrams drawn as placeholders this frame, rendered in the background
+    /// once it is done; each is drawn from the cache when it lands there.
+    pending: Rc<RefCell<Pending>>,
+    math_images: Rc<MathImages>,
+    document: Document,
+    history: History,
+    watcher: FileWatcher,
     watcher_rx: Receiver<()>,
-    toc_entries: Vec<TocEntry>,
+    /// The directory opened with `mdr <dir>`, if any, and its file tree.
+    workspace: Option<Workspace>,
+    tree: Vec<TreeEntry>,
+    tree_watcher: Option<(TreeWatcher, Receiver<()>)>,
     scroll_to_section: Option<usize>,
     search_active: bool,
     search_query: String,
     search_section_matches: Vec<usize>,
     current_match: usize,
+    /// Vertical scroll offset of the content area, as of the last frame.
+    scroll_offset: f32,
+    /// Scroll offset to restore on the next frame after switching documents.
+    pending_scroll: Option<f32>,
+    /// Error from the last attempt to open a linked document or save a diagram.
+    status: Option<String>,
+    /// Where the last diagram saved from its context menu went.
+    saved_diagram: Option<PathBuf>,
+    /// Sections changed by the last reload, tinted until `changes_until`.
+    changed_sections: Vec<(usize, ChangeKind)>,
+    changes_until: Option<Instant>,
+    /// Index into `changed_sections` of the next one to jump to.
+    change_cursor: usize,
+    /// Follow mode (F): stay at the bottom as the document grows.
+    follow: bool,
+    /// Whether the content was scrolled to the end, as of the last frame.
+    at_bottom: bool,
+}
+
+impl MdrApp {
+    /// Re-render after the document changed.
+    fn set_content(&mut self) {
+        self.watcher.watch_assets(self.document.asset_paths());
+        // Front matter is blanked to empty lines, which would make an empty first section
+        self.markdown = preprocess_mermaid_for_egui(self.document.body().trim_start_matches('\n'));
+        self.markdown = math_blocks_to_dollars(&self.markdown);
+        self.markdown = resolve_local_image_paths(&self.markdown, self.document.base_dir());
+        let (has_preamble, sections) = split_by_headings(&self.markdown);
+        self.has_preamble = has_preamble;
+        self.sections = sections;
+        self.caches.clear();
+    }
+
+    /// Show another file in place of the current one and watch it instead.
+    fn load_document(&mut self, ctx: &egui::Context, path: PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+        let document = Document::open(path)?;
+        self.watcher.retarget(document.path())?;
+        while self.watcher_rx.try_recv().is_ok() {}
+        self.document = document;
+        self.saved_diagram = None;
+        self.set_content();
+        self.clear_changes();
+        self.search_active = false;
+        self.search_query.clear();
+        self.search_section_matches.clear();
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(format!("mdr - {}", self.document.header())));
+        Ok(())
+    }
+
+    /// Follow a clicked `.md` link or in-document anchor.
+    fn follow_link(&mut self, ctx: &egui::Context, target: LinkTarget) {
+        match target {
+            LinkTarget::Anchor(anchor) => self.scroll_to_anchor(&anchor),
+            LinkTarget::Document { path, anchor } => {
+                if self.open_document(ctx, path) {
+                    if let Some(anchor) = anchor {
+                        self.scroll_to_anchor(&anchor);
+                    }
+                }
+            }
+            // External links are left to egui, which opens them in the browser
+            LinkTarget::External(_) => {}
+        }
+    }
+
+    /// Open another Markdown file as a new history entry. Returns false (and
+    /// shows why in the sidebar) if it cannot be read.
+    fn open_document(&mut self, ctx: &egui::Context, path: PathBuf) -> bool {
+        self.history.set_scroll(self.scroll_offset as f64);
+        match self.load_document(ctx, path.clone()) {
+            Ok(()) => {
+                self.history.navigate(path);
+                self.status = None;
+                self.pending_scroll = Some(0.0);
+                true
+            }
+            Err(e) => {
+                self.status = Some(format!("Cannot open {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    /// Rescan the workspace after files were added, renamed or removed. If the
+    /// displayed document is gone, fall back to the workspace's default file.
+    fn refresh_tree(&mut self, ctx: &egui::Context) {
+        let Some(workspace) = self.workspace.as_mut() else { return };
+        if !workspace.rescan() {
+            return;
+        }
+        self.tree = workspace.tree();
+        let fallback = workspace.default_file().cloned();
+        if !self.document.path().exists() {
+            if let Some(path) = fallback {
+                self.open_document(ctx, path);
+            }
+        }
+    }
+
+    /// Reload the current file from disk and mark the sections that changed.
+    /// Sections are the blocks egui renders separately, so they are the unit
+    /// of comparison here.
+    fn reload(&mut self, ctx: &egui::Context) {
+        if self.document.reload().is_err() {
+            return;
+        }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(format!("mdr - {}", self.document.header())));
+        let old_sections = std::mem::take(&mut self.sections);
+        self.set_content();
+        self.changed_sections = diff::diff_blocks(&old_sections, &self.sections)
+            .into_iter()
+            .map(|change| (change.index, change.kind))
+            .collect();
+        self.changes_until = Some(Instant::now() + CHANGE_MARK_DURATION);
+        self.change_cursor = 0;
+        // In follow mode the scroll area sticks to the bottom by itself;
+        // a reader elsewhere is taken to the first change instead
+        if self.follow && !self.at_bottom {
+            if let Some(&(section, _)) = self.changed_sections.first() {
+                self.scroll_to_section = Some(section);
+            }
+        }
+    }
+
+    /// Turn follow mode on or off. Turning it on jumps to the end, which the
+    /// scroll area clamps to the last screenful and then sticks to.
+    fn set_follow(&mut self, follow: bool) {
+        self.follow = follow;
+        if follow {
+            self.pending_scroll = Some(f32::MAX);
+        }
+    }
+
+    fn clear_changes(&mut self) {
+        self.changed_sections.clear();
+        self.changes_until = None;
+    }
+
+    /// Scroll to the next changed section, cycling, and keep the tint up a while longer.
+    fn next_change(&mut self) {
+        let Some(&(section, _)) = self.changed_sections.get(self.change_cursor % self.changed_sections.len().max(1)) else { return };
+        self.scroll_to_section = Some(section);
+        self.change_cursor += 1;
+        self.changes_until = Some(Instant::now() + CHANGE_MARK_DURATION);
+    }
+
+    fn scroll_to_anchor(&mut self, anchor: &str) {
+        if let Some(i) = self.document.toc().iter().position(|e| e.anchor == anchor) {
+            self.scroll_to_section = Some(if self.has_preamble { i + 1 } else { i });
+        }
+    }
+
+    /// Go back or forward through visited documents, restoring their scroll offsets.
+    fn step_history(&mut self, ctx: &egui::Context, forward: bool) {
+        self.history.set_scroll(self.scroll_offset as f64);
+        let entry = if forward { self.history.forward() } else { self.history.back() };
+        let Some(entry) = entry.cloned() else { return };
+        match self.load_document(ctx, entry.path.clone()) {
+            Ok(()) => {
+                self.status = None;
+                self.pending_scroll = Some(entry.scroll as f32);
+            }
+            Err(e) => {
+                if forward {
+                    self.history.back();
+                } else {
+                    self.history.forward();
+                }
+                self.status = Some(format!("Cannot open {}: {}", entry.path.display(), e));
+            }
+        }
+    }
 }
 
 impl eframe::App for MdrApp {
@@ -123,17 +441,31 @@ impl eframe::App for MdrApp {
         // Check for file changes
         if self.watcher_rx.try_recv().is_ok() {
             while self.watcher_rx.try_recv().is_ok() {}
-            if let Ok(content) = std::fs::read_to_string(&self.file_path) {
-                self.toc_entries = toc::extract_toc(&content);
-                self.markdown = preprocess_mermaid_for_egui(&content);
-                self.markdown = resolve_local_image_paths(&self.markdown, &self.base_dir);
-                let (has_preamble, sections) = split_by_headings(&self.markdown);
-                self.has_preamble = has_preamble;
-                self.sections = sections;
-                self.caches.clear();
+            self.reload(ctx);
+        }
+        // Switching between light and dark draws the diagrams to match
+        self.theme = diagram_theme(ctx);
+        if self.changes_until.is_some_and(|until| Instant::now() >= until) {
+            self.clear_changes();
+        }
+        if let Some((_, rx)) = &self.tree_watcher {
+            if rx.try_recv().is_ok() {
+                while rx.try_recv().is_ok() {}
+                self.refresh_tree(ctx);
             }
         }
 
+        // Alt+Left/Right and the mouse back/forward buttons walk the history
+        let (back, forward) = ctx.input(|i| (
+            (i.modifiers.alt && i.key_pressed(egui::Key::ArrowLeft)) || i.pointer.button_pressed(egui::PointerButton::Extra1),
+            (i.modifiers.alt && i.key_pressed(egui::Key::ArrowRight)) || i.pointer.button_pressed(egui::PointerButton::Extra2),
+        ));
+        if back {
+            self.step_history(ctx, false);
+        } else if forward {
+            self.step_history(ctx, true);
+        }
+
         // Ensure we have enough caches
         while self.caches.len() < self.sections.len() {
             self.caches.push(CommonMarkCache::default());
@@ -151,6 +483,17 @@ impl eframe::App for MdrApp {
             self.search_active = false;
             self.search_query.clear();
             self.search_section_matches.clear();
+        } else if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.clear_changes();
+        }
+
+        // C jumps to the next section changed by the last reload
+        if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::C) && i.modifiers.is_none()) {
+            self.next_change();
+        }
+        // Shift+F toggles follow mode
+        if !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(egui::Key::F) && i.modifiers.shift_only()) {
+            self.set_follow(!self.follow);
         }
 
         // Search bar panel
@@ -216,14 +559,72 @@ impl eframe::App for MdrApp {
         // TOC sidebar
         let has_preamble = self.has_preamble;
         let scroll_target = &mut self.scroll_to_section;
+        let mut history_step = None;
+        let mut open_file = None;
+        let mut follow_toggled = None;
 
         egui::SidePanel::left("toc_panel")
             .default_width(220.0)
             .show(ctx, |ui| {
+                if self.history.can_go_back() || self.history.can_go_forward() {
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(self.history.can_go_back(), egui::Button::new("\u{25C0}"))
+                            .on_hover_text("Back (Alt+Left)").clicked()
+                        {
+                            history_step = Some(false);
+                        }
+                        if ui.add_enabled(self.history.can_go_forward(), egui::Button::new("\u{25B6}"))
+                            .on_hover_text("Forward (Alt+Right)").clicked()
+                        {
+                            history_step = Some(true);
+                        }
+                    });
+                    ui.separator();
+                }
+                if let Some(status) = &self.status {
+                    ui.colored_label(ui.visuals().error_fg_color, status);
+                }
+                if let Some(path) = &self.saved_diagram {
+                    ui.weak(format!("Saved {}", path.display()));
+                }
+                let mut follow = self.follow;
+                if ui.checkbox(&mut follow, "Follow").on_hover_text("Stay at the bottom as the file grows (Shift+F)").changed() {
+                    follow_toggled = Some(follow);
+                }
+                if !self.changed_sections.is_empty() {
+                    let count = self.changed_sections.len();
+                    ui.weak(format!("{} changed section{} (C: next, Esc: dismiss)", count, if count == 1 { "" } else { "s" }));
+                }
+                if self.workspace.is_some() {
+                    ui.heading("Files");
+                    ui.separator();
+                    let current = self.document.path().canonicalize().unwrap_or_else(|_| self.document.path().to_path_buf());
+                    egui::ScrollArea::vertical()
+                        .id_salt("file_tree")
+                        .max_height(ui.available_height() * 0.4)
+                        .show(ui, |ui| {
+                            for entry in &self.tree {
+                                ui.horizontal(|ui| {
+                                    ui.add_space(entry.depth as f32 * 12.0);
+                                    match &entry.path {
+                                        None => {
+                                            ui.label(egui::RichText::new(format!("{}/", entry.name)).strong());
+                                        }
+                                        Some(path) => {
+                                            if ui.selectable_label(*path == current, &entry.name).clicked() && *path != current {
+                                                open_file = Some(path.clone());
+                                            }
+                                        }
+                                    }
+                                });
+                            }
+                        });
+                    ui.separator();
+                }
                 ui.heading("Table of Contents");
                 ui.separator();
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    for (i, entry) in self.toc_entries.iter().enumerate() {
+                egui::ScrollArea::vertical().id_salt("toc").show(ui, |ui| {
+                    for (i, entry) in self.document.toc().iter().enumerate() {
                         let indent = ((entry.level as f32 - 1.0) * 12.0).max(0.0);
                         ui.horizontal(|ui| {
                             ui.add_space(indent);
@@ -243,11 +644,42 @@ impl eframe::App for MdrApp {
                 });
             });
 
+        if let Some(forward) = history_step {
+            self.step_history(ctx, forward);
+        }
+        if let Some(path) = open_file {
+            self.open_document(ctx, path);
+        }
+        if let Some(follow) = follow_toggled {
+            self.set_follow(follow);
+        }
+
         // Main content - render each section with scroll anchors
         let scroll_to = self.scroll_to_section.take();
+        let mut scroll_area = egui::ScrollArea::vertical().stick_to_bottom(self.follow);
+        if let Some(offset) = self.pending_scroll.take() {
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+        }
 
+        let diagram_save = Rc::new(RefCell::new(None));
         egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::vertical().show(ui, |ui| {
+            let output = scroll_area.show(ui, |ui| {
+                if let Some(front_matter) = self.document.front_matter().filter(|fm| !fm.fields.is_empty()) {
+                    egui::CollapsingHeader::new("Metadata").id_salt("front_matter").show(ui, |ui| {
+                        egui::Grid::new("front_matter_fields").num_columns(2).striped(true).show(ui, |ui| {
+                            for (key, value) in &front_matter.fields {
+                                ui.strong(key);
+                                ui.label(value);
+                                ui.end_row();
+                            }
+                        });
+                    });
+                }
+                let alerts = alert_bundle(ui.visuals().dark_mode);
+                let math_images = Rc::clone(&self.math_images);
+                let render_math = move |ui: &mut egui::Ui, tex: &str, inline: bool| show_math(ui, &math_images, tex, inline);
+                let (theme, pending, save) = (self.theme, Rc::clone(&self.pending), Rc::clone(&diagram_save));
+                let render_html = move |ui: &mut egui::Ui, html: &str| show_html(ui, html, theme, &pending, &save);
                 for (i, section) in self.sections.iter().enumerate() {
                     // Place an invisible anchor widget before the section
                     let response = ui.allocate_response(
@@ -260,15 +692,58 @@ impl eframe::App for MdrApp {
                         response.scroll_to_me(Some(egui::Align::TOP));
                     }
 
-                    // Render the section
+                    // Render the section, tinted if the last reload changed it
                     let anchor_id = ui.id().with(format!("section_{}", i));
-                    ui.push_id(anchor_id, |ui| {
-                        CommonMarkViewer::new()
-                            .show(ui, &mut self.caches[i], section);
+                    let change = self.changed_sections.iter().find(|(index, _)| *index == i).map(|(_, kind)| *kind);
+                    ui.push_id(anchor_id, |ui| match change {
+                        Some(kind) => {
+                            egui::Frame::new().fill(change_tint(kind)).inner_margin(4.0).show(ui, |ui| {
+                                CommonMarkViewer::new().alerts(alerts.clone()).render_math_fn(Some(&render_math)).render_html_fn(Some(&render_html)).show(ui, &mut self.caches[i], section);
+                            });
+                        }
+                        None => {
+                            CommonMarkViewer::new().alerts(alerts.clone()).render_math_fn(Some(&render_math)).render_html_fn(Some(&render_html)).show(ui, &mut self.caches[i], section);
+                        }
                     });
                 }
             });
+            self.scroll_offset = output.state.offset.y;
+            self.at_bottom = output.state.offset.y + output.inner_rect.height() >= output.content_size.y - 1.0;
         });
+        // Render the placeholder diagrams in the background, then draw each one as it lands
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        if !pending.is_empty() {
+            let ctx = ctx.clone();
+            pending.spawn(move || ctx.request_repaint());
+        }
+        if let Some((number, source, format, scale)) = diagram_save.take() {
+            match export::save_diagram_beside(&self.document, number, &source, format, scale, self.theme) {
+                Ok(path) => {
+                    self.status = None;
+                    self.saved_diagram = Some(path);
+                }
+                Err(e) => {
+                    self.status = Some(format!("Cannot save diagram {}: {}", number, e));
+                    self.saved_diagram = None;
+                }
+            }
+        }
+
+        // Relative .md links and anchors open in place instead of in the browser
+        let mut link_target = None;
+        ctx.output_mut(|o| o.commands.retain(|command| {
+            let egui::OutputCommand::OpenUrl(open) = command else { return true };
+            match history::classify_link(&open.url, self.document.base_dir()) {
+                LinkTarget::External(_) => true,
+                target => {
+                    link_target = Some(target);
+                    false
+                }
+            }
+        }));
+        if let Some(target) = link_target {
+            self.follow_link(ctx, target);
+        }
 
         ctx.request_repaint_after(std::time::Duration::from_millis(500));
     }
@@ -412,7 +887,7 @@ fn resolve_local_image_paths(markdown: &str, base_dir: &std::path::Path) -> Stri
                 .unwrap_or(false);
             if is_svg {
                 // Try rasterizing SVG to PNG (handles complex SVGs better)
-                if let Ok(data_uri) = rasterize_svg_to_png_data_uri(&abs_path) {
+                if let Ok(data_uri) = svg_file_to_png_data_uri(&abs_path) {
                     return format!("![{}]({})", alt, data_uri);
                 }
                 // Fallback: embed SVG directly as data URI for egui_commonmark's SVG feature
@@ -434,80 +909,3 @@ fn resolve_local_image_paths(markdown: &str, base_dir: &std::path::Path) -> Stri
     .to_string()
 }
 
-/// Convert a local file to a base64 data URI string.
-fn file_to_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
-    use base64::Engine;
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-    let mime = match ext.to_lowercase().as_str() {
-        "png" => "image/png",
-        "jpg" | "jpeg" => "image/jpeg",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
-        "svg" => "image/svg+xml",
-        "bmp" => "image/bmp",
-        "ico" => "image/x-icon",
-        _ => "application/octet-stream",
-    };
-    let data = std::fs::read(path)?;
-    let b64 = base64::engine::general_purpose::STANDARD.encode(&data);
-    Ok(format!("data:{};base64,{}", mime, b64))
-}
-
-/// Rasterize an SVG file to PNG and return as a base64 data URI.
-/// Caps dimensions at 8192px to avoid GPU texture overflow.
-fn rasterize_svg_to_png_data_uri(path: &std::path::Path) -> Result<String, Box<dyn std::error::Error>> {
-    use base64::Engine;
-    use std::sync::{Arc, OnceLock};
-
-    const MAX_DIM: f32 = 8192.0;
-
-    let svg_data = std::fs::read_to_string(path)?;
-
-    // Reject files that aren't actually SVG (e.g. HTML pages saved with .svg extension)
-    let trimmed = svg_data.trim_start();
-    if !trimmed.starts_with('<') || trimmed.starts_with("<!DOCTYPE html") || trimmed.starts_with("<html") {
-        if !trimmed.contains("<svg") {
-            return Err("File is not a valid SVG (possibly an HTML page)".into());
-        }
-    }
-
-    static FONTDB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
-    let fontdb = FONTDB.get_or_init(|| {
-        let mut db = usvg::fontdb::Database::new();
-        db.load_system_fonts();
-        Arc::new(db)
-    });
-
-    let mut options = usvg::Options::default();
-    options.fontdb = Arc::clone(fontdb);
-    let tree = usvg::Tree::from_str(&svg_data, &options)?;
-    let size = tree.size();
-    let svg_w = size.width();
-    let svg_h = size.height();
-
-    if svg_w <= 0.0 || svg_h <= 0.0 {
-        return Err("SVG has zero dimensions".into());
-    }
-
-    // Scale 2x for retina, but cap at MAX_DIM
-    let ideal_scale = 2.0_f32;
-    let max_scale_w = MAX_DIM / svg_w;
-    let max_scale_h = MAX_DIM / svg_h;
-    let scale = ideal_scale.min(max_scale_w).min(max_scale_h);
-
-    let width = (svg_w * scale) as u32;
-    let height = (svg_h * scale) as u32;
-
-    if width == 0 || height == 0 {
-        return Err("SVG too small after scaling".into());
-    }
-
-    let mut pixmap = tiny_skia::Pixmap::new(width, height)
-        .ok_or("Failed to create pixmap")?;
-    let transform = tiny_skia::Transform::from_scale(scale, scale);
-    resvg::render(&tree, transform, &mut pixmap.as_mut());
-
-    let png_data = pixmap.encode_png()?;
-    let b64 = base64::engine::general_purpose::STANDARD.encode(&png_data);
-    Ok(format!("data:image/png;base64,{}", b64))
-}
@@ -1,3 +1,6 @@
+use crate::core::document::Document;
+use crate::core::workspace::Workspace;
+
 #[cfg(feature = "egui-backend")]
 pub mod egui;
 
@@ -6,3 +9,31 @@ pub mod tui;
 
 #[cfg(feature = "webview-backend")]
 pub mod webview;
+
+/// How the viewer was asked to show the document, beyond the document itself.
+#[cfg_attr(not(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend")), allow(dead_code))]
+pub struct ViewOptions {
+    /// The directory opened with `mdr <dir>`, if any.
+    pub workspace: Option<Workspace>,
+    /// Start in follow mode (`--follow`).
+    pub follow: bool,
+}
+
+/// An interactive viewer. Backends get a loaded [`Document`] and keep it in
+/// sync with the file until the user closes the viewer.
+pub trait Backend {
+    fn run(&self, document: Document, options: ViewOptions) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// The compiled-in backend called `name` (`egui`, `webview` or `tui`).
+pub fn by_name(name: &str) -> Option<Box<dyn Backend>> {
+    match name {
+        #[cfg(feature = "egui-backend")]
+        "egui" => Some(Box::new(egui::Egui)),
+        #[cfg(feature = "webview-backend")]
+        "webview" => Some(Box::new(webview::Webview)),
+        #[cfg(feature = "tui-backend")]
+        "tui" => Some(Box::new(tui::Tui)),
+        _ => None,
+    }
+}
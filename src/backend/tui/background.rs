@@ -0,0 +1,100 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::core::mermaid::Theme;
+
+/// How long to wait for the terminal to report its background colour.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Whether the terminal has a light or dark background, which code blocks
+/// and diagrams are drawn to match: from `COLORFGBG` when the terminal sets
+/// it, else from [`detect`] if it ran, else dark.
+pub fn theme() -> Theme {
+    *THEME.get_or_init(|| from_env().unwrap_or(Theme::Dark))
+}
+
+/// Settle [`theme`] for the interactive viewer, asking the terminal for its
+/// background colour (OSC 11) when `COLORFGBG` does not say. Call in raw
+/// mode, before anything else reads the terminal's input.
+pub fn detect() -> Theme {
+    *THEME.get_or_init(|| from_env().or_else(query).unwrap_or(Theme::Dark))
+}
+
+fn from_env() -> Option<Theme> {
+    from_colorfgbg(&std::env::var("COLORFGBG").ok()?)
+}
+
+/// The theme for a `COLORFGBG` value such as `15;0`, whose last field is the
+/// background's ANSI colour: 7 (light gray) and the bright colours bar 8
+/// (dark gray) are light.
+fn from_colorfgbg(value: &str) -> Option<Theme> {
+    let background: u8 = value.rsplit(';').next()?.parse().ok()?;
+    Some(if background == 7 || (background > 8 && background < 16) { Theme::Light } else { Theme::Dark })
+}
+
+/// Ask the terminal for its background colour, followed by a status report
+/// that every terminal answers so the read ends even when OSC 11 is not
+/// supported. Gives up after [`QUERY_TIMEOUT`] like the image picker does.
+fn query() -> Option<Theme> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stdout = io::stdout();
+        if stdout.write_all(b"\x1b]11;?\x1b\\\x1b[5n").and_then(|_| stdout.flush()).is_err() {
+            return;
+        }
+        let mut reply = Vec::new();
+        let mut byte = [0; 1];
+        while !reply.ends_with(b"\x1b[0n") {
+            match io::stdin().read(&mut byte) {
+                Ok(1) => reply.push(byte[0]),
+                _ => break,
+            }
+        }
+        let _ = tx.send(reply);
+    });
+    from_osc11(&rx.recv_timeout(QUERY_TIMEOUT).ok()?)
+}
+
+/// The theme for a reply to OSC 11, `ESC ] 11 ; rgb:RRRR/GGGG/BBBB` ended by
+/// ST or BEL, with one to four hex digits per channel.
+fn from_osc11(reply: &[u8]) -> Option<Theme> {
+    let reply = String::from_utf8_lossy(reply);
+    let start = reply.find("]11;rgb:")? + "]11;rgb:".len();
+    let end = reply[start..].find(['\x1b', '\x07'])? + start;
+    let mut channels = reply[start..end].split('/').map(|hex| {
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = 16u32.checked_pow(hex.len() as u32).filter(|_| (1..=4).contains(&hex.len()))? - 1;
+        Some(value as f32 / max as f32)
+    });
+    let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    Some(if luminance > 0.5 { Theme::Light } else { Theme::Dark })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colorfgbg_background_picks_the_theme() {
+        assert_eq!(from_colorfgbg("15;0"), Some(Theme::Dark));
+        assert_eq!(from_colorfgbg("0;15"), Some(Theme::Light));
+        assert_eq!(from_colorfgbg("0;default;7"), Some(Theme::Light));
+        assert_eq!(from_colorfgbg("7;8"), Some(Theme::Dark));
+        assert_eq!(from_colorfgbg("default;default"), None);
+    }
+
+    #[test]
+    fn osc11_reply_picks_the_theme() {
+        assert_eq!(from_osc11(b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\\x1b[0n"), Some(Theme::Light));
+        assert_eq!(from_osc11(b"\x1b]11;rgb:1e1e/1e1e/2e2e\x07\x1b[0n"), Some(Theme::Dark));
+        assert_eq!(from_osc11(b"\x1b]11;rgb:fd/f6/e3\x1b\\"), Some(Theme::Light));
+        // Terminals without OSC 11 only answer the status report
+        assert_eq!(from_osc11(b"\x1b[0n"), None);
+        assert_eq!(from_osc11(b"\x1b]11;rgb:zz/00/00\x1b\\"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::OnceLock;
+
+use ratatui::prelude::*;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use super::background;
+use crate::core::mermaid;
+
+/// Theme used for code blocks on a dark terminal background.
+const THEME_NAME: &str = "base16-ocean.dark";
+/// Theme used for code blocks on a light terminal background.
+const LIGHT_THEME_NAME: &str = "base16-ocean.light";
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme() -> &'static Theme {
+    static THEME: OnceLock<Theme> = OnceLock::new();
+    THEME.get_or_init(|| {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let name = match background::theme() {
+            mermaid::Theme::Light => LIGHT_THEME_NAME,
+            mermaid::Theme::Dark => THEME_NAME,
+        };
+        themes.remove(name).unwrap_or_default()
+    })
+}
+
+/// Whether the terminal advertises 24-bit colour support.
+fn truecolor() -> bool {
+    static TRUECOLOR: OnceLock<bool> = OnceLock::new();
+    *TRUECOLOR.get_or_init(|| {
+        std::env::var("COLORTERM")
+            .map(|v| v.eq_ignore_ascii_case("truecolor") || v.eq_ignore_ascii_case("24bit"))
+            .unwrap_or(false)
+    })
+}
+
+/// Highlight a code block with syntect's grammar for the fence language.
+/// Returns one vector of spans per source line, or `None` when the language
+/// is unknown so the caller can fall back to plain rendering.
+pub fn highlight_code(lang: &str, source: &str) -> Option<Vec<Vec<Span<'static>>>> {
+    highlight_code_with(lang, source, truecolor())
+}
+
+fn highlight_code_with(lang: &str, source: &str, truecolor: bool) -> Option<Vec<Vec<Span<'static>>>> {
+    if lang.is_empty() {
+        return None;
+    }
+    let syntaxes = syntax_set();
+    let syntax = syntaxes.find_syntax_by_token(lang)?;
+    let mut highlighter = HighlightLines::new(syntax, theme());
+
+    let mut lines = Vec::new();
+    for line in LinesWithEndings::from(source) {
+        let regions = highlighter.highlight_line(line, syntaxes).ok()?;
+        let spans = regions
+            .into_iter()
+            .filter_map(|(style, text)| {
+                let text = text.trim_end_matches(['\n', '\r']);
+                if text.is_empty() {
+                    return None;
+                }
+                Some(Span::styled(text.to_string(), to_style(style, truecolor)))
+            })
+            .collect();
+        lines.push(spans);
+    }
+    Some(lines)
+}
+
+/// Map a syntect style onto a ratatui style. Backgrounds are left to the
+/// terminal so code blends in with the rest of the document.
+fn to_style(style: syntect::highlighting::Style, truecolor: bool) -> Style {
+    let fg = style.foreground;
+    let color = if truecolor {
+        Color::Rgb(fg.r, fg.g, fg.b)
+    } else {
+        Color::Indexed(rgb_to_ansi256(fg.r, fg.g, fg.b))
+    };
+    let mut result = Style::default().fg(color);
+    if style.font_style.contains(FontStyle::BOLD) {
+        result = result.bold();
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        result = result.italic();
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        result = result.underlined();
+    }
+    result
+}
+
+/// Nearest xterm 256-colour index for an RGB colour, picking between the
+/// 6x6x6 colour cube and the 24-step grayscale ramp.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let cube_index = |v: u8| -> usize {
+        LEVELS.iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (**level as i32 - v as i32).abs())
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(8) / 10).min(23) as u8;
+    let gray_level = 8 + 10 * gray_index;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| -> i32 {
+        let dr = cr as i32 - r as i32;
+        let dg = cg as i32 - g as i32;
+        let db = cb as i32 - b as i32;
+        dr * dr + dg * dg + db * db
+    };
+    if distance((gray_level, gray_level, gray_level)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + (36 * ri + 6 * gi + bi) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlight_known_language_uses_several_colors() {
+        let lines = highlight_code_with("rust", "fn main() {\n    let x = \"hi\";\n}\n", true).unwrap();
+        assert_eq!(lines.len(), 3);
+        let text: String = lines[0].iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(text, "fn main() {");
+        let colors: std::collections::HashSet<_> = lines.iter().flatten().filter_map(|s| s.style.fg).collect();
+        assert!(colors.len() > 1, "expected multiple token colours, got {:?}", colors);
+        assert!(colors.iter().all(|c| matches!(c, Color::Rgb(..))));
+    }
+
+    #[test]
+    fn highlight_falls_back_to_256_colors() {
+        let lines = highlight_code_with("py", "print('x')\n", false).unwrap();
+        assert!(lines.iter().flatten().all(|s| matches!(s.style.fg, Some(Color::Indexed(_)))));
+    }
+
+    #[test]
+    fn highlight_unknown_language_is_none() {
+        assert!(highlight_code_with("definitely-not-a-language", "x", true).is_none());
+        assert!(highlight_code_with("", "x", true).is_none());
+    }
+
+    #[test]
+    fn rgb_to_ansi256_maps_cube_and_grays() {
+        assert_eq!(rgb_to_ansi256(0, 0, 0), 16);
+        assert_eq!(rgb_to_ansi256(255, 255, 255), 231);
+        assert_eq!(rgb_to_ansi256(255, 0, 0), 196);
+        assert_eq!(rgb_to_ansi256(128, 128, 128), 244);
+    }
+}
@@ -0,0 +1,210 @@
+//! Images the document shows, decoded once and kept between rebuilds so
+//! resizing or a diagram finishing does not load them all again.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use image::DynamicImage;
+
+use crate::core::raster;
+
+enum Entry {
+    /// A remote image still being fetched.
+    Loading,
+    /// `modified` is the local file's modification time when it was read, so an
+    /// edited file is read again.
+    Loaded { modified: Option<SystemTime>, image: Result<DynamicImage, String> },
+}
+
+/// Decoded images by URL. Remote images are fetched on a worker thread and
+/// `ready_tx` is signalled when one arrives, like a background-rendered diagram.
+pub struct Images {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    ready_tx: Sender<()>,
+}
+
+impl Images {
+    pub fn new(ready_tx: Sender<()>) -> Images {
+        Images { entries: Arc::default(), ready_tx }
+    }
+
+    /// The image `url` points to, relative to `base_dir`, or `None` while a
+    /// remote one is still being fetched.
+    pub fn get(&self, url: &str, base_dir: &Path) -> Option<Result<DynamicImage, String>> {
+        let remote = url.starts_with("http://") || url.starts_with("https://");
+        let (key, modified) = if remote || url.starts_with("data:") {
+            (url.to_string(), None)
+        } else {
+            let path = base_dir.join(url);
+            let modified = std::fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            (path.to_string_lossy().into_owned(), modified)
+        };
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(&key) {
+            Some(Entry::Loading) => return None,
+            Some(Entry::Loaded { modified: loaded, image }) if *loaded == modified => return Some(image.clone()),
+            _ => {}
+        }
+        if remote {
+            entries.insert(key.clone(), Entry::Loading);
+            let (entries, ready_tx) = (Arc::clone(&self.entries), self.ready_tx.clone());
+            std::thread::spawn(move || {
+                let image = load_image_from_http(&key).map_err(|e| e.to_string());
+                entries.lock().unwrap_or_else(|e| e.into_inner()).insert(key, Entry::Loaded { modified: None, image });
+                let _ = ready_tx.send(());
+            });
+            return None;
+        }
+        let image = load_image(url, base_dir).map_err(|e| e.to_string());
+        entries.insert(key, Entry::Loaded { modified, image: image.clone() });
+        Some(image)
+    }
+}
+
+/// Load an image from a URL, data URI, or local file path.
+/// SVG files are rasterized via resvg/usvg before returning.
+pub fn load_image(url: &str, base_dir: &Path) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    if url.starts_with("data:") {
+        // data: URI - decode base64
+        load_image_from_data_uri(url)
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        // HTTP fetch
+        load_image_from_http(url)
+    } else {
+        // Local file path (resolve relative to markdown file's directory)
+        let path = if Path::new(url).is_absolute() {
+            PathBuf::from(url)
+        } else {
+            base_dir.join(url)
+        };
+        // Path traversal protection: ensure resolved path is within base_dir
+        if let (Ok(canonical), Ok(canonical_base)) = (path.canonicalize(), base_dir.canonicalize()) {
+            if !canonical.starts_with(&canonical_base) {
+                return Err("path traversal blocked: image path escapes base directory".into());
+            }
+        }
+        // SVG files need rasterization
+        if path.extension().and_then(|e| e.to_str()) == Some("svg") {
+            let svg_data = std::fs::read_to_string(&path)?;
+            return rasterize_svg(&svg_data);
+        }
+        let img = image::open(&path)?;
+        Ok(img)
+    }
+}
+
+/// Load an image from a data: URI by decoding the base64 payload.
+/// Rejects data URIs larger than 50MB (base64-encoded) to prevent memory exhaustion.
+fn load_image_from_data_uri(uri: &str) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    const MAX_DATA_URI_LEN: usize = 50 * 1024 * 1024; // 50 MB
+    if uri.len() > MAX_DATA_URI_LEN {
+        return Err(format!("data URI too large ({} bytes, max {})", uri.len(), MAX_DATA_URI_LEN).into());
+    }
+    // Format: data:[<mediatype>][;base64],<data>
+    let comma_pos = uri.find(',').ok_or("Invalid data URI: no comma found")?;
+    let header = &uri[..comma_pos];
+    let data_part = &uri[comma_pos + 1..];
+    let decoded = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        data_part,
+    )?;
+    // SVG data URIs need rasterization
+    if header.contains("image/svg") {
+        let svg_str = String::from_utf8(decoded)?;
+        return rasterize_svg(&svg_str);
+    }
+    let img = image::load_from_memory(&decoded)?;
+    Ok(img)
+}
+
+/// Rasterize an SVG string to a DynamicImage at its natural size.
+fn rasterize_svg(svg_data: &str) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    let pixmap = raster::rasterize_svg(svg_data, 1.0)?;
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let img = image::RgbaImage::from_raw(width, height, pixmap.take())
+        .ok_or("Failed to create image from pixmap")?;
+    Ok(image::DynamicImage::ImageRgba8(img))
+}
+
+/// Load an image from an HTTP(S) URL using ureq.
+fn load_image_from_http(url: &str) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
+    let response = ureq::get(url).call()?;
+    let mut bytes = Vec::new();
+    response.into_body().into_reader().read_to_end(&mut bytes)?;
+    let img = image::load_from_memory(&bytes)?;
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn load_image_svg_local_file() {
+        // Create a minimal SVG file in a temp directory
+        let dir = std::env::temp_dir().join("mdr_test_svg");
+        std::fs::create_dir_all(&dir).unwrap();
+        let svg_path = dir.join("test.svg");
+        let mut f = std::fs::File::create(&svg_path).unwrap();
+        write!(f, r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100"><rect width="100" height="100" fill="red"/></svg>"#).unwrap();
+
+        let result = load_image("test.svg", &dir);
+        // This should succeed — SVG files must be rasterized before display
+        assert!(result.is_ok(), "load_image should handle SVG files but got: {:?}", result.err());
+        let img = result.unwrap();
+        assert!(img.width() > 0 && img.height() > 0);
+
+        // Cleanup
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_image_svg_data_uri() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="50" height="50"><circle cx="25" cy="25" r="20" fill="blue"/></svg>"#;
+        let b64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, svg.as_bytes());
+        let data_uri = format!("data:image/svg+xml;base64,{}", b64);
+
+        let result = load_image(&data_uri, std::path::Path::new("."));
+        assert!(result.is_ok(), "load_image should handle SVG data URIs but got: {:?}", result.err());
+    }
+
+    #[test]
+    fn images_are_decoded_once_until_the_file_changes() {
+        let dir = std::env::temp_dir().join("mdr_test_image_cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("box.svg");
+        let svg = |size: u32| format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}"><rect width="{0}" height="{0}"/></svg>"#, size);
+        std::fs::write(&path, svg(10)).unwrap();
+        let images = Images::new(mpsc::channel().0);
+        assert_eq!(images.get("box.svg", &dir).unwrap().unwrap().width(), 10);
+
+        // Rewritten in place with the same modification time: still the cached image
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        std::fs::write(&path, svg(20)).unwrap();
+        std::fs::File::options().append(true).open(&path).unwrap().set_modified(modified).unwrap();
+        assert_eq!(images.get("box.svg", &dir).unwrap().unwrap().width(), 10);
+
+        std::fs::File::options().append(true).open(&path).unwrap().set_modified(modified + Duration::from_secs(5)).unwrap();
+        assert_eq!(images.get("box.svg", &dir).unwrap().unwrap().width(), 20);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn remote_images_load_in_the_background() {
+        let (tx, rx) = mpsc::channel();
+        let images = Images::new(tx);
+        // Nothing listens on port 1, so the fetch fails quickly
+        let url = "http://127.0.0.1:1/missing.png";
+        assert!(images.get(url, Path::new(".")).is_none());
+        rx.recv_timeout(Duration::from_secs(10)).expect("fetch finished");
+        assert!(images.get(url, Path::new(".")).unwrap().is_err());
+    }
+}
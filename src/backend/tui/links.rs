@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+use std::ops::Range;
+use std::process::{Command, Stdio};
+
+/// Characters used for link hint labels, home row first.
+const HINT_CHARS: &str = "asdfghjkl";
+
+/// A link in the rendered document, located by terminal row.
+pub struct DocLink {
+    pub url: String,
+    /// (row, span range) for every row the link text occupies.
+    pub segments: Vec<(usize, Range<usize>)>,
+}
+
+impl DocLink {
+    /// Row of the first line of the link text.
+    pub fn row(&self) -> Option<usize> {
+        self.segments.first().map(|(row, _)| *row)
+    }
+}
+
+/// Generate `count` distinct hint labels. Single characters are used when they
+/// suffice, otherwise every label has two, so no label is a prefix of another.
+pub fn hint_labels(count: usize) -> Vec<String> {
+    let chars: Vec<char> = HINT_CHARS.chars().collect();
+    if count <= chars.len() {
+        return chars.iter().take(count).map(|c| c.to_string()).collect();
+    }
+    chars.iter()
+        .flat_map(|a| chars.iter().map(move |b| format!("{}{}", a, b)))
+        .take(count)
+        .collect()
+}
+
+/// Over SSH the system opener would run on the remote host, so links are
+/// copied to the local clipboard instead.
+pub fn prefers_clipboard() -> bool {
+    std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some()
+}
+
+/// Hand a URL or file path to the platform's default opener.
+pub fn open_external(target: &str) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = Command::new("xdg-open");
+
+    let mut child = command
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reap the opener in the background so it does not linger as a zombie
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Copy text to the clipboard of the terminal emulator with an OSC 52 escape
+/// sequence. Works over SSH and inside tmux when `set-clipboard` is enabled.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(osc52(text).as_bytes())?;
+    stdout.flush()
+}
+
+fn osc52(text: &str) -> String {
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    format!("\x1b]52;c;{}\x07", encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hint_labels_are_unique_and_prefix_free() {
+        assert_eq!(hint_labels(3), vec!["a", "s", "d"]);
+        let labels = hint_labels(20);
+        assert_eq!(labels.len(), 20);
+        assert!(labels.iter().all(|l| l.len() == 2));
+        let unique: std::collections::HashSet<_> = labels.iter().collect();
+        assert_eq!(unique.len(), 20);
+    }
+
+    #[test]
+    fn osc52_encodes_text() {
+        assert_eq!(osc52("hi"), "\x1b]52;c;aGk=\x07");
+    }
+}
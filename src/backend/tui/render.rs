@@ -0,0 +1,1037 @@
+use comrak::nodes::{ListDelimType, ListType, NodeValue, TableAlignment};
+use ratatui::prelude::*;
+
+use crate::core::ast::Node;
+use crate::core::markdown::alert_style;
+use crate::core::math::to_unicode;
+
+use super::highlight::highlight_code;
+
+/// Intermediate representation for parsed markdown lines.
+pub enum ParsedLine {
+    Text(Line<'static>),
+    /// The first row of a heading, so the TOC can find it after wrapping.
+    Heading(Line<'static>),
+    ImageRef { alt: String, url: String },
+    /// A mermaid diagram source extracted from a ```mermaid code block.
+    MermaidRef { source: String },
+}
+
+/// A link in the rendered output. A link that wraps over several rows has one
+/// segment per row.
+pub struct RenderedLink {
+    pub url: String,
+    pub segments: Vec<LinkSegment>,
+}
+
+/// The spans of one rendered item (a `Text` or `Heading` line) that belong to a link.
+pub struct LinkSegment {
+    /// Index into `RenderedMarkdown::items`.
+    pub item: usize,
+    /// Range of span indices within that line.
+    pub spans: std::ops::Range<usize>,
+}
+
+/// Output of `markdown_to_lines_with_images`.
+pub struct RenderedMarkdown {
+    pub items: Vec<ParsedLine>,
+    /// Links in document order.
+    pub links: Vec<RenderedLink>,
+    /// Top-level blocks in document order.
+    pub blocks: Vec<RenderedBlock>,
+}
+
+/// The items a top-level Markdown block rendered to.
+pub struct RenderedBlock {
+    /// 0-based source lines of the block, end exclusive.
+    pub lines: std::ops::Range<usize>,
+    /// Range into `RenderedMarkdown::items`, excluding leading blank separators.
+    pub items: std::ops::Range<usize>,
+}
+
+/// A span tagged with the index of the link it belongs to, if any.
+type InlineSpan = (Span<'static>, Option<usize>);
+
+/// Convert a parsed document to a mix of styled text lines and image references.
+/// Walks the document's own AST (see `Document::ast`), the one HTML rendering
+/// and the TOC use, so the TUI agrees with the other backends on the document
+/// structure.
+/// `width` is the number of terminal columns available for content; text is
+/// soft-wrapped to it, so every returned text line occupies exactly one row.
+pub fn markdown_to_lines_with_images(root: &Node, width: usize) -> RenderedMarkdown {
+    let mut renderer = LineRenderer { width, ..Default::default() };
+    let mut blocks = Vec::new();
+    for node in root.children() {
+        let start = renderer.items.len();
+        renderer.render_block(node);
+        let end = renderer.items.len();
+        let first = (start..end)
+            .find(|&i| !matches!(&renderer.items[i], ParsedLine::Text(line) if line.spans.iter().all(|s| s.content.is_empty())))
+            .unwrap_or(end);
+        blocks.push(RenderedBlock { lines: node.lines(), items: first..end });
+    }
+    RenderedMarkdown { items: renderer.items, links: renderer.links, blocks }
+}
+
+/// Text placed in front of every line emitted inside a container block
+/// (list item marker, blockquote bar, footnote label).
+struct Prefix {
+    /// Spans for the first line of the container.
+    first: Vec<Span<'static>>,
+    /// Spans for every following line (usually blank padding of the same width).
+    rest: Vec<Span<'static>>,
+    used: bool,
+}
+
+#[derive(Default)]
+struct LineRenderer {
+    width: usize,
+    items: Vec<ParsedLine>,
+    links: Vec<RenderedLink>,
+    prefixes: Vec<Prefix>,
+    /// Base text style of the enclosing blocks (e.g. dimmed inside blockquotes).
+    base_styles: Vec<Style>,
+    /// Tightness of the enclosing lists; blocks in tight list items are not
+    /// separated by blank lines.
+    tight: Vec<bool>,
+    pending_blank: bool,
+}
+
+impl LineRenderer {
+    fn render_children(&mut self, node: &Node) {
+        for child in node.children() {
+            self.render_block(child);
+        }
+    }
+
+    fn render_block(&mut self, node: &Node) {
+        let value = node.value().clone();
+        match value {
+            NodeValue::Heading(heading) => {
+                self.pending_blank = true;
+                let style = match heading.level {
+                    1 => Style::default().fg(Color::Cyan).bold().underlined(),
+                    2 => Style::default().fg(Color::Blue).bold(),
+                    3 => Style::default().fg(Color::Yellow).bold(),
+                    _ => Style::default().fg(Color::Magenta).bold(),
+                };
+                let available = self.available_width();
+                let rows: Vec<Vec<InlineSpan>> = self.inline_lines(node, style)
+                    .into_iter()
+                    .flat_map(|spans| wrap_spans(&spans, available))
+                    .collect();
+                let width = rows.iter().map(|row| spans_width(row)).max().unwrap_or(0);
+                for (i, row) in rows.into_iter().enumerate() {
+                    if i == 0 {
+                        self.flush_blank();
+                        let line = self.prefixed(row);
+                        self.items.push(ParsedLine::Heading(line));
+                    } else {
+                        self.push_tagged(row);
+                    }
+                }
+                match heading.level {
+                    1 => self.push_line(vec![Span::styled("═".repeat(width.min(60)), Style::default().fg(Color::Cyan))]),
+                    2 => self.push_line(vec![Span::styled("─".repeat(width.min(50)), Style::default().fg(Color::Blue))]),
+                    _ => {}
+                }
+                self.pending_blank = true;
+            }
+            NodeValue::Paragraph => {
+                if let Some((alt, url)) = sole_image(node) {
+                    self.push_item(ParsedLine::ImageRef { alt, url });
+                } else {
+                    let base = self.base_style();
+                    for line in self.inline_lines(node, base) {
+                        self.push_wrapped(line);
+                    }
+                }
+                self.end_block();
+            }
+            NodeValue::CodeBlock(code) => {
+                let lang = code.info.split_whitespace().next().unwrap_or("").to_string();
+                let source = code.literal.trim_end_matches('\n');
+                if lang == "mermaid" {
+                    self.push_item(ParsedLine::MermaidRef { source: source.to_string() });
+                } else if lang == "math" {
+                    let math = Span::styled(format!("    {}", to_unicode(source)), Style::default().fg(Color::Yellow));
+                    self.push_wrapped(vec![(math, None)]);
+                } else {
+                    self.push_code_block(&lang, source);
+                }
+                self.end_block();
+            }
+            NodeValue::HtmlBlock(html) => {
+                self.push_html_block(&html.literal);
+                self.end_block();
+            }
+            NodeValue::ThematicBreak => {
+                let width = self.available_width().min(60);
+                self.push_line(vec![Span::styled("─".repeat(width), Style::default().fg(Color::DarkGray))]);
+                self.end_block();
+            }
+            NodeValue::BlockQuote => {
+                let bar = vec![Span::styled("▎ ", Style::default().fg(Color::DarkGray))];
+                self.with_container(bar.clone(), bar, Some(Style::default().fg(Color::Gray).italic()), |r| {
+                    r.tight.push(false);
+                    r.render_children(node);
+                    r.tight.pop();
+                });
+                self.end_block();
+            }
+            NodeValue::Alert(alert) => {
+                let style = alert_style(alert.alert_type);
+                let [r, g, b] = style.dark;
+                let color = Color::Rgb(r, g, b);
+                let title = alert.title.clone().unwrap_or_else(|| style.label.to_string());
+                let bar = vec![Span::styled("▎ ", Style::default().fg(color))];
+                self.with_container(bar.clone(), bar, None, |r| {
+                    r.push_line(vec![Span::styled(format!("{} {}", style.icon, title), Style::default().fg(color).bold())]);
+                    r.tight.push(false);
+                    r.render_children(node);
+                    r.tight.pop();
+                });
+                self.end_block();
+            }
+            NodeValue::List(list) => {
+                self.tight.push(list.tight);
+                for (index, item) in node.children().iter().enumerate() {
+                    self.render_list_item(item, &list, index);
+                }
+                self.tight.pop();
+                self.end_block();
+            }
+            NodeValue::Table(_) => {
+                self.push_table(node);
+                self.end_block();
+            }
+            NodeValue::FootnoteDefinition(def) => {
+                let label = format!("[^{}]: ", def.name);
+                let pad = " ".repeat(Line::from(label.as_str()).width());
+                let first = vec![Span::styled(label, Style::default().fg(Color::DarkGray))];
+                self.with_container(first, vec![Span::raw(pad)], None, |r| {
+                    r.tight.push(true);
+                    r.render_children(node);
+                    r.tight.pop();
+                });
+                self.end_block();
+            }
+            NodeValue::Item(_) | NodeValue::TaskItem(_) => {
+                // Items are rendered by their parent list; reaching one here means
+                // a malformed tree, so just render the contents.
+                self.render_children(node);
+            }
+            _ => {
+                if !node.children().is_empty() {
+                    self.render_children(node);
+                }
+            }
+        }
+    }
+
+    fn render_list_item(&mut self, item: &Node, list: &comrak::nodes::NodeList, index: usize) {
+        let task = match item.value() {
+            NodeValue::TaskItem(task) => Some(task.symbol.is_some()),
+            _ => None,
+        };
+        let marker = match (task, list.list_type) {
+            (Some(true), _) => Span::styled("☑ ", Style::default().fg(Color::Green)),
+            (Some(false), _) => Span::styled("☐ ", Style::default().fg(Color::Yellow)),
+            (None, ListType::Bullet) => Span::styled("• ", Style::default().fg(Color::Cyan)),
+            (None, ListType::Ordered) => {
+                let delim = if list.delimiter == ListDelimType::Paren { ')' } else { '.' };
+                Span::styled(format!("{}{} ", list.start + index, delim), Style::default().fg(Color::Cyan))
+            }
+        };
+        let pad = " ".repeat(marker.width());
+        let base = if task == Some(true) { Some(Style::default().fg(Color::DarkGray)) } else { None };
+        if !self.in_tight_item() && index > 0 {
+            self.pending_blank = true;
+        }
+        self.with_container(vec![marker], vec![Span::raw(pad)], base, |r| r.render_children(item));
+    }
+
+    fn with_container(
+        &mut self,
+        first: Vec<Span<'static>>,
+        rest: Vec<Span<'static>>,
+        base: Option<Style>,
+        body: impl FnOnce(&mut Self),
+    ) {
+        self.prefixes.push(Prefix { first, rest, used: false });
+        if let Some(style) = base {
+            let patched = self.base_style().patch(style);
+            self.base_styles.push(patched);
+        }
+        body(self);
+        if base.is_some() {
+            self.base_styles.pop();
+        }
+        let prefix = self.prefixes.pop();
+        // A container that produced no lines (e.g. an empty list item) still shows its marker
+        if let Some(prefix) = prefix.filter(|p| !p.used) {
+            self.prefixes.push(prefix);
+            self.push_line(Vec::new());
+            self.prefixes.pop();
+        }
+    }
+
+    fn base_style(&self) -> Style {
+        self.base_styles.last().copied().unwrap_or_default()
+    }
+
+    /// Columns left for content once the container prefixes are drawn.
+    fn available_width(&self) -> usize {
+        let prefix: usize = self.prefixes.iter()
+            .map(|p| p.rest.iter().map(|s| s.width()).sum::<usize>())
+            .sum();
+        self.width.saturating_sub(prefix).max(1)
+    }
+
+    fn in_tight_item(&self) -> bool {
+        self.tight.last().copied().unwrap_or(false)
+    }
+
+    /// Mark the end of a block: the next block is separated by a blank line
+    /// unless we are inside a tight list item.
+    fn end_block(&mut self) {
+        if !self.in_tight_item() {
+            self.pending_blank = true;
+        }
+    }
+
+    fn flush_blank(&mut self) {
+        if self.pending_blank && !self.items.is_empty() {
+            let mut spans: Vec<Span<'static>> = self.prefixes.iter()
+                .filter(|p| p.used)
+                .flat_map(|p| p.rest.iter().cloned())
+                .collect();
+            while spans.last().is_some_and(|s| s.content.trim().is_empty()) {
+                spans.pop();
+            }
+            if let Some(last) = spans.last_mut() {
+                last.content = last.content.trim_end().to_string().into();
+            }
+            self.items.push(ParsedLine::Text(Line::from(spans)));
+        }
+        self.pending_blank = false;
+    }
+
+    fn push_line(&mut self, spans: Vec<Span<'static>>) {
+        self.push_tagged(spans.into_iter().map(|s| (s, None)).collect());
+    }
+
+    fn push_tagged(&mut self, spans: Vec<InlineSpan>) {
+        self.flush_blank();
+        let line = self.prefixed(spans);
+        self.items.push(ParsedLine::Text(line));
+    }
+
+    /// Soft-wrap a line of text to the width left inside the current
+    /// containers, so continuation rows line up under the list marker or
+    /// blockquote bar.
+    fn push_wrapped(&mut self, spans: Vec<InlineSpan>) {
+        let width = self.available_width();
+        for row in wrap_spans(&spans, width) {
+            self.push_tagged(row);
+        }
+    }
+
+    /// Prepend the container prefixes to a line that is about to be pushed,
+    /// using each container's first-line prefix the first time it is drawn,
+    /// and record where its link spans end up.
+    fn prefixed(&mut self, spans: Vec<InlineSpan>) -> Line<'static> {
+        let mut line: Vec<Span<'static>> = Vec::new();
+        for prefix in &mut self.prefixes {
+            if prefix.used {
+                line.extend(prefix.rest.iter().cloned());
+            } else {
+                line.extend(prefix.first.iter().cloned());
+                prefix.used = true;
+            }
+        }
+        let item = self.items.len();
+        for (span, link) in spans {
+            if let Some(link) = link.and_then(|id| self.links.get_mut(id)) {
+                let index = line.len();
+                match link.segments.last_mut() {
+                    Some(segment) if segment.item == item && segment.spans.end == index => segment.spans.end += 1,
+                    _ => link.segments.push(LinkSegment { item, spans: index..index + 1 }),
+                }
+            }
+            line.push(span);
+        }
+        Line::from(line)
+    }
+
+    fn push_item(&mut self, item: ParsedLine) {
+        self.flush_blank();
+        for prefix in &mut self.prefixes {
+            prefix.used = true;
+        }
+        self.items.push(item);
+    }
+
+    fn push_code_block(&mut self, lang: &str, source: &str) {
+        let header = if lang.is_empty() {
+            "┌─ code ──────────────────────────────────┐".to_string()
+        } else {
+            format!("┌─ {} {}", lang, "─".repeat(38usize.saturating_sub(lang.len())))
+        };
+        self.push_line(vec![Span::styled(header, Style::default().fg(Color::DarkGray))]);
+        let lines: Vec<Line<'static>> = match highlight_code(lang, source) {
+            Some(lines) => lines.into_iter().map(Line::from).collect(),
+            None => source.lines()
+                .map(|line| Line::from(Span::styled(line.to_string(), Style::default().fg(Color::Green))))
+                .collect(),
+        };
+        // Long code lines wrap inside the box rather than running off screen
+        let width = self.available_width().saturating_sub(2);
+        for line in &lines {
+            for row in wrap_line(line, width) {
+                let mut spans = vec![Span::styled("│ ", Style::default().fg(Color::DarkGray))];
+                spans.extend(row.spans);
+                self.push_line(spans);
+            }
+        }
+        self.push_line(vec![Span::styled(
+            "└─────────────────────────────────────────┘",
+            Style::default().fg(Color::DarkGray),
+        )]);
+    }
+
+    /// Render a raw HTML block: `<img>` tags become image references and the
+    /// remaining visible text is shown with tags stripped.
+    fn push_html_block(&mut self, literal: &str) {
+        use std::sync::OnceLock;
+        static RE_COMMENT: OnceLock<regex::Regex> = OnceLock::new();
+        static RE_TAG: OnceLock<regex::Regex> = OnceLock::new();
+        let re_comment = RE_COMMENT.get_or_init(|| regex::Regex::new(r"(?s)<!--.*?-->").unwrap());
+        let re_tag = RE_TAG.get_or_init(|| regex::Regex::new(r"<[^>]*>").unwrap());
+
+        let without_comments = re_comment.replace_all(literal, "");
+        for tag in re_tag.find_iter(&without_comments) {
+            if let Some((alt, url)) = html_img(tag.as_str()) {
+                self.push_item(ParsedLine::ImageRef { alt, url });
+            }
+        }
+        let base = self.base_style();
+        for line in re_tag.replace_all(&without_comments, "").lines() {
+            let text = decode_entities(line.trim());
+            if !text.is_empty() {
+                self.push_wrapped(vec![(Span::styled(text, base), None)]);
+            }
+        }
+    }
+
+    /// Lay out a GFM table with box-drawing borders. Column widths follow the
+    /// content, shrinking the widest columns (and wrapping their cells) when the
+    /// table does not fit in the available width.
+    fn push_table(&mut self, table: &Node) {
+        let alignments = match table.value() {
+            NodeValue::Table(t) => t.alignments.clone(),
+            _ => return,
+        };
+        let header_style = Style::default().fg(Color::Cyan).bold();
+        let body_style = self.base_style();
+
+        let mut rows: Vec<(bool, Vec<Vec<InlineSpan>>)> = Vec::new();
+        for row in table.children() {
+            let header = matches!(row.value(), NodeValue::TableRow(true));
+            let style = if header { header_style } else { body_style };
+            let cells = row.children().iter()
+                .map(|cell| {
+                    // Cells are single-line in GFM; join any hard breaks with a space
+                    let lines = self.inline_lines(cell, style);
+                    let mut spans = Vec::new();
+                    for (i, line) in lines.into_iter().enumerate() {
+                        if i > 0 {
+                            spans.push((Span::styled(" ", style), None));
+                        }
+                        spans.extend(line);
+                    }
+                    spans
+                })
+                .collect();
+            rows.push((header, cells));
+        }
+
+        let columns = alignments.len().max(rows.iter().map(|(_, c)| c.len()).max().unwrap_or(0));
+        if columns == 0 {
+            return;
+        }
+        let mut natural = vec![1usize; columns];
+        for (_, cells) in &rows {
+            for (i, cell) in cells.iter().enumerate() {
+                natural[i] = natural[i].max(spans_width(cell));
+            }
+        }
+        // Each column costs its content plus "│ " and " ", and one closing "│"
+        let chrome = columns * 3 + 1;
+        let widths = fit_column_widths(&natural, self.available_width().saturating_sub(chrome));
+
+        let border = Style::default().fg(Color::DarkGray);
+        let rule = |left: &str, mid: &str, right: &str| {
+            let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+            vec![Span::styled(format!("{}{}{}", left, segments.join(mid), right), border)]
+        };
+
+        self.push_line(rule("┌", "┬", "┐"));
+        let last = rows.len().saturating_sub(1);
+        for (index, (header, cells)) in rows.into_iter().enumerate() {
+            let wrapped: Vec<Vec<Vec<InlineSpan>>> = (0..columns)
+                .map(|i| match cells.get(i) {
+                    Some(cell) => wrap_spans(cell, widths[i]),
+                    None => vec![Vec::new()],
+                })
+                .collect();
+            let height = wrapped.iter().map(|w| w.len()).max().unwrap_or(1);
+            for row_line in 0..height {
+                let mut spans = vec![(Span::styled("│", border), None)];
+                for (i, cell_lines) in wrapped.iter().enumerate() {
+                    let line = cell_lines.get(row_line).cloned().unwrap_or_default();
+                    let alignment = alignments.get(i).copied().unwrap_or(TableAlignment::None);
+                    let fill = widths[i].saturating_sub(spans_width(&line));
+                    let (left, right) = match alignment {
+                        TableAlignment::Right => (fill, 0),
+                        TableAlignment::Center => (fill / 2, fill - fill / 2),
+                        TableAlignment::Left | TableAlignment::None => (0, fill),
+                    };
+                    spans.push((Span::raw(" ".repeat(left + 1)), None));
+                    spans.extend(line);
+                    spans.push((Span::raw(" ".repeat(right + 1)), None));
+                    spans.push((Span::styled("│", border), None));
+                }
+                self.push_tagged(spans);
+            }
+            if header {
+                self.push_line(rule("├", "┼", "┤"));
+            } else if index == last {
+                self.push_line(rule("└", "┴", "┘"));
+            }
+        }
+    }
+
+    /// Render the inline children of a block into one or more lines of spans.
+    /// Hard line breaks start a new line; soft breaks become spaces.
+    fn inline_lines(&mut self, node: &Node, base: Style) -> Vec<Vec<InlineSpan>> {
+        let mut lines = vec![Vec::new()];
+        for child in node.children() {
+            render_inline(child, base, None, &mut lines, &mut self.links);
+        }
+        if lines.len() > 1 && lines.last().is_some_and(|l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+}
+
+/// Render an inline node into `lines`. `link` is the index (into `links`) of
+/// the link enclosing this node, so its spans can be located after wrapping.
+fn render_inline(
+    node: &Node,
+    style: Style,
+    link: Option<usize>,
+    lines: &mut Vec<Vec<InlineSpan>>,
+    links: &mut Vec<RenderedLink>,
+) {
+    let push = |lines: &mut Vec<Vec<InlineSpan>>, text: String, style: Style| {
+        if let Some(line) = lines.last_mut() {
+            line.push((Span::styled(text, style), link));
+        }
+    };
+    let value = node.value().clone();
+    match value {
+        NodeValue::Text(text) => push(lines, text.to_string(), style),
+        NodeValue::Code(code) => push(lines, code.literal, style.fg(Color::Green).bg(Color::Rgb(30, 30, 30))),
+        NodeValue::Math(math) if math.display_math => {
+            // Display math gets a line of its own
+            if lines.last().is_some_and(|line| !line.is_empty()) {
+                lines.push(Vec::new());
+            }
+            push(lines, format!("    {}", to_unicode(&math.literal)), style.fg(Color::Yellow));
+            lines.push(Vec::new());
+        }
+        NodeValue::Math(math) => push(lines, to_unicode(&math.literal), style.fg(Color::Yellow)),
+        NodeValue::SoftBreak => push(lines, " ".to_string(), style),
+        NodeValue::LineBreak => lines.push(Vec::new()),
+        NodeValue::Emph => render_inline_children(node, style.italic(), link, lines, links),
+        NodeValue::Strong => render_inline_children(node, style.bold(), link, lines, links),
+        NodeValue::Strikethrough => render_inline_children(
+            node,
+            style.fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT),
+            link,
+            lines,
+            links,
+        ),
+        NodeValue::Link(target) => {
+            let id = links.len();
+            links.push(RenderedLink { url: target.url.clone(), segments: Vec::new() });
+            render_inline_children(node, style.fg(Color::Blue).underlined(), Some(id), lines, links);
+        }
+        NodeValue::Image(_) => {
+            let alt = collect_text(node);
+            let label = if alt.is_empty() { "image".to_string() } else { alt };
+            push(lines, format!("[Image: {}]", label), Style::default().fg(Color::Magenta).italic());
+        }
+        NodeValue::FootnoteReference(footnote) => {
+            push(lines, format!("[^{}]", footnote.name), style.fg(Color::Cyan));
+        }
+        NodeValue::HtmlInline(html) => {
+            let tag = html.trim().to_lowercase();
+            if tag.starts_with("<br") {
+                lines.push(Vec::new());
+            } else if let Some((alt, _)) = html_img(&html) {
+                let label = if alt.is_empty() { "image".to_string() } else { alt };
+                push(lines, format!("[Image: {}]", label), Style::default().fg(Color::Magenta).italic());
+            }
+        }
+        _ => render_inline_children(node, style, link, lines, links),
+    }
+}
+
+fn render_inline_children(
+    node: &Node,
+    style: Style,
+    link: Option<usize>,
+    lines: &mut Vec<Vec<InlineSpan>>,
+    links: &mut Vec<RenderedLink>,
+) {
+    for child in node.children() {
+        render_inline(child, style, link, lines, links);
+    }
+}
+
+/// If a paragraph consists of a single image (ignoring surrounding whitespace),
+/// return its alt text and URL so it can be displayed as a block image.
+fn sole_image(paragraph: &Node) -> Option<(String, String)> {
+    let mut image = None;
+    for child in paragraph.children() {
+        match child.value() {
+            NodeValue::Image(link) if image.is_none() => image = Some((collect_text(child), link.url.clone())),
+            NodeValue::Text(t) if t.trim().is_empty() => {}
+            NodeValue::SoftBreak | NodeValue::LineBreak => {}
+            _ => return None,
+        }
+    }
+    image
+}
+
+/// Collect the plain text of a node's descendants.
+fn collect_text(node: &Node) -> String {
+    let mut text = String::new();
+    for child in node.descendants().skip(1) {
+        match child.value() {
+            NodeValue::Text(t) => text.push_str(t),
+            NodeValue::Code(c) => text.push_str(&c.literal),
+            NodeValue::SoftBreak | NodeValue::LineBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Extract (alt, src) from an HTML `<img>` tag.
+fn html_img(tag: &str) -> Option<(String, String)> {
+    use std::sync::OnceLock;
+    static RE_IMG: OnceLock<regex::Regex> = OnceLock::new();
+    static RE_ALT: OnceLock<regex::Regex> = OnceLock::new();
+    let re_img = RE_IMG.get_or_init(|| regex::Regex::new(r#"(?i)^<img\s[^>]*?src\s*=\s*["']([^"']+)["']"#).unwrap());
+    let re_alt = RE_ALT.get_or_init(|| regex::Regex::new(r#"(?i)\balt\s*=\s*["']([^"']*)["']"#).unwrap());
+    let src = re_img.captures(tag.trim())?.get(1)?.as_str().to_string();
+    let alt = re_alt.captures(tag).and_then(|c| c.get(1)).map(|m| decode_entities(m.as_str())).unwrap_or_default();
+    Some((alt, src))
+}
+
+fn decode_entities(s: &str) -> String {
+    s.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Shrink column widths so they sum to at most `budget`, narrowing the widest
+/// columns first. Columns never go below 3 characters, so very narrow
+/// terminals get an overflowing table rather than unreadable one-letter cells.
+fn fit_column_widths(natural: &[usize], budget: usize) -> Vec<usize> {
+    const MIN_WIDTH: usize = 3;
+    if natural.iter().sum::<usize>() <= budget {
+        return natural.to_vec();
+    }
+    // Find the largest cap such that sum(min(width, cap)) fits the budget
+    let mut cap = natural.iter().copied().max().unwrap_or(MIN_WIDTH);
+    while cap > MIN_WIDTH && natural.iter().map(|w| (*w).min(cap)).sum::<usize>() > budget {
+        cap -= 1;
+    }
+    natural.iter().map(|w| (*w).min(cap).max(1)).collect()
+}
+
+/// Word-wrap a styled line to at most `width` display columns.
+/// Breaks at whitespace, hard-splits words longer than a row, and indents
+/// continuation rows by the line's leading whitespace.
+pub fn wrap_line(line: &Line<'static>, width: usize) -> Vec<Line<'static>> {
+    if line.width() <= width.max(1) {
+        return vec![line.clone()];
+    }
+    let spans: Vec<InlineSpan> = line.spans.iter().map(|s| (s.clone(), None)).collect();
+    wrap_spans(&spans, width)
+        .into_iter()
+        .map(|row| Line::from(row.into_iter().map(|(span, _)| span).collect::<Vec<_>>()))
+        .collect()
+}
+
+/// `wrap_line` for link-tagged spans: every piece of a split span keeps its tag.
+fn wrap_spans(spans: &[InlineSpan], width: usize) -> Vec<Vec<InlineSpan>> {
+    use unicode_width::UnicodeWidthStr;
+
+    let width = width.max(1);
+    if spans_width(spans) <= width {
+        return vec![spans.to_vec()];
+    }
+
+    let text: String = spans.iter().map(|(s, _)| s.content.as_ref()).collect();
+    let indent = text.len() - text.trim_start_matches(' ').len();
+    let indent = if indent < width / 2 { indent } else { 0 };
+
+    let mut rows: Vec<Vec<InlineSpan>> = Vec::new();
+    let mut row: Vec<InlineSpan> = Vec::new();
+    let mut row_width = 0;
+    let mut row_has_text = false;
+
+    for (span, link) in spans {
+        let piece = |text: String| (Span::styled(text, span.style), *link);
+        for token in split_keep_whitespace(&span.content) {
+            let is_space = token.starts_with(char::is_whitespace);
+            let token_width = token.width();
+            if is_space {
+                if row_has_text && row_width + token_width <= width {
+                    row.push(piece(token.to_string()));
+                    row_width += token_width;
+                } else if !row_has_text && rows.is_empty() {
+                    // Leading indentation of the first row is kept verbatim
+                    row.push(piece(token.to_string()));
+                    row_width += token_width;
+                }
+                continue;
+            }
+            if row_width + token_width > width && row_has_text {
+                rows.push(finish_row(std::mem::take(&mut row)));
+                row.push((Span::raw(" ".repeat(indent)), None));
+                row_width = indent;
+                row_has_text = false;
+            }
+            if row_width + token_width <= width {
+                row.push(piece(token.to_string()));
+                row_width += token_width;
+                row_has_text = true;
+                continue;
+            }
+            // Word longer than a full row: split it by character
+            let mut chunk = String::new();
+            for ch in token.chars() {
+                let ch_width = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+                if row_width + ch_width > width && (row_has_text || !chunk.is_empty()) {
+                    row.push(piece(std::mem::take(&mut chunk)));
+                    rows.push(finish_row(std::mem::take(&mut row)));
+                    row.push((Span::raw(" ".repeat(indent)), None));
+                    row_width = indent;
+                    row_has_text = false;
+                }
+                chunk.push(ch);
+                row_width += ch_width;
+            }
+            if !chunk.is_empty() {
+                row.push(piece(chunk));
+                row_has_text = true;
+            }
+        }
+    }
+    if row_has_text || rows.is_empty() {
+        rows.push(finish_row(row));
+    }
+    rows
+}
+
+fn spans_width(spans: &[InlineSpan]) -> usize {
+    spans.iter().map(|(s, _)| s.width()).sum()
+}
+
+/// Trim trailing whitespace spans off a wrapped row.
+fn finish_row(mut spans: Vec<InlineSpan>) -> Vec<InlineSpan> {
+    while spans.last().is_some_and(|(s, _)| s.content.trim().is_empty()) && spans.len() > 1 {
+        spans.pop();
+    }
+    spans
+}
+
+/// Split text into alternating runs of whitespace and non-whitespace.
+fn split_keep_whitespace(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev_space: Option<bool> = None;
+    for (i, ch) in text.char_indices() {
+        let space = ch.is_whitespace();
+        if prev_space.is_some_and(|p| p != space) {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        prev_space = Some(space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(items: &[ParsedLine]) -> Vec<String> {
+        items.iter().filter_map(|item| match item {
+            ParsedLine::Text(line) | ParsedLine::Heading(line) => Some(line.spans.iter().map(|s| s.content.as_ref()).collect()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn mermaid_block_produces_mermaid_ref() {
+        let md = "# Title\n\n```mermaid\ngraph LR\n  A-->B\n```\n\nSome text after.\n";
+        let items = markdown_to_lines_with_images(&Node::parse(md), 80).items;
+
+        let has_mermaid_ref = items.iter().any(|item| matches!(item, ParsedLine::MermaidRef { .. }));
+        assert!(has_mermaid_ref, "Mermaid code block should produce a MermaidRef variant");
+
+        // Verify the source is captured correctly
+        let mermaid_source = items.iter().find_map(|item| {
+            if let ParsedLine::MermaidRef { source } = item {
+                Some(source.clone())
+            } else {
+                None
+            }
+        }).expect("Should have a MermaidRef");
+        assert!(mermaid_source.contains("graph LR"), "MermaidRef should contain the mermaid source, got: {}", mermaid_source);
+        assert!(mermaid_source.contains("A-->B"), "MermaidRef should contain the diagram content");
+    }
+
+    #[test]
+    fn mermaid_block_not_rendered_as_code_text() {
+        let md = "```mermaid\ngraph LR\n  A-->B\n```\n";
+        let items = markdown_to_lines_with_images(&Node::parse(md), 80).items;
+
+        // Should NOT have green code lines for mermaid content
+        let has_green_code = texts(&items).iter().any(|text| text.contains("│ graph LR") || text.contains("│   A-->B"));
+        assert!(!has_green_code, "Mermaid content should NOT appear as regular code text");
+    }
+
+    #[test]
+    fn non_mermaid_code_block_unchanged() {
+        let md = "```rust\nfn main() {}\n```\n";
+        let items = markdown_to_lines_with_images(&Node::parse(md), 80).items;
+
+        let has_mermaid_ref = items.iter().any(|item| matches!(item, ParsedLine::MermaidRef { .. }));
+        assert!(!has_mermaid_ref, "Non-mermaid code blocks should NOT produce MermaidRef");
+
+        // Should have regular code text
+        let has_code_text = texts(&items).iter().any(|text| text.contains("│ fn main()"));
+        assert!(has_code_text, "Non-mermaid code should appear as regular code text");
+    }
+
+    #[test]
+    fn alerts_get_a_titled_bar() {
+        let md = "> [!WARNING]\n> Mind the gap.\n\n> [!TIP] Shortcut\n> Press `q`.\n";
+        let items = markdown_to_lines_with_images(&Node::parse(md), 80).items;
+        let texts = texts(&items);
+        assert_eq!(texts[..2], ["▎ ⚠ Warning", "▎ Mind the gap."]);
+        assert!(texts.contains(&"▎ 💡 Shortcut".to_string()), "got {:?}", texts);
+        let ParsedLine::Text(line) = &items[0] else { panic!("expected text") };
+        assert_eq!(line.spans[0].style.fg, Some(Color::Rgb(0xd2, 0x99, 0x22)));
+    }
+
+    #[test]
+    fn math_is_shown_as_unicode() {
+        let md = "Square $x^2$ here:\n$$\\frac{a+b}{c}$$\n\n```math\n\\alpha \\leq \\beta\n```\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 80).items);
+        assert_eq!(texts[0], "Square x² here: ");
+        assert_eq!(texts[1], "    (a+b)/c");
+        assert!(texts.iter().any(|t| t == "    α ≤ β"), "got {:?}", texts);
+    }
+
+    #[test]
+    fn tilde_fences_and_indented_code() {
+        let md = "~~~python\nprint('hi')\n~~~\n\n    indented code\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 80).items);
+        assert!(texts.iter().any(|t| t.starts_with("┌─ python")));
+        assert!(texts.iter().any(|t| t == "│ print('hi')"));
+        assert!(texts.iter().any(|t| t == "│ indented code"));
+    }
+
+    #[test]
+    fn setext_heading_is_styled_as_heading() {
+        let items = markdown_to_lines_with_images(&Node::parse("Title\n=====\n\nBody\n"), 80).items;
+        let texts = texts(&items);
+        assert_eq!(texts[0], "Title");
+        assert!(texts[1].starts_with('═'), "H1 should be underlined, got: {:?}", texts);
+    }
+
+    #[test]
+    fn nested_lists_are_indented() {
+        let md = "- one\n  - nested\n    1. deep\n- two\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 80).items);
+        assert_eq!(texts, vec!["• one", "  • nested", "    1. deep", "• two"]);
+    }
+
+    #[test]
+    fn task_list_items_use_checkboxes() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("- [x] done\n- [ ] todo\n"), 80).items);
+        assert_eq!(texts, vec!["☑ done", "☐ todo"]);
+    }
+
+    #[test]
+    fn blockquote_containing_list() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("> quote\n>\n> - item\n"), 80).items);
+        assert_eq!(texts, vec!["▎ quote", "▎", "▎ • item"]);
+    }
+
+    #[test]
+    fn paragraph_wraps_to_width() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("alpha beta gamma delta epsilon\n"), 12).items);
+        assert_eq!(texts, vec!["alpha beta", "gamma delta", "epsilon"]);
+    }
+
+    #[test]
+    fn wrapped_list_item_keeps_indent() {
+        let md = "- one two three four\n\n> five six seven eight\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 12).items);
+        assert_eq!(texts, vec!["• one two", "  three four", "", "▎ five six", "▎ seven", "▎ eight"]);
+    }
+
+    #[test]
+    fn wide_characters_wrap_by_display_width() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("漢字漢字漢字\n"), 6).items);
+        assert_eq!(texts, vec!["漢字漢", "字漢字"]);
+    }
+
+    #[test]
+    fn wrapped_heading_marks_only_first_row() {
+        let items = markdown_to_lines_with_images(&Node::parse("## A long heading title\n"), 10).items;
+        let headings: Vec<_> = items.iter().filter(|i| matches!(i, ParsedLine::Heading(_))).collect();
+        assert_eq!(headings.len(), 1);
+        assert!(matches!(items[0], ParsedLine::Heading(_)));
+        assert!(texts(&items).iter().all(|t| Line::from(t.as_str()).width() <= 10));
+    }
+
+    #[test]
+    fn long_code_lines_wrap_inside_box() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("```\naaaa bbbb cccc\n```\n"), 10).items);
+        assert!(texts.contains(&"│ aaaa".to_string()), "got: {:?}", texts);
+        assert!(texts.contains(&"│ cccc".to_string()), "got: {:?}", texts);
+    }
+
+    #[test]
+    fn links_are_located_after_wrapping() {
+        let md = "See [the docs](guide.md#setup) and <https://example.com>.\n";
+        let rendered = markdown_to_lines_with_images(&Node::parse(md), 10);
+        let urls: Vec<&str> = rendered.links.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(urls, vec!["guide.md#setup", "https://example.com"]);
+
+        // "See the" / "docs and" / "https://ex" ...: the first link spans two rows
+        let docs = &rendered.links[0];
+        assert_eq!(docs.segments.len(), 2);
+        let segment_text = |segment: &LinkSegment| -> String {
+            let ParsedLine::Text(line) = &rendered.items[segment.item] else { panic!("expected text line") };
+            line.spans[segment.spans.clone()].iter().map(|s| s.content.as_ref()).collect()
+        };
+        assert_eq!(segment_text(&docs.segments[0]), "the");
+        assert_eq!(segment_text(&docs.segments[1]), "docs");
+    }
+
+    #[test]
+    fn links_inside_lists_account_for_prefix_spans() {
+        let rendered = markdown_to_lines_with_images(&Node::parse("- [item](#anchor)\n"), 80);
+        let segment = &rendered.links[0].segments[0];
+        let ParsedLine::Text(line) = &rendered.items[segment.item] else { panic!("expected text line") };
+        assert_eq!(line.spans[segment.spans.clone()][0].content, "item");
+    }
+
+    #[test]
+    fn link_with_emphasis_keeps_both_styles() {
+        let items = markdown_to_lines_with_images(&Node::parse("[**bold link**](https://example.com)"), 80).items;
+        let ParsedLine::Text(line) = &items[0] else { panic!("expected text line") };
+        let span = line.spans.iter().find(|s| s.content == "bold link").expect("link text span");
+        assert!(span.style.add_modifier.contains(Modifier::BOLD));
+        assert!(span.style.add_modifier.contains(Modifier::UNDERLINED));
+        assert_eq!(span.style.fg, Some(Color::Blue));
+    }
+
+    #[test]
+    fn standalone_image_produces_image_ref() {
+        let items = markdown_to_lines_with_images(&Node::parse("![my logo](logo.svg)\n"), 80).items;
+        assert!(matches!(&items[0], ParsedLine::ImageRef { alt, url } if alt == "my logo" && url == "logo.svg"));
+    }
+
+    #[test]
+    fn inline_image_is_placeholder_text() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("See ![chart](c.png) here\n"), 80).items);
+        assert_eq!(texts, vec!["See [Image: chart] here"]);
+    }
+
+    #[test]
+    fn html_block_image_and_text() {
+        let md = "<p align=\"center\"><img src=\"logo.png\" alt=\"logo\" width=\"200\"/></p>\n\n<details>\n<summary>More &amp; more</summary>\n</details>\n";
+        let items = markdown_to_lines_with_images(&Node::parse(md), 80).items;
+        assert!(items.iter().any(|i| matches!(i, ParsedLine::ImageRef { url, .. } if url == "logo.png")));
+        assert!(texts(&items).iter().any(|t| t == "More & more"));
+    }
+
+    #[test]
+    fn table_has_borders_and_alignment() {
+        let md = "| Left | Center | Right |\n|:-----|:------:|------:|\n| a | b | c |\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 80).items);
+        assert_eq!(texts, vec![
+            "┌──────┬────────┬───────┐",
+            "│ Left │ Center │ Right │",
+            "├──────┼────────┼───────┤",
+            "│ a    │   b    │     c │",
+            "└──────┴────────┴───────┘",
+        ]);
+    }
+
+    #[test]
+    fn table_header_is_styled() {
+        let items = markdown_to_lines_with_images(&Node::parse("| H |\n|---|\n| v |\n"), 80).items;
+        let ParsedLine::Text(header) = &items[1] else { panic!("expected text line") };
+        let span = header.spans.iter().find(|s| s.content == "H").expect("header cell");
+        assert!(span.style.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn table_wraps_cells_when_too_wide() {
+        let md = "| Name | Description |\n|---|---|\n| x | a very long description that cannot fit |\n";
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse(md), 30).items);
+        for text in &texts {
+            assert!(Line::from(text.as_str()).width() <= 30, "row too wide: {:?}", text);
+        }
+        // The long cell spans several rows, all framed by borders
+        let body_rows: Vec<&String> = texts.iter().skip(3).take_while(|t| t.starts_with('│')).collect();
+        assert!(body_rows.len() > 1, "long cell should wrap, got: {:?}", texts);
+        assert!(body_rows.iter().all(|t| t.ends_with('│')));
+    }
+
+    #[test]
+    fn fit_column_widths_shrinks_widest_first() {
+        assert_eq!(fit_column_widths(&[5, 10], 20), vec![5, 10]);
+        assert_eq!(fit_column_widths(&[5, 30], 20), vec![5, 15]);
+        assert_eq!(fit_column_widths(&[20, 30], 20), vec![10, 10]);
+        // Never below the minimum width, even if that overflows
+        assert_eq!(fit_column_widths(&[10, 10], 2), vec![3, 3]);
+    }
+
+    #[test]
+    fn hard_line_break_starts_new_line() {
+        let texts = texts(&markdown_to_lines_with_images(&Node::parse("first  \nsecond\nthird\n"), 80).items);
+        assert_eq!(texts, vec!["first", "second third"]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use comrak::nodes::NodeValue;
+
+use crate::core::ast::Node;
+use crate::core::html::percent_decode;
+
+/// A local file referenced by a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    /// Canonical path; the file itself may not exist yet.
+    pub path: PathBuf,
+    /// 1-based source line of the first reference.
+    pub line: usize,
+}
+
+/// Local files a document pulls in when rendered: Markdown images and
+/// `<img src>` in raw HTML, resolved against `base_dir` with the same rules as
+/// the image resolvers (remote and data URLs skipped, no escaping `base_dir`).
+/// Files that do not exist yet are included, as long as their directory does,
+/// so that creating them is noticed.
+pub fn local_assets(root: &Node, base_dir: &Path) -> Vec<Asset> {
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r#"<img\s[^>]*?src="([^"]+)""#).unwrap());
+
+    let mut sources = Vec::new();
+    for node in root.descendants() {
+        let line = node.sourcepos().start.line;
+        match node.value() {
+            NodeValue::Image(link) => sources.push((link.url.clone(), line)),
+            NodeValue::HtmlBlock(html) => sources.extend(re.captures_iter(&html.literal).map(|caps| (caps[1].to_string(), line))),
+            NodeValue::HtmlInline(html) => sources.extend(re.captures_iter(html).map(|caps| (caps[1].to_string(), line))),
+            _ => {}
+        }
+    }
+
+    let canonical_base = base_dir.canonicalize().unwrap_or_else(|_| base_dir.to_path_buf());
+    let mut assets: Vec<Asset> = Vec::new();
+    for (src, line) in sources {
+        if src.starts_with("http://") || src.starts_with("https://")
+            || src.starts_with("data:") || src.starts_with("file://")
+        {
+            continue;
+        }
+        let path = base_dir.join(percent_decode(&src));
+        let path = match path.canonicalize() {
+            Ok(canonical) => canonical,
+            // Not created yet: resolve through its directory instead
+            Err(_) => match (path.parent().and_then(|dir| dir.canonicalize().ok()), path.file_name()) {
+                (Some(dir), Some(name)) => dir.join(name),
+                _ => continue,
+            },
+        };
+        if path.starts_with(&canonical_base) && !assets.iter().any(|asset| asset.path == path) {
+            assets.push(Asset { path, line });
+        }
+    }
+    assets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_assets_lists_images_from_markdown_and_html() {
+        let dir = std::env::temp_dir().join("mdr_test_local_assets");
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("img/diagram.png"), b"png").unwrap();
+        std::fs::write(dir.join("logo.svg"), "<svg/>").unwrap();
+        let base = dir.canonicalize().unwrap();
+
+        let md = "![d](img/diagram.png) ![again](img/diagram.png)\n\n\
+                  <p><img alt=\"logo\" src=\"logo.svg\"></p>\n\n\
+                  ![later](not%20yet.png) ![web](https://example.com/a.png) ![up](../outside.png)\n";
+        let assets = local_assets(&Node::parse(md), &base);
+        let found: Vec<_> = assets.iter().map(|asset| (asset.path.clone(), asset.line)).collect();
+        assert_eq!(found, vec![
+            (base.join("img/diagram.png"), 1),
+            (base.join("logo.svg"), 3),
+            (base.join("not yet.png"), 5),
+        ]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,95 @@
+//! The parsed Markdown of a document, owned so a
+//! [`Document`](crate::core::document::Document) can keep it between loads.
+//! comrak's own AST borrows from the arena it was parsed into, so it cannot
+//! be stored next to the source; this is a copy of it that can.
+
+use std::cell::RefCell;
+
+use comrak::nodes::{Ast, AstNode, NodeValue, Sourcepos};
+use comrak::{parse_document, Arena};
+
+use crate::core::markdown::comrak_options;
+
+/// A node of the Markdown AST with the nodes inside it, in document order.
+#[derive(Debug, Clone)]
+pub struct Node {
+    ast: Ast,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Parse `markdown` with [`comrak_options`]; the result is the document node.
+    pub fn parse(markdown: &str) -> Node {
+        let arena = Arena::new();
+        Node::from_comrak(parse_document(&arena, markdown, &comrak_options()))
+    }
+
+    fn from_comrak<'a>(node: &'a AstNode<'a>) -> Node {
+        Node {
+            ast: node.data.borrow().clone(),
+            children: node.children().map(Node::from_comrak).collect(),
+        }
+    }
+
+    /// Rebuild the tree in `arena`, for comrak's HTML formatter.
+    pub fn to_comrak<'a>(&self, arena: &'a Arena<'a>) -> &'a AstNode<'a> {
+        let node = arena.alloc(AstNode::new(RefCell::new(self.ast.clone())));
+        for child in &self.children {
+            node.append(child.to_comrak(arena));
+        }
+        node
+    }
+
+    pub fn value(&self) -> &NodeValue {
+        &self.ast.value
+    }
+
+    pub fn sourcepos(&self) -> Sourcepos {
+        self.ast.sourcepos
+    }
+
+    /// 0-based source lines the node spans, end exclusive.
+    pub fn lines(&self) -> std::ops::Range<usize> {
+        let pos = self.ast.sourcepos;
+        pos.start.line.saturating_sub(1)..pos.end.line
+    }
+
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// This node and every node inside it, depth first in document order.
+    pub fn descendants(&self) -> impl Iterator<Item = &Node> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descendants_are_in_document_order() {
+        let root = Node::parse("# Title\n\n- one\n- *two*\n\nEnd\n");
+        let kinds: Vec<&str> = root.descendants().map(|node| node.value().xml_node_name()).collect();
+        assert_eq!(kinds, [
+            "document", "heading", "text", "list", "item", "paragraph", "text",
+            "item", "paragraph", "emph", "text", "paragraph", "text",
+        ]);
+        assert_eq!(root.children().iter().map(Node::lines).collect::<Vec<_>>(), [0..1, 2..4, 5..6]);
+    }
+
+    #[test]
+    fn rebuilt_tree_formats_like_the_source() {
+        let md = "# T\n\n| a | b |\n|---|:-:|\n| 1 | 2 |\n\n- [x] done\n\nNote[^1] $x$\n\n> [!NOTE]\n> Hi\n\n[^1]: Foot.\n";
+        let arena = Arena::new();
+        let mut html = String::new();
+        comrak::format_html(Node::parse(md).to_comrak(&arena), &comrak_options(), &mut html).unwrap();
+        assert_eq!(html, comrak::markdown_to_html(md, &comrak_options()));
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use crate::vlog;
+
+/// Memory the in-process cache may hold; the least recently used results are
+/// dropped to stay under it.
+const MAX_MEMORY_BYTES: usize = 128 * 1024 * 1024;
+
+/// Size the on-disk cache is pruned to when mdr starts, oldest files first.
+const MAX_DISK_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Files on disk not used for this long are pruned at startup. Keys include
+/// the mdr version, so this is also how results from old versions go away.
+const MAX_DISK_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Rendered bytes, or why rendering failed.
+pub type Rendered = Result<Arc<[u8]>, String>;
+
+/// Why a render passed to [`get_or_render`] failed. A `transient` failure,
+/// such as running out of time under load, says nothing about the input, so
+/// it is not remembered and the next lookup renders again.
+#[derive(Debug)]
+pub struct Failure {
+    pub message: String,
+    pub transient: bool,
+}
+
+impl Failure {
+    pub fn transient(message: String) -> Failure {
+        Failure { message, transient: true }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure { message, transient: false }
+    }
+}
+
+// Unit tests stay out of the user's cache directory
+static DISK_CACHE: AtomicBool = AtomicBool::new(!cfg!(test));
+
+/// Whether rendered results are also read from and written to disk
+/// (`--no-cache` turns this off).
+pub fn set_disk_cache(enabled: bool) {
+    DISK_CACHE.store(enabled, Ordering::Relaxed);
+}
+
+/// Stable 128-bit FNV-1a hash of `parts`, as hex. Each part is length-prefixed
+/// so that `["ab", "c"]` and `["a", "bc"]` differ. Unlike `DefaultHasher`, the
+/// result is the same across builds, so it can name files on disk.
+pub fn key(parts: &[&[u8]]) -> String {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    let mut hash = OFFSET;
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(part.iter()) {
+            hash ^= *byte as u128;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    format!("{:032x}", hash)
+}
+
+/// Return the cached result of rendering `key` as `kind` (e.g. "mermaid-svg"),
+/// calling `render` only on a miss. `key` should come from [`key`] and cover
+/// every input that affects the output (source, theme, size, mdr version).
+/// Successes are kept in memory and on disk under the user cache directory;
+/// failures only in memory, so they are retried by the next mdr process, and
+/// transient failures not at all.
+pub fn get_or_render(
+    kind: &str,
+    key: &str,
+    render: impl FnOnce() -> Result<Vec<u8>, Failure>,
+) -> Rendered {
+    let cache = global();
+    let disk = DISK_CACHE.load(Ordering::Relaxed);
+
+    if let Some(hit) = cache.lock().unwrap().get(kind, key, disk) {
+        return hit;
+    }
+    // Render without holding the lock so other diagrams can proceed meanwhile
+    let start = std::time::Instant::now();
+    let result = match render() {
+        Ok(bytes) => Ok(Arc::from(bytes)),
+        Err(failure) if failure.transient => {
+            vlog!("cache: not keeping {} {}: {}", kind, key, failure.message);
+            return Err(failure.message);
+        }
+        Err(failure) => Err(failure.message),
+    };
+    vlog!("cache: rendered {} {} in {:?}", kind, key, start.elapsed());
+    cache.lock().unwrap().insert(kind, key, result.clone(), disk);
+    result
+}
+
+/// The cached result of rendering `key` as `kind`, if there is one. Never renders.
+pub fn get(kind: &str, key: &str) -> Option<Rendered> {
+    global().lock().unwrap().get(kind, key, DISK_CACHE.load(Ordering::Relaxed))
+}
+
+fn global() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        let dir = dirs::cache_dir().map(|dir| dir.join("mdr"));
+        if let Some(dir) = dir.clone().filter(|_| DISK_CACHE.load(Ordering::Relaxed)) {
+            std::thread::spawn(move || prune(&dir, MAX_DISK_BYTES, MAX_DISK_AGE));
+        }
+        Mutex::new(Cache::new(dir))
+    })
+}
+
+/// Delete cache files under `dir` unused for longer than `max_age`, then the
+/// least recently used ones until the rest fit in `max_bytes`. Hits refresh a
+/// file's modification time, so it tracks when the file was last used.
+fn prune(dir: &Path, max_bytes: u64, max_age: Duration) {
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|kind| std::fs::read_dir(kind.path()).ok())
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    files.sort();
+    let now = SystemTime::now();
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    let mut removed = 0;
+    for (modified, size, path) in &files {
+        let stale = now.duration_since(*modified).is_ok_and(|age| age > max_age);
+        if !stale && total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(path).is_ok() {
+            total -= size;
+            removed += 1;
+        }
+    }
+    vlog!("cache: pruned {} file(s) from {}, {} bytes left", removed, dir.display(), total);
+}
+
+struct Cache {
+    memory: HashMap<(String, String), Entry>,
+    memory_bytes: usize,
+    max_memory_bytes: usize,
+    /// Incremented on every memory lookup, to order entries by last use.
+    clock: u64,
+    /// Root of the on-disk cache, or `None` if there is no cache directory.
+    dir: Option<PathBuf>,
+}
+
+struct Entry {
+    result: Rendered,
+    last_used: u64,
+}
+
+impl Cache {
+    fn new(dir: Option<PathBuf>) -> Self {
+        Cache { memory: HashMap::new(), memory_bytes: 0, max_memory_bytes: MAX_MEMORY_BYTES, clock: 0, dir }
+    }
+
+    fn path(&self, kind: &str, key: &str) -> Option<PathBuf> {
+        Some(self.dir.as_ref()?.join(kind).join(key))
+    }
+
+    fn get(&mut self, kind: &str, key: &str, disk: bool) -> Option<Rendered> {
+        self.clock += 1;
+        if let Some(hit) = self.memory.get_mut(&(kind.to_string(), key.to_string())) {
+            hit.last_used = self.clock;
+            return Some(hit.result.clone());
+        }
+        let path = self.path(kind, key).filter(|_| disk)?;
+        let bytes: Arc<[u8]> = std::fs::read(&path).ok()?.into();
+        // Mark the file as recently used for `prune`
+        if let Err(e) = std::fs::File::options().append(true).open(&path).and_then(|file| file.set_modified(SystemTime::now())) {
+            vlog!("cache: cannot touch {}: {}", path.display(), e);
+        }
+        self.remember(kind, key, Ok(Arc::clone(&bytes)));
+        Some(Ok(bytes))
+    }
+
+    fn insert(&mut self, kind: &str, key: &str, result: Rendered, disk: bool) {
+        if let (Ok(bytes), Some(path)) = (&result, self.path(kind, key).filter(|_| disk)) {
+            // Write to a temporary name and rename, so a concurrent reader never
+            // sees a partial file
+            let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+            let written = path.parent().map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&tmp, bytes))
+                .and_then(|_| std::fs::rename(&tmp, &path));
+            if let Err(e) = written {
+                vlog!("cache: cannot write {}: {}", path.display(), e);
+                let _ = std::fs::remove_file(&tmp);
+            }
+        }
+        self.remember(kind, key, result);
+    }
+
+    fn remember(&mut self, kind: &str, key: &str, result: Rendered) {
+        let size = result.as_ref().map_or(0, |bytes| bytes.len());
+        self.clock += 1;
+        let entry = Entry { result, last_used: self.clock };
+        if let Some(old) = self.memory.insert((kind.to_string(), key.to_string()), entry) {
+            self.memory_bytes -= old.result.as_ref().map_or(0, |bytes| bytes.len());
+        }
+        self.memory_bytes += size;
+        while self.memory_bytes > self.max_memory_bytes && self.memory.len() > 1 {
+            let oldest = self.memory.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+                .expect("cache is not empty");
+            if let Some(evicted) = self.memory.remove(&oldest) {
+                self.memory_bytes -= evicted.result.as_ref().map_or(0, |bytes| bytes.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_is_stable_and_separates_parts() {
+        assert_eq!(key(&[b"graph LR"]), key(&[b"graph LR"]));
+        assert_ne!(key(&[b"ab", b"c"]), key(&[b"a", b"bc"]));
+        assert_eq!(key(&[]), "6c62272e07bb014262b821756295c58d");
+        assert_eq!(key(&[b"x"]).len(), 32);
+    }
+
+    #[test]
+    fn cache_remembers_results_in_memory_and_on_disk() {
+        let dir = std::env::temp_dir().join("mdr_test_cache");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut cache = Cache::new(Some(dir.clone()));
+        assert!(cache.get("svg", "k1", true).is_none());
+        cache.insert("svg", "k1", Ok(Arc::from(&b"<svg/>"[..])), true);
+        cache.insert("svg", "k2", Err("bad diagram".to_string()), true);
+        assert_eq!(cache.get("svg", "k1", true).unwrap().unwrap().as_ref(), b"<svg/>");
+        assert_eq!(cache.get("svg", "k2", true).unwrap().unwrap_err(), "bad diagram");
+        assert_eq!(std::fs::read(dir.join("svg").join("k1")).unwrap(), b"<svg/>");
+        assert!(!dir.join("svg").join("k2").exists(), "failures are not written to disk");
+
+        // A new process finds successes on disk, unless the disk cache is off
+        let mut cache = Cache::new(Some(dir.clone()));
+        assert!(cache.get("svg", "k1", false).is_none());
+        assert_eq!(cache.get("svg", "k1", true).unwrap().unwrap().as_ref(), b"<svg/>");
+        assert!(cache.get("svg", "k2", true).is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn transient_failures_are_rendered_again() {
+        let renders = std::cell::Cell::new(0);
+        let render = |failure: Option<Failure>| {
+            get_or_render("test-transient", "k", || {
+                renders.set(renders.get() + 1);
+                failure.map_or(Ok(b"ok".to_vec()), Err)
+            })
+        };
+        let timeout = Failure::transient("diagram took longer than 5s to render".to_string());
+        assert_eq!(render(Some(timeout)).unwrap_err(), "diagram took longer than 5s to render");
+        assert_eq!(render(None).unwrap().as_ref(), b"ok");
+        assert_eq!(render(Some(Failure::from("bad".to_string()))).unwrap().as_ref(), b"ok");
+        assert_eq!(renders.get(), 2);
+
+        // Real failures are remembered
+        let failed = get_or_render("test-transient", "bad", || Err(Failure::from("bad diagram".to_string())));
+        assert_eq!(failed.unwrap_err(), "bad diagram");
+        assert_eq!(get_or_render("test-transient", "bad", || Ok(b"ok".to_vec())).unwrap_err(), "bad diagram");
+    }
+
+    #[test]
+    fn memory_evicts_least_recently_used_results() {
+        let mut cache = Cache::new(None);
+        cache.max_memory_bytes = 10;
+        cache.insert("png", "a", Ok(Arc::from(&b"aaaa"[..])), false);
+        cache.insert("png", "b", Ok(Arc::from(&b"bbbb"[..])), false);
+        assert!(cache.get("png", "a", false).is_some());
+        cache.insert("png", "c", Ok(Arc::from(&b"cccc"[..])), false);
+        assert!(cache.get("png", "b", false).is_none(), "the least recently used result goes first");
+        assert!(cache.get("png", "a", false).is_some());
+        assert!(cache.get("png", "c", false).is_some());
+        assert_eq!(cache.memory_bytes, 8);
+    }
+
+    #[test]
+    fn prune_removes_stale_files_then_the_oldest_over_the_limit() {
+        let dir = std::env::temp_dir().join("mdr_test_cache_prune");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("svg")).unwrap();
+        let now = SystemTime::now();
+        let day = Duration::from_secs(24 * 60 * 60);
+        for (name, age) in [("stale", 60), ("old", 3), ("recent", 2), ("new", 0)] {
+            let path = dir.join("svg").join(name);
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            let file = std::fs::File::options().append(true).open(&path).unwrap();
+            file.set_modified(now - day * age).unwrap();
+        }
+
+        prune(&dir, 250, day * 30);
+        let left = |name: &str| dir.join("svg").join(name).exists();
+        assert!(!left("stale"), "files unused for longer than the maximum age go");
+        assert!(!left("old"), "then the oldest until the rest fit");
+        assert!(left("recent") && left("new"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+use crate::core::ast::Node;
+
+/// How a block of the new document differs from the previous version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    /// The block has no counterpart in the old document.
+    Inserted,
+    /// The block replaced one or more old blocks at the same place.
+    Modified,
+}
+
+/// A changed block in the new document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockChange {
+    /// Index of the block in the new block list.
+    pub index: usize,
+    pub kind: ChangeKind,
+}
+
+/// A changed region of the new Markdown source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineChange {
+    /// 0-based source lines, end exclusive.
+    pub lines: Range<usize>,
+    pub kind: ChangeKind,
+}
+
+/// Compare two block lists and report the blocks of `new` that are not part
+/// of their longest common subsequence with `old`. A new block counts as
+/// modified when old blocks were dropped at the same position, and as
+/// inserted otherwise.
+pub fn diff_blocks<T: PartialEq>(old: &[T], new: &[T]) -> Vec<BlockChange> {
+    // lcs[i][j] = length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    // Unmatched blocks of the current gap between matched pairs
+    let mut removed = 0;
+    let mut gap = Vec::new();
+    fn flush(removed: &mut usize, gap: &mut Vec<usize>, changes: &mut Vec<BlockChange>) {
+        let kind = if *removed > 0 { ChangeKind::Modified } else { ChangeKind::Inserted };
+        changes.extend(gap.drain(..).map(|index| BlockChange { index, kind }));
+        *removed = 0;
+    }
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            flush(&mut removed, &mut gap, &mut changes);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            gap.push(j);
+            j += 1;
+        } else {
+            removed += 1;
+            i += 1;
+        }
+    }
+    flush(&mut removed, &mut gap, &mut changes);
+    changes
+}
+
+/// Source line ranges (0-based, end exclusive) of the top-level blocks of a
+/// Markdown document, in document order.
+pub fn markdown_blocks(root: &Node) -> Vec<Range<usize>> {
+    root.children().iter().map(Node::lines).collect()
+}
+
+/// Diff two versions of a Markdown document block by block and return the
+/// changed regions of the new version. Each version comes with its parse.
+pub fn changed_lines(old: &str, old_root: &Node, new: &str, new_root: &Node) -> Vec<LineChange> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let block_text = |lines: &[&str], range: &Range<usize>| -> String {
+        lines[range.start.min(lines.len())..range.end.min(lines.len())].join("\n")
+    };
+
+    let old_blocks: Vec<String> = markdown_blocks(old_root).iter().map(|r| block_text(&old_lines, r)).collect();
+    let new_ranges = markdown_blocks(new_root);
+    let new_blocks: Vec<String> = new_ranges.iter().map(|r| block_text(&new_lines, r)).collect();
+
+    diff_blocks(&old_blocks, &new_blocks)
+        .into_iter()
+        .map(|change| LineChange { lines: new_ranges[change.index].clone(), kind: change.kind })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_blocks_classifies_inserted_and_modified() {
+        let old = ["a", "b", "c"];
+        assert_eq!(diff_blocks(&old, &old), vec![]);
+        assert_eq!(
+            diff_blocks(&old, &["a", "x", "b", "c"]),
+            vec![BlockChange { index: 1, kind: ChangeKind::Inserted }],
+        );
+        assert_eq!(
+            diff_blocks(&old, &["a", "B", "c", "d"]),
+            vec![
+                BlockChange { index: 1, kind: ChangeKind::Modified },
+                BlockChange { index: 3, kind: ChangeKind::Inserted },
+            ],
+        );
+        assert_eq!(diff_blocks(&old, &["a", "c"]), vec![]);
+    }
+
+    #[test]
+    fn markdown_blocks_cover_top_level_nodes() {
+        let md = "# Title\n\nFirst paragraph\nstill first.\n\n- one\n- two\n";
+        assert_eq!(markdown_blocks(&Node::parse(md)), vec![0..1, 2..4, 5..7]);
+    }
+
+    #[test]
+    fn changed_lines_reports_new_source_ranges() {
+        let old = "# Title\n\nIntro.\n\n## Usage\n\nRun it.\n";
+        let new = "# Title\n\nIntro, reworded.\n\n## Usage\n\nRun it.\n\nAppended.\n";
+        let (old_root, new_root) = (Node::parse(old), Node::parse(new));
+        assert_eq!(changed_lines(old, &old_root, new, &new_root), vec![
+            LineChange { lines: 2..3, kind: ChangeKind::Modified },
+            LineChange { lines: 8..9, kind: ChangeKind::Inserted },
+        ]);
+        assert_eq!(changed_lines(old, &old_root, old, &old_root), vec![]);
+    }
+}
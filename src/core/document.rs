@@ -0,0 +1,286 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::core::assets::{local_assets, Asset};
+use crate::core::ast::Node;
+use crate::core::diff::{self, LineChange};
+use crate::core::markdown::{blank_front_matter, parse_front_matter, FrontMatter};
+use crate::core::mermaid::{self, MermaidError};
+use crate::core::stdin;
+use crate::core::toc::{self, TocEntry};
+use crate::vlog;
+
+/// A problem found while loading a document that does not stop it rendering.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based source line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A Markdown document as every backend sees it: where it lives, its source,
+/// and what is derived from the source (its AST, front matter, TOC, local
+/// assets, diagnostics).
+/// The source is parsed once per load; the TOC, the assets, the diagnostics,
+/// the reload diff, the TUI renderer and HTML rendering all work from
+/// [`Document::ast`]. Only egui, whose Markdown widget parses for itself,
+/// renders from [`Document::body`].
+/// Backends render from this and call [`Document::reload`] when the watcher
+/// fires, so loading behaves the same everywhere.
+pub struct Document {
+    path: PathBuf,
+    base_dir: PathBuf,
+    /// The source without its front matter, for rendering.
+    body: String,
+    ast: Node,
+    front_matter: Option<FrontMatter>,
+    toc: Vec<TocEntry>,
+    assets: Vec<Asset>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    /// Load the document at `path`, or the stdin stream for `-`.
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let source = stdin::read_markdown(&path)?;
+        let base_dir = document_dir(&path);
+        vlog!("document: {} (base_dir={})", path.display(), base_dir.display());
+        let mut document = Document {
+            path,
+            base_dir,
+            body: String::new(),
+            ast: Node::parse(""),
+            front_matter: None,
+            toc: Vec::new(),
+            assets: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        document.set_source(source);
+        Ok(document)
+    }
+
+    /// Re-read the source and return the regions that changed since the last
+    /// load. On error the previous contents are kept.
+    pub fn reload(&mut self) -> std::io::Result<Vec<LineChange>> {
+        let source = stdin::read_markdown(&self.path)?;
+        let old_body = std::mem::take(&mut self.body);
+        let old_ast = std::mem::replace(&mut self.ast, Node::parse(""));
+        self.set_source(source);
+        Ok(diff::changed_lines(&old_body, &old_ast, &self.body, &self.ast))
+    }
+
+    fn set_source(&mut self, source: String) {
+        let mut diagnostics = Vec::new();
+        self.front_matter = match parse_front_matter(&source) {
+            Some(Ok(front_matter)) => Some(front_matter),
+            Some(Err(message)) => {
+                diagnostics.push(Diagnostic { line: 1, message });
+                None
+            }
+            None => None,
+        };
+        self.body = match &self.front_matter {
+            Some(front_matter) => blank_front_matter(&source, front_matter.lines),
+            None => source.clone(),
+        };
+        self.ast = Node::parse(&self.body);
+        self.toc = toc::extract_toc(&self.ast);
+        self.assets = local_assets(&self.ast, &self.base_dir);
+        diagnostics.extend(self.assets.iter()
+            .filter(|asset| !asset.path.exists())
+            .map(|asset| Diagnostic {
+                line: asset.line,
+                message: format!("image not found: {}", asset.path.display()),
+            }));
+        diagnostics.extend(mermaid::diagrams(&self.ast).into_iter()
+            .filter_map(|(line, source)| Some(mermaid_diagnostic(line, mermaid::check(&source).err()?))));
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        self.diagnostics = diagnostics;
+        for diagnostic in &self.diagnostics {
+            vlog!("{}: {}", self.path.display(), diagnostic);
+        }
+    }
+
+    /// The path the document was opened with (`-` for stdin).
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Directory that relative links and images resolve against.
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    /// The Markdown to render: the source with front matter blanked out, so
+    /// line numbers still match the file.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend"))]
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// The parsed [`Document::body`].
+    pub fn ast(&self) -> &Node {
+        &self.ast
+    }
+
+    pub fn front_matter(&self) -> Option<&FrontMatter> {
+        self.front_matter.as_ref()
+    }
+
+    pub fn toc(&self) -> &[TocEntry] {
+        &self.toc
+    }
+
+    /// Local files the document shows, for the watcher.
+    pub fn asset_paths(&self) -> Vec<PathBuf> {
+        self.assets.iter().map(|asset| asset.path.clone()).collect()
+    }
+
+    #[cfg(any(test, feature = "tui-backend"))]
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// [`Document::diagnostics`] plus Mermaid diagrams that look right but fail
+    /// to render, for `--check`. Renders every diagram, so it can be slow.
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = self.diagnostics.clone();
+        for (line, source) in mermaid::diagrams(&self.ast) {
+            if mermaid::check(&source).is_ok() {
+                if let Err(e) = mermaid::render_mermaid_to_svg(&source, mermaid::Theme::default()) {
+                    diagnostics.push(mermaid_diagnostic(line, mermaid::diagnose(&source, &e)));
+                }
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+        diagnostics
+    }
+
+    /// Page title: the front matter `title`, else the file name, or "mdr"
+    /// for stdin.
+    pub fn title(&self) -> String {
+        if let Some(title) = self.front_matter_title() {
+            return title.to_string();
+        }
+        self.path.file_name()
+            .filter(|_| !stdin::is_stdin(&self.path))
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "mdr".to_string())
+    }
+
+    /// Name for window titles and the TUI header: the front matter `title`,
+    /// else the path as given.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn header(&self) -> String {
+        match self.front_matter_title() {
+            Some(title) => title.to_string(),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    fn front_matter_title(&self) -> Option<&str> {
+        self.front_matter.as_ref()?.title.as_deref()
+    }
+}
+
+/// Directory that relative links and images in a document resolve against.
+fn document_dir(file_path: &Path) -> PathBuf {
+    std::fs::canonicalize(file_path)
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .or_else(|| {
+            std::env::current_dir().ok()
+                .map(|cwd| cwd.join(file_path))
+                .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        })
+        .unwrap_or_default()
+}
+
+/// A diagnostic for `error` in the diagram whose source starts on `line`.
+pub(crate) fn mermaid_diagnostic(line: usize, error: MermaidError) -> Diagnostic {
+    Diagnostic {
+        line: line + error.line.map_or(0, |l| l - 1),
+        message: match error.column {
+            Some(column) => format!("mermaid: {} (column {})", error.message, column),
+            None => format!("mermaid: {}", error.message),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_and_reload_track_source_toc_and_diagnostics() {
+        let dir = std::env::temp_dir().join("mdr_test_document");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("logo.png"), b"png").unwrap();
+        let path = dir.join("doc.md");
+        std::fs::write(&path, "# Title\n\n![logo](logo.png)\n").unwrap();
+
+        let mut document = Document::open(&path).unwrap();
+        assert_eq!(document.title(), "doc.md");
+        assert_eq!(document.base_dir(), dir.canonicalize().unwrap());
+        assert_eq!(document.toc().len(), 1);
+        assert_eq!(document.asset_paths(), vec![dir.canonicalize().unwrap().join("logo.png")]);
+        assert!(document.diagnostics().is_empty());
+
+        std::fs::write(&path, "# Title\n\n![logo](logo.png)\n\n## Missing\n\n![gone](gone.png)\n").unwrap();
+        let changes = document.reload().unwrap();
+        assert!(!changes.is_empty());
+        assert_eq!(document.toc().len(), 2);
+        assert_eq!(document.diagnostics().len(), 1);
+        assert_eq!(document.diagnostics()[0].line, 7);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(document.reload().is_err());
+        assert!(document.body().contains("## Missing"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn front_matter_is_hidden_from_the_body_and_titles_the_document() {
+        let dir = std::env::temp_dir().join("mdr_test_document_front_matter");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notes.md");
+        std::fs::write(&path, "---\ntitle: Notes\nauthor: Ada\n---\n# Intro\n").unwrap();
+
+        let document = Document::open(&path).unwrap();
+        assert_eq!(document.title(), "Notes");
+        assert_eq!(document.header(), "Notes");
+        assert_eq!(document.body(), "\n\n\n\n# Intro\n");
+        assert_eq!(document.toc().len(), 1);
+        assert_eq!(document.front_matter().unwrap().fields, vec![("author".to_string(), "Ada".to_string())]);
+
+        std::fs::write(&path, "---\ntitle: [oops\n---\n# Intro\n").unwrap();
+        let document = Document::open(&path).unwrap();
+        assert!(document.front_matter().is_none());
+        assert_eq!(document.diagnostics()[0].line, 1);
+        assert_eq!(document.header(), path.display().to_string());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn broken_diagrams_are_reported_at_their_markdown_line() {
+        let dir = std::env::temp_dir().join("mdr_test_document_mermaid");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("diagrams.md");
+        std::fs::write(&path, "# Diagrams\n\n```mermaid\ngraph LR\n  A-->B\n```\n\n```mermaid\ngraph LR\n  A[Start --> B\n```\n").unwrap();
+
+        let document = Document::open(&path).unwrap();
+        assert_eq!(document.diagnostics(), [Diagnostic { line: 10, message: "mermaid: `[` is never closed (column 4)".to_string() }]);
+        assert_eq!(document.check(), document.diagnostics());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
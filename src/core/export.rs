@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+
+use crate::core::document::{self, Diagnostic, Document};
+use crate::core::html::{build_html, front_matter_html, resolve_local_images};
+use crate::core::markdown::render_markdown;
+use crate::core::mermaid::{self, Theme};
+use crate::core::stdin;
+use crate::vlog;
+
+/// Render a Markdown file to a standalone HTML document.
+/// Uses the same pipeline as the webview backend (TOC sidebar, search bar,
+/// inlined Mermaid SVG and images) but never opens a window.
+pub fn export_html(file_path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let document = Document::open(file_path)?;
+    vlog!("export: file_path={}", file_path.display());
+    Ok(render_html(&document))
+}
+
+/// Render a loaded document to a standalone HTML page.
+pub fn render_html(document: &Document) -> String {
+    let html_body = render_markdown(document.ast());
+    let html_body = front_matter_html(document.front_matter()) + &resolve_local_images(&html_body, document.base_dir());
+    build_html(&html_body, document.toc(), &document.title())
+}
+
+/// Write an exported document to `output`, or to stdout when `output` is `None` or `-`.
+pub fn write_output(document: &str, output: Option<&Path>) -> std::io::Result<()> {
+    use std::io::Write;
+    match output {
+        Some(path) if path.as_os_str() != "-" => std::fs::write(path, document),
+        _ => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(document.as_bytes())?;
+            stdout.flush()
+        }
+    }
+}
+
+/// File format a single Mermaid diagram is saved in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagramFormat {
+    Svg,
+    Png,
+}
+
+impl DiagramFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            DiagramFormat::Svg => "svg",
+            DiagramFormat::Png => "png",
+        }
+    }
+}
+
+/// Render a Mermaid diagram in the colours of `theme` and write it to `path`
+/// as `format`; PNG is rasterized at `scale`.
+pub fn save_diagram(source: &str, path: &Path, format: DiagramFormat, scale: f32, theme: Theme) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = match format {
+        DiagramFormat::Svg => mermaid::render_mermaid_to_svg(source, theme)?.into_bytes(),
+        DiagramFormat::Png => mermaid::render_mermaid_to_png(source, scale, theme)?.to_vec(),
+    };
+    std::fs::write(path, bytes)?;
+    vlog!("export: saved diagram to {}", path.display());
+    Ok(())
+}
+
+/// Name for diagram `number` (counting from 1 in document order) of the
+/// document at `document`: `<stem>-diagram-<number>.<ext>`.
+pub fn diagram_file_name(document: &Path, number: usize, format: DiagramFormat) -> String {
+    let stem = document.file_stem()
+        .filter(|_| !stdin::is_stdin(document))
+        .map_or_else(|| "stdin".into(), |stem| stem.to_string_lossy());
+    format!("{}-diagram-{}.{}", stem, number, format.extension())
+}
+
+/// Save diagram `number` of `document` next to the document, for the viewers'
+/// save actions, and return where it went.
+#[cfg(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+pub fn save_diagram_beside(document: &Document, number: usize, source: &str, format: DiagramFormat, scale: f32, theme: Theme) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = document.base_dir().join(diagram_file_name(document.path(), number, format));
+    save_diagram(source, &path, format, scale, theme)?;
+    Ok(path)
+}
+
+/// Write every Mermaid diagram in `document` to `dir` as numbered files (see
+/// [`diagram_file_name`]), in the light theme. Returns the file written for
+/// each diagram, or the problem `--check` would report for it.
+pub fn extract_diagrams(document: &Document, dir: &Path, format: DiagramFormat, scale: f32) -> Vec<Result<PathBuf, Diagnostic>> {
+    mermaid::diagrams(document.ast())
+        .into_iter()
+        .enumerate()
+        .map(|(i, (line, source))| {
+            let path = dir.join(diagram_file_name(document.path(), i + 1, format));
+            save_diagram(&source, &path, format, scale, Theme::Light)
+                .map(|()| path)
+                .map_err(|e| document::mermaid_diagnostic(line, mermaid::diagnose(&source, &e.to_string())))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_html_produces_standalone_document() {
+        let dir = std::env::temp_dir().join("mdr_test_export_html");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let png_path = dir.join("pixel.png");
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.save(&png_path).unwrap();
+
+        let md_path = dir.join("doc.md");
+        std::fs::write(&md_path, "# Title\n\n## Section\n\n![pixel](pixel.png)\n").unwrap();
+
+        let html = export_html(&md_path).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>doc.md</title>"));
+        assert!(html.contains(r##"<a href="#section">Section</a>"##), "TOC should be included");
+        assert!(html.contains("data:image/png;base64,"), "Local images should be inlined");
+        assert!(html.contains(r#"id="searchBar""#), "Search bar should be included");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn export_html_missing_file_is_error() {
+        let result = export_html(Path::new("/nonexistent/mdr_export_missing.md"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn extract_diagrams_writes_numbered_files() {
+        let dir = std::env::temp_dir().join("mdr_test_extract_diagrams");
+        let out = dir.join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        let md_path = dir.join("slides.md");
+        std::fs::write(&md_path, "# Slides\n\n```mermaid\ngraph LR\n  A-->B\n```\n\n```mermaid\ngraph LR\n  B -->\n```\n\n```mermaid\npie\n  \"a\" : 1\n```\n").unwrap();
+
+        let document = Document::open(&md_path).unwrap();
+        let results = extract_diagrams(&document, &out, DiagramFormat::Png, 2.0);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(out.join("slides-diagram-1.png")));
+        let problem = results[1].as_ref().unwrap_err();
+        assert_eq!(problem.line, 10);
+        assert!(problem.message.starts_with("mermaid: edge has no target node"), "{}", problem);
+        assert_eq!(results[2], Ok(out.join("slides-diagram-3.png")));
+        let png = std::fs::read(out.join("slides-diagram-1.png")).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        assert!(!out.join("slides-diagram-2.png").exists());
+
+        let results = extract_diagrams(&document, &out, DiagramFormat::Svg, 1.0);
+        assert_eq!(results[0], Ok(out.join("slides-diagram-1.svg")));
+        assert!(std::fs::read_to_string(out.join("slides-diagram-1.svg")).unwrap().starts_with("<svg"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diagram_file_names_use_the_document_stem() {
+        assert_eq!(diagram_file_name(Path::new("docs/guide.md"), 2, DiagramFormat::Svg), "guide-diagram-2.svg");
+        assert_eq!(diagram_file_name(Path::new(stdin::STDIN_PATH), 1, DiagramFormat::Png), "stdin-diagram-1.png");
+    }
+
+    #[test]
+    fn write_output_to_file() {
+        let dir = std::env::temp_dir().join("mdr_test_export_write");
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out.html");
+
+        write_output("<p>hi</p>", Some(&out)).unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "<p>hi</p>");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
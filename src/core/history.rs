@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use crate::core::html::percent_decode;
+use crate::core::workspace::is_markdown_path;
+
+/// A visited document and where the reader was in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    /// Scroll position in backend-defined units (rows in the TUI, pixels in the GUIs).
+    pub scroll: f64,
+}
+
+/// Browser-style navigation history across linked Markdown files.
+#[derive(Debug)]
+pub struct History {
+    current: HistoryEntry,
+    back: Vec<HistoryEntry>,
+    forward: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        History {
+            current: HistoryEntry { path, scroll: 0.0 },
+            back: Vec::new(),
+            forward: Vec::new(),
+        }
+    }
+
+    /// Record the scroll position of the current document, so going back to it
+    /// later restores where the reader left off.
+    pub fn set_scroll(&mut self, scroll: f64) {
+        self.current.scroll = scroll;
+    }
+
+    /// Open a new document. Clears the forward stack, like following a link in a browser.
+    pub fn navigate(&mut self, path: PathBuf) -> &HistoryEntry {
+        let previous = std::mem::replace(&mut self.current, HistoryEntry { path, scroll: 0.0 });
+        self.back.push(previous);
+        self.forward.clear();
+        &self.current
+    }
+
+    /// Step back to the previous document, if any.
+    pub fn back(&mut self) -> Option<&HistoryEntry> {
+        let previous = self.back.pop()?;
+        let current = std::mem::replace(&mut self.current, previous);
+        self.forward.push(current);
+        Some(&self.current)
+    }
+
+    /// Step forward again after going back, if possible.
+    pub fn forward(&mut self) -> Option<&HistoryEntry> {
+        let next = self.forward.pop()?;
+        let current = std::mem::replace(&mut self.current, next);
+        self.back.push(current);
+        Some(&self.current)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+/// Where following a link leads.
+#[derive(Debug, PartialEq)]
+pub enum LinkTarget {
+    /// `#heading` inside the current document.
+    Anchor(String),
+    /// Another Markdown file, opened in mdr, optionally at a heading.
+    Document { path: PathBuf, anchor: Option<String> },
+    /// Anything else: web URLs, `mailto:`, and local non-Markdown files.
+    External(String),
+}
+
+/// Classify a link URL relative to the directory of the current document.
+pub fn classify_link(url: &str, base_dir: &Path) -> LinkTarget {
+    if let Some(anchor) = url.strip_prefix('#') {
+        return LinkTarget::Anchor(percent_decode(anchor));
+    }
+    if has_scheme(url) {
+        return LinkTarget::External(url.to_string());
+    }
+    let (path, anchor) = match url.split_once('#') {
+        Some((path, anchor)) => (path, Some(percent_decode(anchor)).filter(|a| !a.is_empty())),
+        None => (url, None),
+    };
+    let path = base_dir.join(percent_decode(path));
+    if is_markdown_path(&path) {
+        LinkTarget::Document { path, anchor }
+    } else {
+        LinkTarget::External(path.display().to_string())
+    }
+}
+
+/// Whether `url` starts with a URL scheme such as `https:` or `mailto:`.
+/// Single letters are treated as Windows drive letters, not schemes.
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn navigate_back_and_forward() {
+        let mut history = History::new(PathBuf::from("a.md"));
+        assert!(!history.can_go_back());
+        history.set_scroll(12.0);
+
+        let entry = history.navigate(PathBuf::from("b.md"));
+        assert_eq!(entry.path, PathBuf::from("b.md"));
+        assert_eq!(entry.scroll, 0.0);
+        history.set_scroll(3.0);
+
+        let back = history.back().unwrap();
+        assert_eq!(back.path, PathBuf::from("a.md"));
+        assert_eq!(back.scroll, 12.0);
+        assert!(history.back().is_none());
+
+        let forward = history.forward().unwrap();
+        assert_eq!(forward.path, PathBuf::from("b.md"));
+        assert_eq!(forward.scroll, 3.0);
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn navigate_clears_forward_stack() {
+        let mut history = History::new(PathBuf::from("a.md"));
+        history.navigate(PathBuf::from("b.md"));
+        history.back();
+        assert!(history.can_go_forward());
+        history.navigate(PathBuf::from("c.md"));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.back().unwrap().path, PathBuf::from("a.md"));
+    }
+
+    #[test]
+    fn classify_anchor_and_external() {
+        let base = Path::new("/docs");
+        assert_eq!(classify_link("#getting-started", base), LinkTarget::Anchor("getting-started".to_string()));
+        assert_eq!(classify_link("https://example.com/a.md", base), LinkTarget::External("https://example.com/a.md".to_string()));
+        assert_eq!(classify_link("mailto:me@example.com", base), LinkTarget::External("mailto:me@example.com".to_string()));
+    }
+
+    #[test]
+    fn classify_relative_documents() {
+        let base = Path::new("/docs");
+        assert_eq!(
+            classify_link("guide/Setup%20Notes.md#install", base),
+            LinkTarget::Document { path: PathBuf::from("/docs/guide/Setup Notes.md"), anchor: Some("install".to_string()) },
+        );
+        assert_eq!(
+            classify_link("../README.markdown", base),
+            LinkTarget::Document { path: PathBuf::from("/docs/../README.markdown"), anchor: None },
+        );
+        assert_eq!(classify_link("diagram.png", base), LinkTarget::External("/docs/diagram.png".to_string()));
+    }
+}
@@ -0,0 +1,785 @@
+use crate::core::markdown::{FrontMatter, GITHUB_CSS};
+use crate::core::mermaid::html_encode;
+use crate::core::raster::{file_to_data_uri, svg_file_to_png_data_uri};
+use crate::core::toc;
+#[cfg(feature = "webview-backend")]
+use crate::core::workspace::TreeEntry;
+use crate::vlog;
+
+/// Resolve local image paths to inline base64 data URIs.
+/// wry's `with_html()` does not allow loading file:// URLs, and exported HTML must be
+/// self-contained, so we embed images directly.
+/// SVG files are rasterized to PNG first (to avoid executing embedded scripts/links).
+/// Handles both `<img src="...">` and `<img alt="..." src="...">` attribute orders.
+pub fn resolve_local_images(html: &str, base_dir: &std::path::Path) -> String {
+    use std::sync::OnceLock;
+    vlog!("resolve_local_images: base_dir={}", base_dir.display());
+    // Match the entire <img ...> tag with src="..." anywhere inside
+    static RE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| regex::Regex::new(r#"<img\s[^>]*?src="([^"]+)"[^>]*?>"#).unwrap());
+    static RE_SRC: OnceLock<regex::Regex> = OnceLock::new();
+    let re_src = RE_SRC.get_or_init(|| regex::Regex::new(r#"src="[^"]+""#).unwrap());
+    re.replace_all(html, |caps: &regex::Captures| {
+        let full_tag = &caps[0];
+        let src = &caps[1];
+        vlog!("  IMG src={:?}", src);
+        // Skip URLs and existing data URIs
+        if src.starts_with("http://") || src.starts_with("https://")
+            || src.starts_with("data:") || src.starts_with("file://")
+        {
+            vlog!("    → skipped (remote/data URL)");
+            return full_tag.to_string();
+        }
+        // URL-decode the src path (comrak may percent-encode spaces etc.)
+        let decoded_src = percent_decode(src);
+        // Resolve relative path
+        let abs_path = base_dir.join(&decoded_src);
+        vlog!("    abs_path={}", abs_path.display());
+        vlog!("    exists={}", abs_path.exists());
+        // Path traversal protection: ensure resolved path is within base_dir
+        if let (Ok(canonical), Ok(canonical_base)) = (abs_path.canonicalize(), base_dir.canonicalize()) {
+            if !canonical.starts_with(&canonical_base) {
+                vlog!("    → BLOCKED (path traversal: {} escapes {})", canonical.display(), canonical_base.display());
+                return full_tag.to_string();
+            }
+        }
+        if abs_path.exists() {
+            let is_svg = abs_path.extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.eq_ignore_ascii_case("svg"))
+                .unwrap_or(false);
+            vlog!("    is_svg={}", is_svg);
+            if is_svg {
+                match svg_file_to_png_data_uri(&abs_path) {
+                    Ok(png_data_uri) => {
+                        vlog!("    → SVG rasterized to PNG ({} bytes)", png_data_uri.len());
+                                return re_src.replace(full_tag, format!("src=\"{}\"", png_data_uri).as_str()).to_string();
+                    }
+                    Err(e) => {
+                        vlog!("    → SVG rasterization FAILED: {}", e);
+                    }
+                }
+                // Fallback: embed SVG as data URI (scripts won't execute in <img> context)
+                match file_to_data_uri(&abs_path) {
+                    Ok(data_uri) => {
+                        vlog!("    → SVG embedded as data URI ({} bytes)", data_uri.len());
+                                return re_src.replace(full_tag, format!("src=\"{}\"", data_uri).as_str()).to_string();
+                    }
+                    Err(e) => {
+                        vlog!("    → SVG file_to_data_uri FAILED: {}", e);
+                    }
+                }
+                vlog!("    → SVG: all attempts failed, keeping original tag");
+                return full_tag.to_string();
+            }
+            // For non-SVG images, use base64 data URI
+            match file_to_data_uri(&abs_path) {
+                Ok(data_uri) => {
+                    vlog!("    → embedded as data URI ({} bytes)", data_uri.len());
+                        return re_src.replace(full_tag, format!("src=\"{}\"", data_uri).as_str()).to_string();
+                }
+                Err(e) => {
+                    vlog!("    → file_to_data_uri FAILED: {}", e);
+                }
+            }
+        } else {
+            vlog!("    → file NOT FOUND");
+        }
+        full_tag.to_string()
+    })
+    .to_string()
+}
+
+/// Decode percent-encoded URL path components (e.g. %20 -> space).
+pub fn percent_decode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let hex: String = chars.by_ref().take(2).collect();
+            if hex.len() == 2 {
+                if let Ok(byte) = u8::from_str_radix(&hex, 16) {
+                    result.push(byte as char);
+                    continue;
+                }
+            }
+            result.push('%');
+            result.push_str(&hex);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+pub fn build_toc_html(entries: &[toc::TocEntry]) -> String {
+    let mut toc = String::new();
+    for entry in entries {
+        toc.push_str(&format!(
+            "<li class=\"toc-h{}\"><a href=\"#{}\">{}</a></li>",
+            entry.level, entry.anchor, entry.text
+        ));
+    }
+    toc
+}
+
+/// Collapsed panel listing front matter keys other than `title`, placed
+/// above the document body. Empty if there are none.
+pub fn front_matter_html(front_matter: Option<&FrontMatter>) -> String {
+    let Some(front_matter) = front_matter.filter(|fm| !fm.fields.is_empty()) else {
+        return String::new();
+    };
+    let rows: String = front_matter.fields.iter()
+        .map(|(key, value)| format!("<tr><th>{}</th><td>{}</td></tr>", html_encode(key), html_encode(value)))
+        .collect();
+    format!("<details class=\"front-matter\"><summary>Metadata</summary><table>{}</table></details>\n", rows)
+}
+
+/// Sidebar section listing the files of a workspace, for directory mode.
+/// Entries carry their path in `data-path`; the page posts it back to open the file.
+#[cfg(feature = "webview-backend")]
+pub fn build_file_tree_html(entries: &[TreeEntry], current: &std::path::Path) -> String {
+    let mut html = String::from("<p class=\"sidebar-title\">Files</p><ul>");
+    for entry in entries {
+        match &entry.path {
+            None => html.push_str(&format!(
+                "<li class=\"tree-dir\" style=\"padding-left: {}px\">{}/</li>",
+                16 + entry.depth * 12, html_encode(&entry.name)
+            )),
+            Some(path) => html.push_str(&format!(
+                "<li><span class=\"tree-file{}\" style=\"padding-left: {}px\" data-path=\"{}\">{}</span></li>",
+                if path == current { " active" } else { "" },
+                16 + entry.depth * 12,
+                html_encode(&path.to_string_lossy()),
+                html_encode(&entry.name)
+            )),
+        }
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// `mdrUpdate(contentHtml, tocHtml, markChanges)`: swaps in a re-rendered document for live
+/// reload. Top-level blocks are diffed (longest common subsequence), only the
+/// ones that differ are replaced, so unchanged diagrams and images are not
+/// rebuilt, and the block at the top of the viewport (or failing that, the
+/// nearest heading above it) stays in place. Unless `markChanges` is false,
+/// replaced blocks are tinted for a few seconds; `c` jumps to the next one
+/// and Escape clears the tint. In follow mode (`F`, or `window.mdrFollow`
+/// set before this script runs) a reader at the bottom stays pinned there as
+/// the document grows, and anyone else is taken to the first change.
+const LIVE_UPDATE_JS: &str = r#"<script>
+(function() {
+    var CHANGE_MARK_MS = 5000;
+    var clearTimer = null;
+
+    function atBottom() {
+        return window.innerHeight + window.scrollY >= document.documentElement.scrollHeight - 2;
+    }
+
+    function scrollToBottom() {
+        window.scrollTo(0, document.documentElement.scrollHeight);
+    }
+
+    function setFollow(on) {
+        window.mdrFollow = on;
+        document.body.classList.toggle('mdr-follow', on);
+        if (on) scrollToBottom();
+    }
+    setFollow(!!window.mdrFollow);
+
+    function viewportAnchor(content) {
+        var blocks = content.children;
+        for (var i = 0; i < blocks.length; i++) {
+            var rect = blocks[i].getBoundingClientRect();
+            if (rect.bottom <= 0) continue;
+            var heading = null;
+            for (var j = i; j >= 0 && !heading; j--) {
+                if (/^H[1-6]$/.test(blocks[j].tagName) && blocks[j].id) heading = blocks[j];
+            }
+            return {
+                node: blocks[i],
+                top: rect.top,
+                headingId: heading ? heading.id : null,
+                headingTop: heading ? heading.getBoundingClientRect().top : 0,
+                index: i
+            };
+        }
+        return null;
+    }
+
+    function restoreAnchor(content, anchor) {
+        if (!anchor) return;
+        if (anchor.node.isConnected) {
+            window.scrollBy(0, anchor.node.getBoundingClientRect().top - anchor.top);
+            return;
+        }
+        var heading = anchor.headingId && document.getElementById(anchor.headingId);
+        if (heading) {
+            window.scrollBy(0, heading.getBoundingClientRect().top - anchor.headingTop);
+            return;
+        }
+        var block = content.children[Math.min(anchor.index, content.children.length - 1)];
+        if (block) window.scrollBy(0, block.getBoundingClientRect().top - anchor.top);
+    }
+
+    /* Most cells the LCS table may have (16MB); a changed stretch too long
+       for it is replaced wholesale instead of diffed. */
+    var MAX_DIFF_CELLS = 1 << 22;
+
+    /* A number identifying a node's markup: its length and a 32-bit FNV-1a
+       hash of it, so the diff compares numbers rather than long strings. */
+    function nodeKey(node) {
+        var s = node.nodeType === 1 ? node.outerHTML : node.nodeType + ':' + node.nodeValue;
+        var h = 0x811c9dc5;
+        for (var i = 0; i < s.length; i++) {
+            h ^= s.charCodeAt(i);
+            h = Math.imul(h, 0x01000193);
+        }
+        return s.length * 4294967296 + (h >>> 0);
+    }
+
+    /* Replace the children of `parent` that are not in their longest common
+       subsequence with the children of `html`. Returns the inserted nodes as
+       { node, modified } where `modified` means old blocks were dropped there. */
+    function morphChildren(parent, html) {
+        var fresh = document.createElement('div');
+        fresh.innerHTML = html;
+        var oldNodes = Array.prototype.slice.call(parent.childNodes);
+        var newNodes = Array.prototype.slice.call(fresh.childNodes);
+        var oldKeys = oldNodes.map(nodeKey), newKeys = newNodes.map(nodeKey);
+        // Appends and local edits leave the head and tail alone, so only the
+        // stretch between them is diffed
+        var start = 0, end = 0;
+        while (start < oldNodes.length && start < newNodes.length && oldKeys[start] === newKeys[start]) start++;
+        while (end < oldNodes.length - start && end < newNodes.length - start
+            && oldKeys[oldNodes.length - 1 - end] === newKeys[newNodes.length - 1 - end]) end++;
+        var n = oldNodes.length - start - end, m = newNodes.length - start - end;
+        var lcs = null;
+        if ((n + 1) * (m + 1) <= MAX_DIFF_CELLS) {
+            lcs = [];
+            for (var i = 0; i <= n; i++) lcs.push(new Uint32Array(m + 1));
+            for (var i = n - 1; i >= 0; i--) {
+                for (var j = m - 1; j >= 0; j--) {
+                    lcs[i][j] = oldKeys[start + i] === newKeys[start + j] ? lcs[i + 1][j + 1] + 1 : Math.max(lcs[i + 1][j], lcs[i][j + 1]);
+                }
+            }
+        }
+        var inserted = [], gap = [], removed = 0;
+        function flush() {
+            gap.forEach(function(node) { inserted.push({ node: node, modified: removed > 0 }); });
+            gap = [];
+            removed = 0;
+        }
+        var i = 0, j = 0;
+        while (i < n || j < m) {
+            var oldNode = oldNodes[start + i], newNode = newNodes[start + j];
+            if (lcs && i < n && j < m && oldKeys[start + i] === newKeys[start + j]) {
+                flush();
+                i++; j++;
+            } else if (j < m && (i === n || !lcs || lcs[i][j + 1] >= lcs[i + 1][j])) {
+                // Before the next old node, or the unchanged tail
+                parent.insertBefore(newNode, oldNode || null);
+                gap.push(newNode);
+                j++;
+            } else {
+                parent.removeChild(oldNode);
+                removed++;
+                i++;
+            }
+        }
+        flush();
+        return inserted;
+    }
+
+    function clearChanges() {
+        document.querySelectorAll('.content .mdr-changed').forEach(function(el) {
+            el.classList.remove('mdr-changed', 'mdr-inserted', 'mdr-modified');
+            if (!el.getAttribute('class')) el.removeAttribute('class');
+        });
+    }
+
+    window.mdrUpdate = function(contentHtml, tocHtml, markChanges) {
+        clearChanges();
+        var content = document.querySelector('.content');
+        var wasAtBottom = atBottom();
+        var anchor = viewportAnchor(content);
+        var inserted = morphChildren(content, contentHtml);
+        document.querySelector('.sidebar > ul').innerHTML = tocHtml;
+        restoreAnchor(content, anchor);
+        if (markChanges !== false) inserted.forEach(function(change) {
+            if (change.node.nodeType !== 1) return;
+            change.node.classList.add('mdr-changed', change.modified ? 'mdr-modified' : 'mdr-inserted');
+        });
+        clearTimeout(clearTimer);
+        clearTimer = setTimeout(clearChanges, CHANGE_MARK_MS);
+        if (window.mdrFollow && markChanges !== false) {
+            var first = inserted.filter(function(change) { return change.node.nodeType === 1; })[0];
+            if (wasAtBottom) scrollToBottom();
+            else if (first) first.node.scrollIntoView({ block: 'start' });
+        }
+        if (window.mermaid && mermaid.run) {
+            var diagrams = [];
+            inserted.forEach(function(change) {
+                if (change.node.querySelectorAll) diagrams = diagrams.concat(Array.prototype.slice.call(change.node.querySelectorAll('.mermaid')));
+            });
+            if (diagrams.length) mermaid.run({ nodes: diagrams });
+        }
+        return inserted;
+    };
+
+    document.addEventListener('keydown', function(e) {
+        if (e.target && (e.target.tagName === 'INPUT' || e.target.tagName === 'TEXTAREA')) return;
+        if (e.key === 'Escape') { clearChanges(); return; }
+        if (e.key === 'F' && !e.ctrlKey && !e.metaKey && !e.altKey) { setFollow(!window.mdrFollow); return; }
+        if (e.key !== 'c' || e.ctrlKey || e.metaKey || e.altKey) return;
+        var changed = document.querySelectorAll('.content .mdr-changed');
+        if (!changed.length) return;
+        var next = Array.prototype.find.call(changed, function(el) { return el.getBoundingClientRect().top > 1; }) || changed[0];
+        next.scrollIntoView({ behavior: 'smooth', block: 'start' });
+        clearTimeout(clearTimer);
+        clearTimer = setTimeout(clearChanges, CHANGE_MARK_MS);
+    });
+})();
+</script>"#;
+
+/// Start a page built by [`build_html`] in follow mode.
+pub fn enable_follow(html: &str) -> String {
+    html.replacen("<body>", "<body>\n<script>window.mdrFollow = true;</script>", 1)
+}
+
+/// Mermaid.js embedded at compile time — only injected when the Rust renderer fails.
+const MERMAID_JS: &str = include_str!("../../assets/mermaid.min.js");
+
+pub fn build_html(body: &str, toc_entries: &[toc::TocEntry], title: &str) -> String {
+    let toc_html = build_toc_html(toc_entries);
+    // Only include mermaid.js if there are fallback blocks that need JS rendering
+    let mermaid_script = if body.contains(r#"class="mermaid""#) {
+        format!(
+            r#"<script>{}</script>
+<script>mermaid.initialize({{ startOnLoad: true, theme: (window.matchMedia && window.matchMedia('(prefers-color-scheme: dark)').matches) ? 'dark' : 'default' }});</script>"#,
+            MERMAID_JS
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<meta http-equiv="Content-Security-Policy" content="default-src 'none'; style-src 'unsafe-inline'; script-src 'unsafe-inline'; img-src data:;">
+<style>{css}</style>
+</head>
+<body>
+<nav class="sidebar">
+<p class="sidebar-title">Table of Contents</p>
+<ul>{toc}</ul>
+</nav>
+<div class="content">
+{body}
+</div>
+<script>
+document.querySelector('.sidebar').addEventListener('click', function(e) {{
+    if (e.target.tagName === 'A') {{
+        e.preventDefault();
+        var id = e.target.getAttribute('href').substring(1);
+        var el = document.getElementById(id);
+        if (el) {{
+            el.scrollIntoView({{ behavior: 'smooth', block: 'start' }});
+            document.querySelectorAll('.sidebar a').forEach(a => a.classList.remove('active'));
+            e.target.classList.add('active');
+        }}
+    }}
+}});
+</script>
+<div class="search-bar" id="searchBar" style="display:none;">
+    <input type="text" id="searchInput" placeholder="Search..." />
+    <span class="search-info" id="searchInfo">0/0</span>
+    <button onclick="searchNav(-1)">&#9650;</button>
+    <button onclick="searchNav(1)">&#9660;</button>
+    <button class="close-btn" onclick="closeSearch()">Esc</button>
+</div>
+<script>
+(function() {{
+    var matches = [];
+    var currentIdx = -1;
+
+    function clearHighlights() {{
+        document.querySelectorAll('mark.search-highlight').forEach(function(m) {{
+            var parent = m.parentNode;
+            parent.replaceChild(document.createTextNode(m.textContent), m);
+            parent.normalize();
+        }});
+        matches = [];
+        currentIdx = -1;
+    }}
+
+    function highlightMatches(query) {{
+        clearHighlights();
+        if (!query) {{ updateInfo(); return; }}
+        var walker = document.createTreeWalker(
+            document.querySelector('.content'),
+            NodeFilter.SHOW_TEXT, null, false
+        );
+        var textNodes = [];
+        while (walker.nextNode()) textNodes.push(walker.currentNode);
+
+        var queryLower = query.toLowerCase();
+        for (var i = textNodes.length - 1; i >= 0; i--) {{
+            var node = textNodes[i];
+            var text = node.textContent;
+            var textLower = text.toLowerCase();
+            var idx = textLower.lastIndexOf(queryLower);
+            while (idx >= 0) {{
+                var range = document.createRange();
+                range.setStart(node, idx);
+                range.setEnd(node, idx + query.length);
+                var mark = document.createElement('mark');
+                mark.className = 'search-highlight';
+                range.surroundContents(mark);
+                node = mark.previousSibling || node.parentNode.firstChild;
+                idx = idx > 0 ? node.textContent.toLowerCase().lastIndexOf(queryLower, idx - 1) : -1;
+            }}
+        }}
+        matches = document.querySelectorAll('mark.search-highlight');
+        if (matches.length > 0) {{ currentIdx = 0; goToCurrent(); }}
+        updateInfo();
+    }}
+
+    function goToCurrent() {{
+        document.querySelectorAll('mark.search-highlight.current').forEach(function(m) {{ m.classList.remove('current'); }});
+        if (matches.length > 0 && currentIdx >= 0) {{
+            matches[currentIdx].classList.add('current');
+            matches[currentIdx].scrollIntoView({{ behavior: 'smooth', block: 'center' }});
+        }}
+    }}
+
+    function updateInfo() {{
+        var info = document.getElementById('searchInfo');
+        if (matches.length === 0) {{ info.textContent = '0/0'; }}
+        else {{ info.textContent = (currentIdx + 1) + '/' + matches.length; }}
+    }}
+
+    window.searchNav = function(dir) {{
+        if (matches.length === 0) return;
+        currentIdx = (currentIdx + dir + matches.length) % matches.length;
+        goToCurrent();
+        updateInfo();
+    }};
+
+    window.closeSearch = function() {{
+        document.getElementById('searchBar').style.display = 'none';
+        clearHighlights();
+        updateInfo();
+    }};
+
+    document.addEventListener('keydown', function(e) {{
+        if ((e.ctrlKey || e.metaKey) && e.key === 'f') {{
+            e.preventDefault();
+            var bar = document.getElementById('searchBar');
+            bar.style.display = 'flex';
+            var input = document.getElementById('searchInput');
+            input.focus();
+            input.select();
+        }}
+        if (e.key === 'Escape') {{
+            window.closeSearch();
+        }}
+        if (e.key === 'Enter' && document.activeElement === document.getElementById('searchInput')) {{
+            e.preventDefault();
+            if (e.shiftKey) {{ window.searchNav(-1); }}
+            else {{ window.searchNav(1); }}
+        }}
+    }});
+
+    document.getElementById('searchInput').addEventListener('input', function() {{
+        highlightMatches(this.value);
+    }});
+}})();
+</script>
+{live_update}
+{mermaid_script}
+</body>
+</html>"#,
+        title = html_encode(title),
+        css = GITHUB_CSS,
+        toc = toc_html,
+        body = body,
+        live_update = LIVE_UPDATE_JS,
+        mermaid_script = mermaid_script
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn front_matter_html_lists_fields_escaped() {
+        let front_matter = FrontMatter {
+            title: Some("Ignored".to_string()),
+            fields: vec![("tags".to_string(), "<a>, b".to_string())],
+            lines: 4,
+        };
+        let html = front_matter_html(Some(&front_matter));
+        assert!(html.starts_with("<details class=\"front-matter\">"));
+        assert!(html.contains("<tr><th>tags</th><td>&lt;a&gt;, b</td></tr>"));
+        assert!(!html.contains("Ignored"));
+        assert_eq!(front_matter_html(None), "");
+    }
+
+    #[test]
+    fn build_html_includes_live_update_function() {
+        let html = build_html("<p>hi</p>", &[], "doc");
+        assert!(html.contains("window.mdrUpdate = function(contentHtml, tocHtml, markChanges)"));
+        // Placed before the closing body tag, after the content it updates
+        assert!(html.find("window.mdrUpdate").unwrap() > html.find(r#"<div class="content">"#).unwrap());
+    }
+
+    #[test]
+    fn enable_follow_sets_flag_before_page_scripts() {
+        let html = enable_follow(&build_html("<p>hi</p>", &[], "doc"));
+        let flag = html.find("window.mdrFollow = true;").unwrap();
+        assert!(flag < html.find("setFollow(!!window.mdrFollow)").unwrap());
+    }
+
+    #[cfg(feature = "webview-backend")]
+    #[test]
+    fn file_tree_html_marks_current_file_and_escapes_names() {
+        let entries = vec![
+            TreeEntry { depth: 0, name: "docs".to_string(), path: None },
+            TreeEntry { depth: 1, name: "a&b.md".to_string(), path: Some("/w/docs/a&b.md".into()) },
+            TreeEntry { depth: 1, name: "c.md".to_string(), path: Some("/w/docs/c.md".into()) },
+        ];
+        let html = build_file_tree_html(&entries, std::path::Path::new("/w/docs/c.md"));
+        assert!(html.contains(r#"<li class="tree-dir" style="padding-left: 16px">docs/</li>"#));
+        assert!(html.contains(r#"data-path="/w/docs/a&amp;b.md">a&amp;b.md</span>"#));
+        assert!(html.contains(r#"class="tree-file active" style="padding-left: 28px" data-path="/w/docs/c.md""#));
+    }
+
+    #[test]
+    fn build_html_does_not_block_clipboard_in_csp() {
+        let toc = vec![];
+        let html = build_html("<p>Hello</p>", &toc, "Hello");
+        // CSP must NOT block clipboard API — it should either omit clipboard restrictions
+        // or not have a restrictive default-src that prevents copy operations
+        // The key is that the webview's native copy (Cmd+C/Ctrl+C) works through
+        // the OS menu, not through CSP-gated JavaScript APIs
+        assert!(html.contains("Content-Security-Policy"), "CSP should be present");
+        // Verify CSP doesn't block scripts (needed for search, mermaid, etc.)
+        assert!(html.contains("script-src 'unsafe-inline'"), "Scripts must be allowed for search to work");
+    }
+
+    #[test]
+    fn resolve_local_images_svg_rasterized_to_png() {
+        let dir = std::env::temp_dir().join("mdr_test_webview_svg_raster");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let svg_content = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100"><rect width="100" height="100" fill="red"/></svg>"#;
+        std::fs::write(dir.join("test.svg"), svg_content).unwrap();
+
+        let html = r#"<img src="test.svg" alt="test">"#;
+        let result = resolve_local_images(html, &dir);
+
+        // SVG should be rasterized to PNG data URI (not inlined as raw SVG)
+        assert!(result.contains("data:image/png;base64,"), "SVG should be rasterized to PNG, got: {}", result);
+        assert!(!result.contains("<svg"), "Raw SVG should NOT be inlined (security), got: {}", result);
+        assert!(result.contains("<img"), "Should remain an <img> tag with PNG data URI");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_svg_with_links_is_safe() {
+        // SVGs with <a> tags must NOT be inlined (they cause navigation)
+        let dir = std::env::temp_dir().join("mdr_test_webview_svg_links");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let svg_with_links = r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="100">
+<a href="https://example.com"><rect width="100" height="100" fill="blue"/></a></svg>"#;
+        std::fs::write(dir.join("logo.svg"), svg_with_links).unwrap();
+
+        let html = r#"<img src="logo.svg" alt="logo">"#;
+        let result = resolve_local_images(html, &dir);
+
+        // Must NOT contain raw SVG with links
+        assert!(!result.contains("href=\"https://example.com\""),
+            "SVG links must not leak into page, got: {}", result);
+        assert!(result.contains("data:image/png;base64,"),
+            "Should be rasterized to safe PNG, got: {}", result);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_non_svg_uses_data_uri() {
+        let dir = std::env::temp_dir().join("mdr_test_webview_png_datauri");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let png_path = dir.join("test.png");
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.save(&png_path).unwrap();
+
+        let html = r#"<img src="test.png" alt="pixel">"#;
+        let result = resolve_local_images(html, &dir);
+
+        assert!(result.contains("data:image/png;base64,"), "PNG should use data URI, got: {}", result);
+        assert!(result.contains("<img"), "img tag should be preserved for PNG, got: {}", result);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_preserves_remote_urls() {
+        let dir = std::env::temp_dir();
+        let html = r#"<img src="https://example.com/image.svg" alt="remote">"#;
+        let result = resolve_local_images(html, &dir);
+        assert_eq!(result, html, "Remote URLs should be preserved unchanged");
+    }
+
+    #[test]
+    fn resolve_local_images_subdirectory_paths() {
+        // Simulate the real-world scenario: images in subdirectories
+        let dir = std::env::temp_dir().join("mdr_test_webview_subdir");
+        let img_dir = dir.join("assets").join("screenshots");
+        std::fs::create_dir_all(&img_dir).unwrap();
+
+        // Create a real PNG file in subdirectory
+        let png_path = img_dir.join("chart.png");
+        let mut img = image::RgbaImage::new(2, 2);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.save(&png_path).unwrap();
+
+        // This is what comrak generates from ![alt](assets/screenshots/chart.png)
+        let html = r#"<img src="assets/screenshots/chart.png" alt="Revenue chart" />"#;
+        let result = resolve_local_images(html, &dir);
+
+        assert!(result.contains("data:image/png;base64,"),
+            "PNG in subdirectory should be resolved to data URI, got: {}",
+            &result[..result.len().min(200)]);
+        assert!(result.contains("<img"), "Should still be an img tag");
+        assert!(result.contains("alt=\"Revenue chart\""), "Alt text should be preserved");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_empty_base_dir() {
+        // When file_path.parent() is empty (bare filename), base_dir is ""
+        // This should still work for files that exist relative to CWD
+        let dir = std::env::temp_dir().join("mdr_test_webview_empty_base");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let png_path = dir.join("test.png");
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([0, 255, 0, 255]));
+        img.save(&png_path).unwrap();
+
+        // With proper base_dir, it should work
+        let html = r#"<img src="test.png" alt="test" />"#;
+        let result = resolve_local_images(html, &dir);
+        assert!(result.contains("data:image/png;base64,"),
+            "Should resolve with proper base_dir, got: {}", &result[..result.len().min(200)]);
+
+        // With empty base_dir, the file won't be found (unless CWD happens to match)
+        let empty = std::path::PathBuf::from("");
+        let result2 = resolve_local_images(html, &empty);
+        // This will likely NOT find the file since CWD != dir
+        // The tag should be returned unchanged
+        assert!(result2.contains("src=\"test.png\"") || result2.contains("data:image/png;base64,"),
+            "With empty base_dir, should either find file or return original, got: {}",
+            &result2[..result2.len().min(200)]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_comrak_output_format() {
+        // Test with the exact HTML format comrak produces from markdown images
+        let dir = std::env::temp_dir().join("mdr_test_webview_comrak_format");
+        let screenshots_dir = dir.join("assets").join("screenshots");
+        std::fs::create_dir_all(&screenshots_dir).unwrap();
+
+        let png_path = screenshots_dir.join("revenue.png");
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([0, 0, 255, 255]));
+        img.save(&png_path).unwrap();
+
+        // Comrak generates self-closing tags with alt attribute
+        let html = r#"<p><img src="assets/screenshots/revenue.png" alt="Monthly Revenue Growth — Jan 2023 to Feb 2026" /></p>"#;
+        let result = resolve_local_images(html, &dir);
+
+        assert!(result.contains("data:image/png;base64,"),
+            "Comrak-style img tag should be resolved, got: {}", &result[..result.len().min(300)]);
+        assert!(result.contains("alt=\"Monthly Revenue Growth"), "Alt text with special chars should be preserved");
+        assert!(result.contains("<p>") && result.contains("</p>"), "Surrounding <p> tags should be preserved");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_multiple_images_in_html() {
+        // Test multiple images in a single HTML string
+        let dir = std::env::temp_dir().join("mdr_test_webview_multi_img");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for name in &["a.png", "b.png"] {
+            let path = dir.join(name);
+            let mut img = image::RgbaImage::new(1, 1);
+            img.put_pixel(0, 0, image::Rgba([128, 128, 128, 255]));
+            img.save(&path).unwrap();
+        }
+
+        let html = r#"<p><img src="a.png" alt="A" /></p><p><img src="b.png" alt="B" /></p>"#;
+        let result = resolve_local_images(html, &dir);
+
+        // Both images should be resolved
+        let count = result.matches("data:image/png;base64,").count();
+        assert_eq!(count, 2, "Both images should be resolved to data URIs, got {} matches in: {}", count, &result[..result.len().min(300)]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rasterize_svg_to_png_data_uri_basic() {
+        let dir = std::env::temp_dir().join("mdr_test_rasterize_svg");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let svg = r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" width="50" height="50"><circle cx="25" cy="25" r="20" fill="blue"/></svg>"#;
+        let path = dir.join("test.svg");
+        std::fs::write(&path, svg).unwrap();
+
+        let result = svg_file_to_png_data_uri(&path).unwrap();
+        assert!(result.starts_with("data:image/png;base64,"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolve_local_images_blocks_path_traversal() {
+        let dir = std::env::temp_dir().join("mdr_test_webview_traversal");
+        let subdir = dir.join("docs");
+        std::fs::create_dir_all(&subdir).unwrap();
+
+        // Create a file OUTSIDE the subdir (in parent)
+        let mut img = image::RgbaImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.save(dir.join("secret.png")).unwrap();
+
+        // Try to access it via path traversal from subdir
+        let html = r#"<img src="../secret.png" alt="secret">"#;
+        let result = resolve_local_images(html, &subdir);
+
+        // Should NOT resolve to data URI — the path escapes subdir
+        assert!(!result.contains("data:image/png;base64,"),
+            "Path traversal should be blocked, got: {}", &result[..result.len().min(200)]);
+        assert!(result.contains("src=\"../secret.png\""),
+            "Original src should be preserved when blocked");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
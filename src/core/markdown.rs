@@ -1,21 +1,76 @@
-use comrak::{markdown_to_html, Options};
-use crate::core::mermaid::process_mermaid_blocks;
+#[cfg(any(test, feature = "egui-backend", feature = "tui-backend"))]
+use comrak::nodes::AlertType;
+use comrak::{format_html, Arena, Options};
+use crate::core::ast::Node;
+use crate::core::math::process_math;
+use crate::core::mermaid::{process_mermaid_blocks, Pending};
 
-/// Convert markdown content to HTML with all GFM extensions enabled.
-/// Processes mermaid code blocks into inline SVG diagrams.
-/// Adds id attributes to headings for TOC anchor navigation.
-pub fn parse_markdown(content: &str) -> String {
+/// Comrak options shared by every consumer of the Markdown AST (HTML rendering,
+/// TOC extraction, TUI rendering) so they all agree on what the document is.
+pub fn comrak_options() -> Options<'static> {
     let mut options = Options::default();
     options.extension.strikethrough = true;
     options.extension.table = true;
     options.extension.autolink = true;
     options.extension.tasklist = true;
     options.extension.footnotes = true;
+    options.extension.math_dollars = true;
+    options.extension.math_code = true;
+    options.extension.alerts = true;
     options.render.r#unsafe = true;
+    options
+}
+
+/// How a GitHub alert (`> [!NOTE]` and friends) is labelled and coloured.
+/// GITHUB_CSS uses the same icons and colours for the HTML backends.
+#[cfg(any(test, feature = "egui-backend", feature = "tui-backend"))]
+pub struct AlertStyle {
+    pub label: &'static str,
+    pub icon: char,
+    /// Accent colour on light and dark backgrounds.
+    pub light: [u8; 3],
+    pub dark: [u8; 3],
+}
+
+#[cfg(feature = "egui-backend")]
+pub const ALERT_TYPES: [AlertType; 5] =
+    [AlertType::Note, AlertType::Tip, AlertType::Important, AlertType::Warning, AlertType::Caution];
+
+#[cfg(any(test, feature = "egui-backend", feature = "tui-backend"))]
+pub fn alert_style(alert_type: AlertType) -> AlertStyle {
+    let (label, icon, light, dark) = match alert_type {
+        AlertType::Note => ("Note", 'ℹ', [0x09, 0x69, 0xda], [0x44, 0x93, 0xf8]),
+        AlertType::Tip => ("Tip", '💡', [0x1a, 0x7f, 0x37], [0x3f, 0xb9, 0x50]),
+        AlertType::Important => ("Important", '💬', [0x82, 0x50, 0xdf], [0xab, 0x7d, 0xf8]),
+        AlertType::Warning => ("Warning", '⚠', [0x9a, 0x67, 0x00], [0xd2, 0x99, 0x22]),
+        AlertType::Caution => ("Caution", '⛔', [0xd1, 0x24, 0x2f], [0xf8, 0x51, 0x49]),
+    };
+    AlertStyle { label, icon, light, dark }
+}
+
+/// Convert a parsed document to HTML with all GFM extensions enabled.
+/// Processes mermaid code blocks and math into inline SVG.
+/// Adds id attributes to headings for TOC anchor navigation.
+pub fn render_markdown(root: &Node) -> String {
+    render(root, None)
+}
 
-    let html = markdown_to_html(content, &options);
+/// Like [`render_markdown`], but diagrams that are not rendered yet become a
+/// placeholder and are added to `pending` instead of being waited for.
+#[cfg(feature = "webview-backend")]
+pub fn render_markdown_deferred(root: &Node, pending: &mut Pending) -> String {
+    render(root, Some(pending))
+}
+
+fn render(root: &Node, pending: Option<&mut Pending>) -> String {
+    let arena = Arena::new();
+    let mut html = String::new();
+    // Formatting into a String cannot fail
+    let root = root.to_comrak(&arena);
+    process_math(root);
+    let _ = format_html(root, &comrak_options(), &mut html);
     let html = add_heading_ids(&html);
-    process_mermaid_blocks(&html)
+    process_mermaid_blocks(&html, pending)
 }
 
 /// Add id attributes to heading tags for anchor navigation.
@@ -50,6 +105,108 @@ fn slugify(text: &str) -> String {
         .join("")
 }
 
+/// Metadata from a document's front matter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    /// The other keys in document order, with values flattened for display.
+    pub fields: Vec<(String, String)>,
+    /// Source lines the block spans, delimiters included.
+    pub lines: usize,
+}
+
+/// Parse front matter at the very start of a document: YAML between `---`
+/// lines or TOML between `+++` lines. Returns `None` if there is none (a
+/// block that is not a key/value mapping is taken for ordinary Markdown, such
+/// as a thematic break), and an error if it is a mapping that fails to parse.
+pub fn parse_front_matter(content: &str) -> Option<Result<FrontMatter, String>> {
+    let mut lines = content.lines();
+    let first = lines.next()?.trim_end();
+    let toml = match first {
+        "---" => false,
+        "+++" => true,
+        _ => return None,
+    };
+    let mut block = String::new();
+    let mut count = 1;
+    loop {
+        let line = lines.next()?;
+        count += 1;
+        let delimiter = line.trim_end();
+        if delimiter == first || (!toml && delimiter == "...") {
+            break;
+        }
+        block.push_str(line);
+        block.push('\n');
+    }
+
+    let fields = if toml { toml_fields(&block) } else { yaml_fields(&block) }?;
+    Some(fields.map(|fields| {
+        let mut front_matter = FrontMatter { lines: count, ..FrontMatter::default() };
+        for (key, value) in fields {
+            if key == "title" && front_matter.title.is_none() {
+                front_matter.title = Some(value);
+            } else {
+                front_matter.fields.push((key, value));
+            }
+        }
+        front_matter
+    }))
+}
+
+/// `content` with its first `lines` lines emptied, so that the front matter
+/// is not rendered while line numbers still match the source.
+pub fn blank_front_matter(content: &str, lines: usize) -> String {
+    let rest = content.split_inclusive('\n').skip(lines).collect::<String>();
+    "\n".repeat(lines) + &rest
+}
+
+fn yaml_fields(block: &str) -> Option<Result<Vec<(String, String)>, String>> {
+    use yaml_rust2::{Yaml, YamlLoader};
+    fn display(value: &Yaml) -> String {
+        match value {
+            Yaml::String(s) | Yaml::Real(s) => s.clone(),
+            Yaml::Integer(i) => i.to_string(),
+            Yaml::Boolean(b) => b.to_string(),
+            Yaml::Array(items) => items.iter().map(display).collect::<Vec<_>>().join(", "),
+            Yaml::Hash(hash) => hash.iter()
+                .map(|(k, v)| format!("{}: {}", display(k), display(v)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Yaml::Null | Yaml::Alias(_) | Yaml::BadValue => String::new(),
+        }
+    }
+
+    let docs = match YamlLoader::load_from_str(block) {
+        Ok(docs) => docs,
+        Err(e) => return Some(Err(format!("invalid YAML front matter: {}", e))),
+    };
+    match docs.into_iter().next() {
+        // An empty block is empty metadata
+        None => Some(Ok(Vec::new())),
+        Some(Yaml::Hash(hash)) => Some(Ok(hash.iter().map(|(k, v)| (display(k), display(v))).collect())),
+        Some(_) => None,
+    }
+}
+
+fn toml_fields(block: &str) -> Option<Result<Vec<(String, String)>, String>> {
+    fn display(value: &toml::Value) -> String {
+        match value {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Array(items) => items.iter().map(display).collect::<Vec<_>>().join(", "),
+            toml::Value::Table(table) => table.iter()
+                .map(|(k, v)| format!("{}: {}", k, display(v)))
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => other.to_string(),
+        }
+    }
+
+    Some(block.parse::<toml::Table>()
+        .map(|table| table.iter().map(|(k, v)| (k.clone(), display(v))).collect())
+        .map_err(|e| format!("invalid TOML front matter: {}", e.message())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,6 +245,37 @@ mod tests {
         assert_eq!(result, html);
     }
 
+    // --- front matter tests ---
+
+    #[test]
+    fn front_matter_yaml_and_toml() {
+        let md = "---\ntitle: Release notes\ntags: [a, b]\ndraft: true\n---\n# Body\n";
+        let front_matter = parse_front_matter(md).unwrap().unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Release notes"));
+        assert_eq!(front_matter.fields, vec![
+            ("tags".to_string(), "a, b".to_string()),
+            ("draft".to_string(), "true".to_string()),
+        ]);
+        assert_eq!(front_matter.lines, 5);
+        assert_eq!(blank_front_matter(md, front_matter.lines), "\n\n\n\n\n# Body\n");
+
+        let md = "+++\ntitle = \"Hugo\"\nweight = 3\n+++\nText\n";
+        let front_matter = parse_front_matter(md).unwrap().unwrap();
+        assert_eq!(front_matter.title.as_deref(), Some("Hugo"));
+        assert_eq!(front_matter.fields, vec![("weight".to_string(), "3".to_string())]);
+    }
+
+    #[test]
+    fn front_matter_requires_a_mapping_at_the_start() {
+        assert!(parse_front_matter("# Title\n---\na: 1\n---\n").is_none());
+        // Thematic breaks around plain text are not front matter
+        assert!(parse_front_matter("---\nJust text\n---\n").is_none());
+        // Unterminated
+        assert!(parse_front_matter("---\na: 1\n").is_none());
+        assert!(parse_front_matter("---\na: [1\n---\n").unwrap().is_err());
+        assert!(parse_front_matter("+++\na = \n+++\n").unwrap().is_err());
+    }
+
     // --- strip_html_tags tests ---
 
     #[test]
@@ -97,7 +285,11 @@ mod tests {
         assert_eq!(strip_html_tags("<a href=\"#\">link</a>"), "link");
     }
 
-    // --- parse_markdown integration tests ---
+    // --- render_markdown integration tests ---
+
+    fn parse_markdown(content: &str) -> String {
+        render_markdown(&Node::parse(content))
+    }
 
     #[test]
     fn parse_markdown_basic_paragraph() {
@@ -137,6 +329,27 @@ mod tests {
         assert!(result.contains("checkbox"));
     }
 
+    #[test]
+    fn parse_markdown_alerts() {
+        let result = parse_markdown("> [!CAUTION]\n> Hot.\n\n> [!NOTE] Read me\n> Text.\n");
+        assert!(result.contains(r#"<div class="markdown-alert markdown-alert-caution">"#));
+        assert!(result.contains(r#"<p class="markdown-alert-title">Caution</p>"#));
+        assert!(result.contains(r#"<p class="markdown-alert-title">Read me</p>"#));
+        assert!(!result.contains("[!"));
+    }
+
+    #[test]
+    fn alert_styles_match_github_css() {
+        for alert_type in [AlertType::Note, AlertType::Tip, AlertType::Important, AlertType::Warning, AlertType::Caution] {
+            let style = alert_style(alert_type);
+            let var = style.label.to_lowercase();
+            for [r, g, b] in [style.light, style.dark] {
+                assert!(GITHUB_CSS.contains(&format!("--{}: #{:02x}{:02x}{:02x};", var, r, g, b)), "{} colour", var);
+            }
+            assert!(GITHUB_CSS.contains(&format!(".markdown-alert-{} .markdown-alert-title::before {{ content: \"{}\"; }}", var, style.icon)));
+        }
+    }
+
     #[test]
     fn parse_markdown_strikethrough() {
         let md = "This is ~~deleted~~ text.";
@@ -159,6 +372,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_markdown_renders_math() {
+        let md = "Euler: $e^{i\\pi} + 1 = 0$ costs $5, not $$\\sum_i x_i$$.\n\n```math\n\\frac{a}{b}\n```\n";
+        let result = parse_markdown(md);
+        assert!(result.contains("math-inline"));
+        assert_eq!(result.matches("math-display").count(), 2);
+        assert!(result.contains("costs $5"));
+    }
+
     #[test]
     fn parse_markdown_empty_input() {
         let result = parse_markdown("");
@@ -206,10 +428,10 @@ mod tests {
 /// CSS for GitHub-like markdown rendering with dark/light theme support.
 pub const GITHUB_CSS: &str = r#"
 @media (prefers-color-scheme: dark) {
-    :root { --bg: #0d1117; --fg: #e6edf3; --code-bg: #161b22; --border: #30363d; --link: #58a6ff; --blockquote: #8b949e; --sidebar-bg: #010409; --sidebar-hover: #161b22; --sidebar-active: #1f6feb33; }
+    :root { --bg: #0d1117; --fg: #e6edf3; --code-bg: #161b22; --border: #30363d; --link: #58a6ff; --blockquote: #8b949e; --sidebar-bg: #010409; --sidebar-hover: #161b22; --sidebar-active: #1f6feb33; --note: #4493f8; --tip: #3fb950; --important: #ab7df8; --warning: #d29922; --caution: #f85149; }
 }
 @media (prefers-color-scheme: light) {
-    :root { --bg: #ffffff; --fg: #1f2328; --code-bg: #f6f8fa; --border: #d0d7de; --link: #0969da; --blockquote: #656d76; --sidebar-bg: #f6f8fa; --sidebar-hover: #eaeef2; --sidebar-active: #ddf4ff; }
+    :root { --bg: #ffffff; --fg: #1f2328; --code-bg: #f6f8fa; --border: #d0d7de; --link: #0969da; --blockquote: #656d76; --sidebar-bg: #f6f8fa; --sidebar-hover: #eaeef2; --sidebar-active: #ddf4ff; --note: #0969da; --tip: #1a7f37; --important: #8250df; --warning: #9a6700; --caution: #d1242f; }
 }
 * { box-sizing: border-box; }
 html, body { margin: 0; padding: 0; height: 100%; }
@@ -258,6 +480,11 @@ body {
 .sidebar li.toc-h3 a { padding-left: 36px; font-size: 13px; }
 .sidebar li.toc-h4 a { padding-left: 48px; font-size: 13px; color: var(--blockquote); }
 .sidebar li.toc-h5 a, .sidebar li.toc-h6 a { padding-left: 56px; font-size: 12px; color: var(--blockquote); }
+.sidebar-files { margin-bottom: 12px; padding-bottom: 8px; border-bottom: 1px solid var(--border); }
+.sidebar li.tree-dir { padding: 4px 16px; color: var(--blockquote); font-weight: 600; }
+.sidebar .tree-file { display: block; padding: 4px 16px; cursor: pointer; border-left: 3px solid transparent; }
+.sidebar .tree-file:hover { background: var(--sidebar-hover); }
+.sidebar .tree-file.active { background: var(--sidebar-active); border-left-color: var(--link); color: var(--link); }
 .content {
     margin-left: 250px;
     max-width: 900px;
@@ -291,6 +518,20 @@ blockquote {
     padding: 0 16px;
     margin: 16px 0;
 }
+.markdown-alert { padding: 8px 16px; margin: 16px 0; border-left: 4px solid var(--alert); }
+.markdown-alert > :last-child { margin-bottom: 0; }
+.markdown-alert-title { margin: 0 0 8px; font-weight: 600; color: var(--alert); }
+.markdown-alert-title::before { margin-right: 8px; }
+.markdown-alert-note { --alert: var(--note); }
+.markdown-alert-note .markdown-alert-title::before { content: "ℹ"; }
+.markdown-alert-tip { --alert: var(--tip); }
+.markdown-alert-tip .markdown-alert-title::before { content: "💡"; }
+.markdown-alert-important { --alert: var(--important); }
+.markdown-alert-important .markdown-alert-title::before { content: "💬"; }
+.markdown-alert-warning { --alert: var(--warning); }
+.markdown-alert-warning .markdown-alert-title::before { content: "⚠"; }
+.markdown-alert-caution { --alert: var(--caution); }
+.markdown-alert-caution .markdown-alert-title::before { content: "⛔"; }
 a { color: var(--link); text-decoration: none; }
 a:hover { text-decoration: underline; }
 hr { border: none; border-top: 1px solid var(--border); margin: 24px 0; }
@@ -299,6 +540,11 @@ ul, ol { padding-left: 2em; }
 input[type="checkbox"] { margin-right: 0.5em; }
 .mermaid-diagram { text-align: center; margin: 16px 0; }
 .mermaid-diagram svg { max-width: 100%; height: auto; }
+.mermaid-diagram .mermaid-dark { display: none; }
+@media (prefers-color-scheme: dark) {
+    .mermaid-diagram .mermaid-light { display: none; }
+    .mermaid-diagram .mermaid-dark { display: block; }
+}
 .mermaid-error {
     border: 2px solid #f85149;
     border-radius: 6px;
@@ -307,6 +553,9 @@ input[type="checkbox"] { margin-right: 0.5em; }
     background: var(--code-bg);
 }
 .mermaid-error strong { color: #f85149; }
+.mermaid-error pre { margin: 8px 0 0; }
+.mermaid-error-line { display: inline-block; width: 100%; background: rgba(248, 81, 73, 0.15); }
+.mermaid-error-caret { color: #f85149; font-weight: 600; }
 .mermaid-fallback {
     border: 1px solid var(--border);
     border-radius: 6px;
@@ -325,6 +574,64 @@ input[type="checkbox"] { margin-right: 0.5em; }
 .mermaid-icon { margin-right: 6px; }
 .mermaid-fallback pre { margin: 0; border-radius: 0; }
 .mermaid-fallback code { font-size: 13px; color: var(--fg); }
+.mermaid-pending { opacity: 0.7; }
+.mdr-menu {
+    position: fixed;
+    z-index: 1000;
+    display: flex;
+    flex-direction: column;
+    padding: 4px;
+    background: var(--bg);
+    border: 1px solid var(--border);
+    border-radius: 6px;
+    box-shadow: 0 4px 12px rgba(0, 0, 0, 0.25);
+}
+.mdr-menu button {
+    padding: 4px 12px;
+    border: none;
+    border-radius: 4px;
+    background: none;
+    color: var(--fg);
+    font: inherit;
+    font-size: 14px;
+    text-align: left;
+    cursor: pointer;
+}
+.mdr-menu button:hover { background: var(--code-bg); }
+.mdr-toast {
+    position: fixed;
+    bottom: 16px;
+    right: 16px;
+    z-index: 1000;
+    padding: 8px 12px;
+    background: var(--code-bg);
+    border: 1px solid var(--border);
+    border-radius: 6px;
+    font-size: 14px;
+}
+.math-display { display: block; margin: 16px 0; text-align: center; overflow-x: auto; }
+.math-error { color: #f85149; }
+/* Front matter metadata panel */
+.front-matter { margin: 0 0 16px; font-size: 14px; color: var(--blockquote); }
+.front-matter summary { cursor: pointer; font-weight: 600; }
+.front-matter table { width: auto; margin: 8px 0 0; }
+.front-matter th { text-align: left; }
+/* Blocks changed by the last live reload */
+.content .mdr-inserted { background: rgba(46, 160, 67, 0.15); box-shadow: -6px 0 0 rgba(46, 160, 67, 0.7); }
+.content .mdr-modified { background: rgba(210, 153, 34, 0.15); box-shadow: -6px 0 0 rgba(210, 153, 34, 0.7); }
+body.mdr-follow::after {
+    content: "FOLLOW";
+    position: fixed;
+    top: 8px;
+    right: 12px;
+    padding: 2px 8px;
+    font-size: 11px;
+    font-weight: 600;
+    letter-spacing: 0.5px;
+    color: var(--bg);
+    background: var(--link);
+    border-radius: 4px;
+}
 /* Search */
 .search-bar {
     position: fixed;
@@ -0,0 +1,629 @@
+//! Box layout of parsed math, loosely following TeX's rules, emitted as SVG.
+//!
+//! All lengths are in em of the base font size until [`to_svg`] scales them
+//! to pixels. Glyph metrics are estimates, so text is drawn centered in (or
+//! stretched to) the width the layout gave it, which keeps the result stable
+//! whichever font the renderer picks.
+
+use super::parse::{parse, Class, Font, Node};
+use crate::core::mermaid::html_encode;
+
+/// Height of the math axis (where fraction bars and `+` sit) above the baseline.
+const AXIS: f32 = 0.25;
+/// Thickness of fraction bars and radicals.
+const RULE: f32 = 0.045;
+const THIN: f32 = 3.0 / 18.0;
+const MEDIUM: f32 = 4.0 / 18.0;
+const THICK: f32 = 5.0 / 18.0;
+/// Space around the rendered formula so strokes at the edges are not clipped.
+const PAD: f32 = 0.08;
+const FONT_FAMILY: &str = "'Latin Modern Math', 'STIX Two Math', 'Cambria Math', 'DejaVu Serif', 'Times New Roman', serif";
+
+/// Math rendered to SVG, with its size in pixels.
+#[derive(Debug, Clone)]
+pub struct Svg {
+    pub markup: String,
+    pub width: f32,
+    pub height: f32,
+    /// Distance from the bottom edge up to the baseline, for aligning inline
+    /// math with the surrounding text.
+    pub depth: f32,
+}
+
+/// Render TeX math to SVG at `font_px` pixels per em, drawn in `color` (any
+/// SVG paint, e.g. `currentColor` or `#24292f`).
+pub fn to_svg(tex: &str, display: bool, font_px: f32, color: &str) -> Result<Svg, String> {
+    let nodes = parse(tex)?;
+    if nodes.is_empty() {
+        return Err("empty formula".to_string());
+    }
+    let style = if display { Style::Display } else { Style::Text };
+    let formula = layout_list(&nodes, style);
+
+    let px = |v: f32| v * font_px;
+    let width = px(formula.width + 2.0 * PAD);
+    let height = px(formula.height + formula.depth + 2.0 * PAD);
+    let (dx, dy) = (PAD, formula.height + PAD);
+    let mut markup = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w:.2}" height="{h:.2}" viewBox="0 0 {w:.2} {h:.2}" fill="{color}">"#,
+        w = width, h = height, color = color,
+    );
+    for item in &formula.items {
+        match item {
+            Item::Glyph { x, y, width, text, size, font } => {
+                let mut attrs = format!(r#"y="{:.2}" font-size="{:.2}" font-family="{}""#, px(y + dy), px(*size), FONT_FAMILY);
+                match font {
+                    Font::Italic => attrs.push_str(r#" font-style="italic""#),
+                    Font::Bold => attrs.push_str(r#" font-weight="bold""#),
+                    Font::Upright => {}
+                }
+                if text.chars().count() == 1 {
+                    attrs.push_str(&format!(r#" x="{:.2}" text-anchor="middle""#, px(x + dx + width / 2.0)));
+                } else {
+                    attrs.push_str(&format!(r#" x="{:.2}" textLength="{:.2}" lengthAdjust="spacingAndGlyphs""#, px(x + dx), px(*width)));
+                }
+                markup.push_str(&format!("<text {}>{}</text>", attrs, html_encode(text)));
+            }
+            Item::Rule { x, y, width, height } => markup.push_str(&format!(
+                r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}"/>"#,
+                px(x + dx), px(y + dy), px(*width), px(*height),
+            )),
+            Item::Path { segments, stroke } => {
+                let d: String = segments.iter()
+                    .map(|(cmd, points)| {
+                        let points: Vec<String> = points.iter()
+                            .map(|(x, y)| format!("{:.2} {:.2}", px(x + dx), px(y + dy)))
+                            .collect();
+                        format!("{}{}", cmd, points.join(" "))
+                    })
+                    .collect();
+                markup.push_str(&format!(
+                    r#"<path d="{}" fill="none" stroke="{}" stroke-width="{:.2}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                    d, color, px(*stroke),
+                ));
+            }
+        }
+    }
+    markup.push_str("</svg>");
+    Ok(Svg { markup, width, height, depth: px(formula.depth + PAD) })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Display,
+    Text,
+    Script,
+    ScriptScript,
+}
+
+impl Style {
+    fn size(self) -> f32 {
+        match self {
+            Style::Display | Style::Text => 1.0,
+            Style::Script => 0.7,
+            Style::ScriptScript => 0.5,
+        }
+    }
+
+    /// Style of superscripts and subscripts.
+    fn script(self) -> Style {
+        match self {
+            Style::Display | Style::Text => Style::Script,
+            _ => Style::ScriptScript,
+        }
+    }
+
+    /// Style of numerators and denominators.
+    fn fraction(self) -> Style {
+        match self {
+            Style::Display => Style::Text,
+            Style::Text => Style::Script,
+            _ => Style::ScriptScript,
+        }
+    }
+}
+
+/// Something drawn, positioned relative to its box's origin: the left end of
+/// the baseline, with y growing downwards as in SVG.
+#[derive(Debug, Clone)]
+enum Item {
+    Glyph { x: f32, y: f32, width: f32, text: String, size: f32, font: Font },
+    Rule { x: f32, y: f32, width: f32, height: f32 },
+    /// A stroked path: SVG commands with their points.
+    Path { segments: Vec<(char, Vec<(f32, f32)>)>, stroke: f32 },
+}
+
+impl Item {
+    fn shifted(mut self, dx: f32, dy: f32) -> Item {
+        match &mut self {
+            Item::Glyph { x, y, .. } | Item::Rule { x, y, .. } => {
+                *x += dx;
+                *y += dy;
+            }
+            Item::Path { segments, .. } => {
+                for (x, y) in segments.iter_mut().flat_map(|(_, points)| points.iter_mut()) {
+                    *x += dx;
+                    *y += dy;
+                }
+            }
+        }
+        self
+    }
+}
+
+/// A laid-out box: `height` above the baseline and `depth` below it.
+#[derive(Debug, Clone, Default)]
+struct MathBox {
+    width: f32,
+    height: f32,
+    depth: f32,
+    items: Vec<Item>,
+}
+
+impl MathBox {
+    fn space(width: f32) -> MathBox {
+        MathBox { width, ..MathBox::default() }
+    }
+
+    /// Place `other` with its origin at (`dx`, `dy`), growing this box's
+    /// height and depth to fit but leaving its width alone.
+    fn place(&mut self, other: MathBox, dx: f32, dy: f32) {
+        self.height = self.height.max(other.height - dy);
+        self.depth = self.depth.max(other.depth + dy);
+        self.items.extend(other.items.into_iter().map(|item| item.shifted(dx, dy)));
+    }
+
+    /// Append `other` to the right, shifted down by `dy`.
+    fn append(&mut self, other: MathBox, dy: f32) {
+        let width = other.width;
+        self.place(other, self.width, dy);
+        self.width += width;
+    }
+}
+
+fn char_width(c: char) -> f32 {
+    match c {
+        'i' | 'j' | 'l' | 'ı' => 0.32,
+        'f' | 't' | 'r' => 0.4,
+        'm' | 'w' => 0.78,
+        'M' | 'W' => 0.95,
+        'I' | 'J' => 0.45,
+        'A'..='Z' => 0.72,
+        'a'..='z' => 0.52,
+        '0'..='9' => 0.5,
+        '.' | ',' | ';' | ':' | '!' | '\'' | '′' | '⋅' | '∘' | '∙' | ' ' | '\u{a0}' => 0.28,
+        '(' | ')' | '[' | ']' | '{' | '}' | '|' | '/' | '⌊' | '⌋' | '⌈' | '⌉' | '⟨' | '⟩' => 0.39,
+        '→' | '←' | '↔' | '⇒' | '⇐' | '⇔' | '↦' | '∞' | '…' | '⋯' => 1.0,
+        '⟶' | '⟵' => 1.5,
+        '∫' | '∮' | '∗' => 0.5,
+        '∬' => 0.85,
+        '∭' => 1.2,
+        '∑' | '∏' | '∐' | '⋃' | '⋂' | '⨁' | '⨂' => 0.9,
+        'α'..='ω' | 'ϑ' | 'ϕ' | 'ϖ' | 'ϱ' | 'ϵ' => 0.58,
+        '+' | '−' | '=' | '<' | '>' | '±' | '∓' | '×' | '÷' | '≤' | '≥' | '≠' | '≈' | '≡' | '∼' | '≃' | '≅'
+        | '∈' | '∉' | '∋' | '⊂' | '⊃' | '⊆' | '⊇' | '∪' | '∩' | '∧' | '∨' | '⊕' | '⊗' | '⊖' | '∝' => 0.78,
+        _ => 0.72,
+    }
+}
+
+fn has_descender(text: &str) -> bool {
+    text.chars().any(|c| "gjpqy,;()[]{}|/βγζημξρςφχψϕ∫∬∭∮⌊⌋⌈⌉⟨⟩".contains(c))
+}
+
+fn glyph(text: &str, size: f32, font: Font) -> MathBox {
+    let width = text.chars().map(char_width).sum::<f32>() * size;
+    MathBox {
+        width,
+        height: 0.72 * size,
+        depth: if has_descender(text) { 0.22 * size } else { 0.0 },
+        items: vec![Item::Glyph { x: 0.0, y: 0.0, width, text: text.to_string(), size, font }],
+    }
+}
+
+fn node_class(node: &Node) -> Option<Class> {
+    match node {
+        Node::Symbol { class, .. } => Some(*class),
+        Node::Scripts { base, .. } => node_class(base),
+        Node::Space(_) => None,
+        _ => Some(Class::Ord),
+    }
+}
+
+/// Space between adjacent atoms, after TeX's inter-atom spacing table.
+fn spacing(left: Class, right: Class, style: Style) -> f32 {
+    use Class::*;
+    let script = matches!(style, Style::Script | Style::ScriptScript);
+    match (left, right) {
+        (Bin, _) | (_, Bin) if script => 0.0,
+        (Bin, _) | (_, Bin) => MEDIUM,
+        (Rel, Rel) | (Rel, Close) | (Rel, Punct) | (Open, Rel) => 0.0,
+        (Rel, _) | (_, Rel) if script => 0.0,
+        (Rel, _) | (_, Rel) => THICK,
+        (Op { .. }, Ord | Op { .. }) | (Ord | Close, Op { .. }) => THIN,
+        (Punct, _) if !script => THIN,
+        _ => 0.0,
+    }
+}
+
+/// Lay out atoms left to right.
+fn layout_list(nodes: &[Node], style: Style) -> MathBox {
+    let mut list = MathBox::default();
+    let mut prev = None;
+    for (i, node) in nodes.iter().enumerate() {
+        let Some(mut class) = node_class(node) else {
+            if let Node::Space(width) = node {
+                list.append(MathBox::space(width * style.size()), 0.0);
+            }
+            continue;
+        };
+        // A binary operator with nothing to its left (or right) is unary
+        let next = nodes[i + 1..].iter().find_map(node_class);
+        if class == Class::Bin
+            && (matches!(prev, None | Some(Class::Bin | Class::Op { .. } | Class::Rel | Class::Open | Class::Punct))
+                || matches!(next, None | Some(Class::Rel | Class::Close | Class::Punct)))
+        {
+            class = Class::Ord;
+        }
+        if let Some(prev) = prev {
+            list.append(MathBox::space(spacing(prev, class, style) * style.size()), 0.0);
+        }
+        list.append(layout_node(node, style), 0.0);
+        prev = Some(class);
+    }
+    list
+}
+
+fn layout_node(node: &Node, style: Style) -> MathBox {
+    let size = style.size();
+    match node {
+        Node::Symbol { text, class: Class::Op { .. }, font } if !text.chars().all(char::is_alphabetic) => {
+            big_operator(text, style, *font)
+        }
+        Node::Symbol { text, font, .. } => glyph(text, size, *font),
+        Node::Text(text) => glyph(&text.replace(' ', "\u{a0}"), size, Font::Upright),
+        Node::Group(nodes) => layout_list(nodes, style),
+        Node::Scripts { base, sup, sub } => layout_scripts(base, sup.as_deref(), sub.as_deref(), style),
+        Node::Frac { num, den } => layout_fraction(num, den, style),
+        Node::Sqrt { index, body } => layout_sqrt(index.as_deref(), body, style),
+        Node::Delimited { left, body, right } => layout_delimited(left, body, right, style),
+        Node::Space(width) => MathBox::space(width * size),
+        Node::Matrix(rows) => layout_matrix(rows, style),
+    }
+}
+
+/// Sums, products and integrals: larger in display style, centered on the axis.
+fn big_operator(text: &str, style: Style, font: Font) -> MathBox {
+    let integral = text.chars().any(|c| "∫∬∭∮".contains(c));
+    let scale = match (style, integral) {
+        (Style::Display, true) => 2.0,
+        (Style::Display, false) => 1.45,
+        _ => 1.0,
+    };
+    let size = style.size() * scale;
+    let mut op = glyph(text, size, font);
+    // Glyphs of these sit roughly 0.35em (of their own size) above the baseline
+    let shift = 0.35 * size - AXIS * style.size();
+    op.items = op.items.into_iter().map(|item| item.shifted(0.0, shift)).collect();
+    op.height = 0.75 * size - shift;
+    op.depth = shift + 0.25 * size;
+    op
+}
+
+fn layout_scripts(base: &Node, sup: Option<&[Node]>, sub: Option<&[Node]>, style: Style) -> MathBox {
+    let size = style.size();
+    let mut result = layout_node(base, style);
+    let sup = sup.map(|nodes| layout_list(nodes, style.script()));
+    let sub = sub.map(|nodes| layout_list(nodes, style.script()));
+
+    let limits = style == Style::Display && matches!(base, Node::Symbol { class: Class::Op { limits: true }, .. });
+    if limits {
+        let width = [Some(&result), sup.as_ref(), sub.as_ref()].into_iter().flatten()
+            .map(|b| b.width)
+            .fold(0.0, f32::max);
+        let base = std::mem::take(&mut result);
+        let (base_height, base_depth) = (base.height, base.depth);
+        result.width = width;
+        let x = (width - base.width) / 2.0;
+        result.place(base, x, 0.0);
+        if let Some(sup) = sup {
+            let (x, y) = ((width - sup.width) / 2.0, -(base_height + 0.12 * size + sup.depth));
+            result.place(sup, x, y);
+        }
+        if let Some(sub) = sub {
+            let (x, y) = ((width - sub.width) / 2.0, base_depth + 0.12 * size + sub.height);
+            result.place(sub, x, y);
+        }
+        return result;
+    }
+
+    let mut sup_up = (0.4 * size).max(result.height - 0.3 * size);
+    let mut sub_down = (0.18 * size).max(result.depth + 0.05 * size);
+    if let (Some(sup), Some(sub)) = (&sup, &sub) {
+        sub_down = sub_down.max(0.25 * size);
+        let gap = (sup_up - sup.depth) - (sub.height - sub_down);
+        if gap < 0.12 * size {
+            sub_down += 0.12 * size - gap;
+        }
+    }
+    if let Some(sup) = &sup {
+        sup_up = sup_up.max(sup.depth + 0.2 * size);
+    }
+    let x = result.width + 0.03 * size;
+    let width = sup.iter().chain(sub.iter()).map(|b| b.width).fold(0.0, f32::max);
+    if let Some(sup) = sup {
+        result.place(sup, x, -sup_up);
+    }
+    if let Some(sub) = sub {
+        result.place(sub, x, sub_down);
+    }
+    result.width = x + width + 0.05 * size;
+    result
+}
+
+fn layout_fraction(num: &[Node], den: &[Node], style: Style) -> MathBox {
+    let size = style.size();
+    let num = layout_list(num, style.fraction());
+    let den = layout_list(den, style.fraction());
+    let axis = AXIS * size;
+    let thickness = RULE * size;
+    let gap = if style == Style::Display { 0.15 * size } else { 0.1 * size };
+    let width = num.width.max(den.width) + 0.24 * size;
+
+    let mut result = MathBox::space(width);
+    let num_y = -(axis + thickness / 2.0 + gap + num.depth);
+    let den_y = -axis + thickness / 2.0 + gap + den.height;
+    let (num_x, den_x) = ((width - num.width) / 2.0, (width - den.width) / 2.0);
+    result.place(num, num_x, num_y);
+    result.place(den, den_x, den_y);
+    result.items.push(Item::Rule {
+        x: 0.06 * size,
+        y: -axis - thickness / 2.0,
+        width: width - 0.12 * size,
+        height: thickness,
+    });
+    result
+}
+
+fn layout_sqrt(index: Option<&[Node]>, body: &[Node], style: Style) -> MathBox {
+    let size = style.size();
+    let body = layout_list(body, style);
+    let stroke = RULE * size;
+    let top = -(body.height + 0.12 * size + stroke);
+    let bottom = body.depth.max(0.05 * size);
+    let sign = 0.55 * size;
+    let index = index.map(|nodes| layout_list(nodes, Style::ScriptScript));
+    let x0 = index.as_ref().map_or(0.0, |index| (index.width - 0.25 * size).max(0.0));
+    let hook = top + 0.6 * (bottom - top);
+
+    let mut result = MathBox::space(x0 + sign + body.width + 0.15 * size);
+    result.items.push(Item::Path {
+        segments: vec![
+            ('M', vec![(x0, hook)]),
+            ('L', vec![(x0 + 0.12 * size, hook - 0.05 * size)]),
+            ('L', vec![(x0 + 0.28 * size, bottom)]),
+            ('L', vec![(x0 + sign, top)]),
+            ('L', vec![(x0 + sign + body.width + 0.1 * size, top)]),
+        ],
+        stroke,
+    });
+    result.height = -top + stroke / 2.0;
+    result.depth = bottom;
+    result.place(body, x0 + sign + 0.05 * size, 0.0);
+    if let Some(index) = index {
+        let (x, y) = (x0 + 0.25 * size - index.width, hook - 0.1 * size - index.depth);
+        result.place(index, x, y);
+    }
+    result
+}
+
+fn layout_delimited(left: &str, body: &[Node], right: &str, style: Style) -> MathBox {
+    let size = style.size();
+    let body = layout_list(body, style);
+    let axis = AXIS * size;
+    let half = (body.height - axis).max(body.depth + axis).max(0.5 * size) + 0.05 * size;
+    let (top, bottom) = (-axis - half, -axis + half);
+    let width = 0.35 * size + (half - 0.55 * size).max(0.0) * 0.15;
+
+    let mut result = MathBox::default();
+    result.append(delimiter(left, top, bottom, width, style), 0.0);
+    result.append(MathBox::space(0.05 * size), 0.0);
+    result.append(body, 0.0);
+    result.append(MathBox::space(0.05 * size), 0.0);
+    result.append(delimiter(right, top, bottom, width, style), 0.0);
+    result
+}
+
+/// A delimiter stretched from `top` to `bottom`, drawn as a path so it can
+/// grow with its contents.
+fn delimiter(text: &str, top: f32, bottom: f32, width: f32, style: Style) -> MathBox {
+    if text.is_empty() {
+        return MathBox::default();
+    }
+    let mid = (top + bottom) / 2.0;
+    let x = |f: f32| f * width;
+    let line = |points: &[(f32, f32)]| -> Vec<(char, Vec<(f32, f32)>)> {
+        points.iter().enumerate().map(|(i, p)| (if i == 0 { 'M' } else { 'L' }, vec![*p])).collect()
+    };
+    let segments = match text {
+        "(" => vec![('M', vec![(x(0.8), top)]), ('Q', vec![(x(0.0), mid), (x(0.8), bottom)])],
+        ")" => vec![('M', vec![(x(0.2), top)]), ('Q', vec![(x(1.0), mid), (x(0.2), bottom)])],
+        "[" => line(&[(x(0.8), top), (x(0.3), top), (x(0.3), bottom), (x(0.8), bottom)]),
+        "]" => line(&[(x(0.2), top), (x(0.7), top), (x(0.7), bottom), (x(0.2), bottom)]),
+        "{" => vec![
+            ('M', vec![(x(0.85), top)]),
+            ('C', vec![(x(0.3), top), (x(0.6), mid), (x(0.1), mid)]),
+            ('C', vec![(x(0.6), mid), (x(0.3), bottom), (x(0.85), bottom)]),
+        ],
+        "}" => vec![
+            ('M', vec![(x(0.15), top)]),
+            ('C', vec![(x(0.7), top), (x(0.4), mid), (x(0.9), mid)]),
+            ('C', vec![(x(0.4), mid), (x(0.7), bottom), (x(0.15), bottom)]),
+        ],
+        "|" => line(&[(x(0.5), top), (x(0.5), bottom)]),
+        "‖" => {
+            let mut segments = line(&[(x(0.35), top), (x(0.35), bottom)]);
+            segments.extend(line(&[(x(0.65), top), (x(0.65), bottom)]));
+            segments
+        }
+        "⟨" => line(&[(x(0.8), top), (x(0.2), mid), (x(0.8), bottom)]),
+        "⟩" => line(&[(x(0.2), top), (x(0.8), mid), (x(0.2), bottom)]),
+        "⌊" => line(&[(x(0.3), top), (x(0.3), bottom), (x(0.8), bottom)]),
+        "⌋" => line(&[(x(0.7), top), (x(0.7), bottom), (x(0.2), bottom)]),
+        "⌈" => line(&[(x(0.3), bottom), (x(0.3), top), (x(0.8), top)]),
+        "⌉" => line(&[(x(0.7), bottom), (x(0.7), top), (x(0.2), top)]),
+        "/" => line(&[(x(0.9), top), (x(0.1), bottom)]),
+        _ => return glyph(text, style.size(), Font::Upright),
+    };
+    MathBox {
+        width,
+        height: -top,
+        depth: bottom,
+        items: vec![Item::Path { segments, stroke: RULE * style.size() }],
+    }
+}
+
+/// Matrices: centered columns on a grid, centered on the axis.
+fn layout_matrix(rows: &[Vec<Vec<Node>>], style: Style) -> MathBox {
+    let size = style.size();
+    let cell_style = if style == Style::Display { Style::Text } else { style };
+    let cells: Vec<Vec<MathBox>> = rows.iter()
+        .map(|row| row.iter().map(|cell| layout_list(cell, cell_style)).collect())
+        .collect();
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<f32> = (0..columns)
+        .map(|col| cells.iter().filter_map(|row| row.get(col)).map(|b| b.width).fold(0.0, f32::max))
+        .collect();
+    let (column_gap, row_gap, pad) = (0.8 * size, 0.15 * size, 0.1 * size);
+    let extents: Vec<(f32, f32)> = cells.iter()
+        .map(|row| (
+            row.iter().map(|b| b.height).fold(0.7 * size, f32::max),
+            row.iter().map(|b| b.depth).fold(0.25 * size, f32::max),
+        ))
+        .collect();
+    let total = extents.iter().map(|(h, d)| h + d).sum::<f32>() + row_gap * rows.len().saturating_sub(1) as f32;
+
+    let gaps = column_gap * columns.saturating_sub(1) as f32;
+    let mut result = MathBox::space(pad * 2.0 + widths.iter().sum::<f32>() + gaps);
+    let mut y = -AXIS * size - total / 2.0;
+    for (row, (height, depth)) in cells.into_iter().zip(extents) {
+        y += height;
+        let mut x = pad;
+        for (col, cell) in row.into_iter().enumerate() {
+            if col > 0 {
+                x += column_gap;
+            }
+            let dx = x + (widths[col] - cell.width) / 2.0;
+            result.place(cell, dx, y);
+            x += widths[col];
+        }
+        y += depth + row_gap;
+    }
+    result.height = result.height.max(AXIS * size + total / 2.0);
+    result.depth = result.depth.max(total / 2.0 - AXIS * size);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_svg_sizes_formulas_by_their_structure() {
+        let inline = to_svg("x", false, 16.0, "currentColor").unwrap();
+        assert!(inline.markup.starts_with("<svg"));
+        assert!(inline.markup.contains(r#"font-style="italic""#));
+        assert!(inline.markup.contains(r#"fill="currentColor""#));
+
+        let fraction = to_svg(r"\frac{a}{b}", false, 16.0, "currentColor").unwrap();
+        assert!(fraction.height > inline.height);
+        assert!(fraction.depth > inline.depth);
+        assert!(fraction.markup.contains("<rect"));
+
+        let sum = r"\sum_{i=1}^{n} i^2";
+        let display = to_svg(sum, true, 16.0, "#000").unwrap();
+        let text = to_svg(sum, false, 16.0, "#000").unwrap();
+        assert!(display.height > text.height, "limits go above and below in display style");
+
+        let matrix = to_svg(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}", true, 16.0, "#000").unwrap();
+        assert!(matrix.markup.contains("<path"));
+        assert!(crate::core::raster::svg_to_png(&matrix.markup, 1.0).is_ok());
+
+        assert!(to_svg(r"\unknown", false, 16.0, "#000").is_err());
+        assert!(to_svg("  ", false, 16.0, "#000").is_err());
+    }
+
+    /// The glyphs of `tex` laid out in text style, with their positions.
+    fn glyphs(tex: &str) -> Vec<(String, f32, f32)> {
+        layout_list(&parse(tex).unwrap(), Style::Text).items.into_iter()
+            .filter_map(|item| match item {
+                Item::Glyph { x, y, text, .. } => Some((text, x, y)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fraction_puts_numerator_over_a_rule_over_denominator() {
+        let fraction = layout_list(&parse(r"\frac{a}{b}").unwrap(), Style::Text);
+        let rule = fraction.items.iter().find_map(|item| match item {
+            Item::Rule { y, .. } => Some(*y),
+            _ => None,
+        });
+        let glyphs = glyphs(r"\frac{a}{b}");
+        let (num, den) = (&glyphs[0], &glyphs[1]);
+        assert_eq!((num.0.as_str(), den.0.as_str()), ("a", "b"));
+        assert!(num.2 < rule.unwrap() && rule.unwrap() < den.2);
+        assert!((num.1 - den.1).abs() < 0.01, "numerator and denominator are centered");
+    }
+
+    #[test]
+    fn scripts_sit_above_and_below_the_base() {
+        let glyphs = glyphs("x_i^2");
+        let at = |name: &str| glyphs.iter().find(|g| g.0 == name).map(|g| (g.1, g.2)).unwrap();
+        let (base, sub, sup) = (at("x"), at("i"), at("2"));
+        assert!(sup.1 < base.1 && base.1 < sub.1);
+        assert!(sup.0 > base.0 && (sup.0 - sub.0).abs() < 0.01);
+
+        // Prime is a superscript too
+        let prime = self::glyphs("f'");
+        assert_eq!(prime[1].0, "′");
+        assert!(prime[1].2 < prime[0].2);
+    }
+
+    #[test]
+    fn sqrt_draws_a_sign_over_its_body() {
+        let root = layout_list(&parse(r"\sqrt{x}").unwrap(), Style::Text);
+        let Some(Item::Path { segments, .. }) = root.items.iter().find(|item| matches!(item, Item::Path { .. })) else {
+            panic!("no radical sign");
+        };
+        let (bar_start, bar_end) = (segments[3].1[0], segments[4].1[0]);
+        let x = &glyphs(r"\sqrt{x}")[0];
+        assert!(bar_start.0 <= x.1 && x.1 < bar_end.0, "the bar covers the body");
+        assert!(bar_start.1 < -0.5, "the bar is above the body");
+
+        let indexed = glyphs(r"\sqrt[3]{x}");
+        let at = |name: &str| indexed.iter().find(|g| g.0 == name).map(|g| (g.1, g.2)).unwrap();
+        let (index, body) = (at("3"), at("x"));
+        assert!(index.0 < body.0 && index.1 < body.1, "the index sits up and to the left");
+    }
+
+    #[test]
+    fn matrix_cells_form_a_grid() {
+        let glyphs = glyphs(r"\begin{bmatrix} a & b \\ c & d \end{bmatrix}");
+        let at = |name: &str| glyphs.iter().find(|g| g.0 == name).map(|g| (g.1, g.2)).unwrap();
+        let (a, b, c, d) = (at("a"), at("b"), at("c"), at("d"));
+        assert!((a.1 - b.1).abs() < 0.01 && (c.1 - d.1).abs() < 0.01, "rows share a baseline");
+        assert!(a.1 < c.1);
+        assert!((a.0 - c.0).abs() < 0.1 && (b.0 - d.0).abs() < 0.1, "columns line up");
+        assert!(a.0 < b.0);
+    }
+
+    #[test]
+    fn spacing_follows_atom_classes() {
+        let width = |tex: &str| layout_list(&parse(tex).unwrap(), Style::Text).width;
+        assert!(width("a=b") > width("ab") + 2.0 * THICK - 0.01);
+        // A leading minus is unary, so it gets no binary spacing
+        assert!((width("-a") - width("+a")).abs() < 0.01);
+        assert!(width("-a") < width("b-a") - width("b"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Native math rendering: TeX between `$...$`, `$$...$$` or in ```` ```math ````
+//! blocks is laid out to SVG for the GUI backends and to Unicode text for the
+//! terminal, with no JavaScript involved.
+//!
+//! Only a subset of TeX is typeset:
+//!
+//! - letters, digits and ASCII operators; Greek letters, common operators,
+//!   relations and arrows (`\alpha`, `\pm`, `\leq`, `\to`, `\infty`, ...)
+//! - superscripts, subscripts and primes (`x_i^2`, `f'`)
+//! - `\frac{a}{b}` and `\sqrt{x}` / `\sqrt[n]{x}`
+//! - large operators with limits (`\sum`, `\prod`, `\int`, `\lim`, ...) and
+//!   named functions (`\sin`, `\log`, ...)
+//! - `\left( ... \right)` with `( ) [ ] \{ \} | \| \langle \rangle`, floors and ceilings
+//! - `matrix`, `pmatrix`, `bmatrix` and `vmatrix` environments
+//! - `\text{...}`, `\mathrm`, `\mathbf`, `\operatorname` and the spaces
+//!   `\, \: \; \! \quad \qquad`
+//!
+//! Anything else (accents, `cases`, `aligned`, `\mathbb`, style switches,
+//! unknown commands) is shown as the TeX source rather than typeset wrongly.
+
+mod layout;
+mod parse;
+#[cfg(feature = "tui-backend")]
+mod unicode;
+
+use comrak::nodes::{AstNode, NodeHtmlBlock, NodeValue};
+#[cfg(feature = "egui-backend")]
+use regex::Regex;
+
+pub use layout::to_svg;
+#[cfg(feature = "tui-backend")]
+pub use unicode::to_unicode;
+
+use crate::core::mermaid::html_encode;
+use crate::vlog;
+
+/// Font size the HTML output is laid out at; sizes are then written in em so
+/// formulas scale with the text around them.
+const HTML_FONT_PX: f32 = 16.0;
+
+/// Replace the math in a comrak tree about to be formatted to HTML with
+/// inline SVG: `$...$`, `$$...$$` and `` $`...`$ `` spans, and ```` ```math ````
+/// blocks. Formulas outside the supported subset are shown as their source,
+/// with the reason in a tooltip.
+pub fn process_math<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let mut ast = node.data.borrow_mut();
+        let html = match &ast.value {
+            NodeValue::Math(math) => {
+                let style = if math.display_math { "display" } else { "inline" };
+                NodeValue::HtmlInline(match math_html(&math.literal, math.display_math) {
+                    Ok(svg) => format!(r#"<span class="math math-{}">{}</span>"#, style, svg),
+                    Err(e) => {
+                        let delimiter = if math.display_math { "$$" } else { "$" };
+                        format!(
+                            r#"<code class="math-error" title="{}">{}{}{}</code>"#,
+                            html_encode(&e), delimiter, html_encode(&math.literal), delimiter,
+                        )
+                    }
+                })
+            }
+            NodeValue::CodeBlock(block) if block.info.split_whitespace().next() == Some("math") => {
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    block_type: 0,
+                    literal: match math_html(&block.literal, true) {
+                        Ok(svg) => format!(r#"<div class="math math-display">{}</div>"#, svg),
+                        Err(e) => format!(
+                            r#"<pre class="math-error" title="{}"><code>{}</code></pre>"#,
+                            html_encode(&e), html_encode(&block.literal),
+                        ),
+                    },
+                })
+            }
+            _ => continue,
+        };
+        ast.value = html;
+    }
+}
+
+/// SVG markup for a formula sized in em and lowered by its depth, so inline
+/// math sits on the text baseline.
+fn math_html(tex: &str, display: bool) -> Result<String, String> {
+    let svg = to_svg(tex, display, HTML_FONT_PX, "currentColor").inspect_err(|e| {
+        vlog!("math: {}: {}", e, tex.trim());
+    })?;
+    let em = |px: f32| px / HTML_FONT_PX;
+    Ok(svg.markup.replacen(
+        &format!(r#"width="{:.2}" height="{:.2}""#, svg.width, svg.height),
+        &format!(
+            r#"width="{:.3}em" height="{:.3}em" style="vertical-align:-{:.3}em""#,
+            em(svg.width), em(svg.height), em(svg.depth),
+        ),
+        1,
+    ))
+}
+
+/// Pre-process markdown for egui: egui_commonmark only passes `$...$` and
+/// `$$...$$` to the math renderer, so turn ```` ```math ```` blocks into `$$`.
+#[cfg(feature = "egui-backend")]
+pub fn math_blocks_to_dollars(markdown: &str) -> String {
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"(?m)^```math\n([\s\S]*?)^```[ \t]*$").unwrap());
+    re.replace_all(markdown, |caps: &regex::Captures| format!("$$\n{}$$", &caps[1]))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::markdown::comrak_options;
+
+    fn math_to_html(markdown: &str) -> String {
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, markdown, &comrak_options());
+        process_math(root);
+        let mut html = String::new();
+        comrak::format_html(root, &comrak_options(), &mut html).unwrap();
+        html
+    }
+
+    #[test]
+    fn process_math_replaces_spans_and_blocks() {
+        let html = math_to_html("Area $\\pi r^2$ and $`x`$\n\n$$a < b$$\n\n```math\na < b\n```\n");
+        assert_eq!(html.matches(r#"<span class="math math-inline"><svg"#).count(), 2);
+        assert!(html.contains("vertical-align:-"));
+        assert!(html.contains(r#"<span class="math math-display"><svg"#));
+        assert!(html.contains(r#"<div class="math math-display"><svg"#));
+        assert!(!html.contains("data-math-style"));
+    }
+
+    #[test]
+    fn process_math_shows_unsupported_math_as_source() {
+        let html = math_to_html("Set $\\mathbb{R} & x$\n\n```math\n\\hat{x}\n```\n");
+        assert!(html.contains(r#"<code class="math-error" title="unsupported command \mathbb">$\mathbb{R} &amp; x$</code>"#), "{}", html);
+        assert!(html.contains(r#"<pre class="math-error" title="unsupported command \hat"><code>\hat{x}"#), "{}", html);
+        assert!(!html.contains("<svg"));
+    }
+
+    #[test]
+    fn process_math_shows_deeply_nested_math_as_source() {
+        let tex = format!("{}x{}", "{".repeat(10_000), "}".repeat(10_000));
+        let html = math_to_html(&format!("${}$\n", tex));
+        assert!(html.contains(r#"<code class="math-error" title="formula nested too deeply">"#));
+    }
+
+    #[cfg(feature = "egui-backend")]
+    #[test]
+    fn math_blocks_to_dollars_converts_fenced_math() {
+        let md = "Text\n\n```math\nx^2\n```\n\n```rust\nlet x = 1;\n```\n";
+        assert_eq!(math_blocks_to_dollars(md), "Text\n\n$$\nx^2\n$$\n\n```rust\nlet x = 1;\n```\n");
+    }
+}
@@ -0,0 +1,528 @@
+//! Parser for the subset of TeX math that mdr renders (listed in
+//! [`crate::core::math`]). Anything outside it is an error rather than a
+//! guess, so callers show the source instead of a wrong formula.
+
+/// Spacing class of an atom, as in TeX: it decides the space around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Class {
+    Ord,
+    /// Large operator; with `limits`, scripts go above and below in display style.
+    Op { limits: bool },
+    Bin,
+    Rel,
+    Open,
+    Close,
+    Punct,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Font {
+    Italic,
+    Upright,
+    Bold,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Symbol { text: String, class: Class, font: Font },
+    /// `\text{...}`: upright text with its spaces kept.
+    Text(String),
+    /// A braced group, which acts as a single ordinary atom.
+    Group(Vec<Node>),
+    Scripts { base: Box<Node>, sup: Option<Vec<Node>>, sub: Option<Vec<Node>> },
+    Frac { num: Vec<Node>, den: Vec<Node> },
+    Sqrt { index: Option<Vec<Node>>, body: Vec<Node> },
+    /// `\left ... \right`, or a delimited matrix. An empty delimiter is `.`.
+    Delimited { left: String, body: Vec<Node>, right: String },
+    /// Horizontal space, in em.
+    Space(f32),
+    /// Rows of cells, with centered columns.
+    Matrix(Vec<Vec<Vec<Node>>>),
+}
+
+/// How deeply groups, scripts and commands may nest. The parser and the
+/// layout recurse once per level, so deeper input is refused before it can
+/// overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Parse TeX math into nodes. Unsupported commands are errors, so callers can
+/// fall back to showing the source.
+pub fn parse(tex: &str) -> Result<Vec<Node>, String> {
+    parse_nested(tex, 0)
+}
+
+/// [`parse`] for text inside a formula already `depth` levels deep.
+fn parse_nested(tex: &str, depth: usize) -> Result<Vec<Node>, String> {
+    let mut parser = Parser { chars: tex.chars().collect(), pos: 0, depth };
+    let nodes = parser.parse_list()?;
+    match parser.peek_token() {
+        None => Ok(nodes),
+        Some(Token::Close) => Err("unbalanced '}'".to_string()),
+        Some(Token::Command(name)) => Err(format!("unexpected \\{}", name)),
+        Some(_) => Err("'&' and '\\\\' are only allowed in environments".to_string()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Command(String),
+    Char(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Prime,
+    /// `&`, the column separator.
+    Align,
+    /// `\\`, the row separator.
+    Newline,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    /// Run `parse` one nesting level deeper, failing past [`MAX_DEPTH`].
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Parser) -> Result<T, String>) -> Result<T, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err("formula nested too deeply".to_string());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_token(&mut self) -> Option<Token> {
+        let start = self.pos;
+        let token = self.next_token();
+        self.pos = start;
+        token
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_whitespace();
+        let c = *self.chars.get(self.pos)?;
+        self.pos += 1;
+        Some(match c {
+            '{' => Token::Open,
+            '}' => Token::Close,
+            '^' => Token::Sup,
+            '_' => Token::Sub,
+            '\'' => Token::Prime,
+            '&' => Token::Align,
+            '\\' => {
+                let Some(&next) = self.chars.get(self.pos) else {
+                    return Some(Token::Command(String::new()));
+                };
+                self.pos += 1;
+                if next == '\\' {
+                    Token::Newline
+                } else if next.is_ascii_alphabetic() {
+                    let mut name = next.to_string();
+                    while let Some(&c) = self.chars.get(self.pos).filter(|c| c.is_ascii_alphabetic()) {
+                        name.push(c);
+                        self.pos += 1;
+                    }
+                    Token::Command(name)
+                } else {
+                    Token::Command(next.to_string())
+                }
+            }
+            c => Token::Char(c),
+        })
+    }
+
+    /// Parse atoms up to (not including) `}`, `&`, `\\`, `\right`, `\end` or
+    /// the end of input.
+    fn parse_list(&mut self) -> Result<Vec<Node>, String> {
+        self.nested(|parser| {
+            let mut nodes: Vec<Node> = Vec::new();
+            while let Some(token) = parser.peek_token() {
+                match token {
+                    Token::Close | Token::Align | Token::Newline => break,
+                    Token::Command(name) if name == "right" || name == "end" => break,
+                    Token::Sup | Token::Sub | Token::Prime => {
+                        parser.next_token();
+                        let base = nodes.pop().unwrap_or(Node::Group(Vec::new()));
+                        nodes.push(parser.attach_script(base, token)?);
+                    }
+                    _ => {
+                        parser.next_token();
+                        if let Some(node) = parser.parse_atom(token)? {
+                            nodes.push(node);
+                        }
+                    }
+                }
+            }
+            Ok(nodes)
+        })
+    }
+
+    fn attach_script(&mut self, base: Node, token: Token) -> Result<Node, String> {
+        let (base, mut sup, mut sub) = match base {
+            Node::Scripts { base, sup, sub } => (*base, sup, sub),
+            base => (base, None, None),
+        };
+        match token {
+            Token::Prime => sup.get_or_insert_with(Vec::new).push(symbol("′", Class::Ord, Font::Upright)),
+            Token::Sup if sup.is_some() => return Err("double superscript".to_string()),
+            Token::Sub if sub.is_some() => return Err("double subscript".to_string()),
+            Token::Sup => sup = Some(self.parse_arg()?),
+            _ => sub = Some(self.parse_arg()?),
+        }
+        Ok(Node::Scripts { base: Box::new(base), sup, sub })
+    }
+
+    /// A command argument: a braced group, or a single token.
+    fn parse_arg(&mut self) -> Result<Vec<Node>, String> {
+        match self.next_token() {
+            Some(Token::Open) => self.parse_group_body(),
+            Some(Token::Char(c)) => Ok(vec![char_symbol(c)]),
+            Some(token @ Token::Command(_)) => Ok(self.parse_atom(token)?.into_iter().collect()),
+            _ => Err("missing argument".to_string()),
+        }
+    }
+
+    /// The rest of a group whose `{` was consumed.
+    fn parse_group_body(&mut self) -> Result<Vec<Node>, String> {
+        let nodes = self.parse_list()?;
+        match self.next_token() {
+            Some(Token::Close) => Ok(nodes),
+            _ => Err("missing '}'".to_string()),
+        }
+    }
+
+    /// Raw text up to the matching `}`, for `\text` and environment names.
+    fn parse_raw_group(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) != Some(&'{') {
+            return Err("expected '{'".to_string());
+        }
+        self.pos += 1;
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(text),
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err("missing '}'".to_string())
+    }
+
+    fn parse_atom(&mut self, token: Token) -> Result<Option<Node>, String> {
+        Ok(Some(match token {
+            Token::Open => Node::Group(self.parse_group_body()?),
+            Token::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&c) = self.chars.get(self.pos).filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    self.pos += 1;
+                }
+                symbol(&number, Class::Ord, Font::Upright)
+            }
+            Token::Char(c) => char_symbol(c),
+            Token::Command(name) => return self.nested(|parser| parser.parse_command(&name)),
+            _ => return Err("unexpected token".to_string()),
+        }))
+    }
+
+    fn parse_command(&mut self, name: &str) -> Result<Option<Node>, String> {
+        if let Some((text, class)) = command_symbol(name) {
+            let font = if text.chars().all(|c| c.is_alphabetic() && !is_greek_upper(c)) && text.chars().count() == 1 {
+                Font::Italic
+            } else {
+                Font::Upright
+            };
+            return Ok(Some(symbol(text, class, font)));
+        }
+        if let Some(limits) = operator_name(name) {
+            return Ok(Some(symbol(name, Class::Op { limits }, Font::Upright)));
+        }
+        Ok(Some(match name {
+            "frac" => Node::Frac { num: self.parse_arg()?, den: self.parse_arg()? },
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = if self.chars.get(self.pos) == Some(&'[') {
+                    self.pos += 1;
+                    let end = self.chars[self.pos..].iter().position(|&c| c == ']')
+                        .ok_or("missing ']'")?;
+                    let inner: String = self.chars[self.pos..self.pos + end].iter().collect();
+                    self.pos += end + 1;
+                    Some(parse_nested(&inner, self.depth)?)
+                } else {
+                    None
+                };
+                Node::Sqrt { index, body: self.parse_arg()? }
+            }
+            "text" => {
+                let text = self.parse_raw_group()?;
+                // Math inside text (`\text{if $x$}`) is not typeset
+                if text.contains(['\\', '$']) {
+                    return Err("commands inside \\text".to_string());
+                }
+                Node::Text(text)
+            }
+            "mathrm" | "operatorname" => restyle(self.parse_arg()?, Font::Upright),
+            "mathbf" => restyle(self.parse_arg()?, Font::Bold),
+            "left" => {
+                let left = self.parse_delimiter()?;
+                let body = self.parse_list()?;
+                match self.next_token() {
+                    Some(Token::Command(name)) if name == "right" => {}
+                    _ => return Err("\\left without \\right".to_string()),
+                }
+                let right = self.parse_delimiter()?;
+                Node::Delimited { left, body, right }
+            }
+            "begin" => self.parse_environment()?,
+            "," | "thinspace" => Node::Space(3.0 / 18.0),
+            ":" | ">" | "medspace" => Node::Space(4.0 / 18.0),
+            ";" | "thickspace" => Node::Space(5.0 / 18.0),
+            "!" | "negthinspace" => Node::Space(-3.0 / 18.0),
+            " " => Node::Space(0.25),
+            "quad" => Node::Space(1.0),
+            "qquad" => Node::Space(2.0),
+            _ => return Err(format!("unsupported command \\{}", name)),
+        }))
+    }
+
+    fn parse_delimiter(&mut self) -> Result<String, String> {
+        match self.next_token() {
+            Some(Token::Char('.')) => Ok(String::new()),
+            Some(Token::Char(c)) => Ok(c.to_string()),
+            Some(Token::Command(name)) => match name.as_str() {
+                "{" | "lbrace" => Ok("{".to_string()),
+                "}" | "rbrace" => Ok("}".to_string()),
+                "|" | "Vert" => Ok("‖".to_string()),
+                "vert" => Ok("|".to_string()),
+                "langle" => Ok("⟨".to_string()),
+                "rangle" => Ok("⟩".to_string()),
+                "lfloor" => Ok("⌊".to_string()),
+                "rfloor" => Ok("⌋".to_string()),
+                "lceil" => Ok("⌈".to_string()),
+                "rceil" => Ok("⌉".to_string()),
+                _ => Err(format!("unsupported delimiter \\{}", name)),
+            },
+            _ => Err("missing delimiter".to_string()),
+        }
+    }
+
+    fn parse_environment(&mut self) -> Result<Node, String> {
+        let name = self.parse_raw_group()?;
+        let (left, right) = match name.as_str() {
+            "matrix" => ("", ""),
+            "pmatrix" => ("(", ")"),
+            "bmatrix" => ("[", "]"),
+            "vmatrix" => ("|", "|"),
+            _ => return Err(format!("unsupported environment '{}'", name)),
+        };
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            row.push(self.parse_list()?);
+            match self.next_token() {
+                Some(Token::Align) => {}
+                Some(Token::Newline) => rows.push(std::mem::take(&mut row)),
+                Some(Token::Command(end)) if end == "end" => {
+                    let end_name = self.parse_raw_group()?;
+                    if end_name != name {
+                        return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end_name));
+                    }
+                    break;
+                }
+                _ => return Err(format!("missing \\end{{{}}}", name)),
+            }
+        }
+        // A trailing `\\` leaves an empty last row
+        if !(row.len() == 1 && row[0].is_empty() && !rows.is_empty()) {
+            rows.push(row);
+        }
+        let matrix = Node::Matrix(rows);
+        Ok(if left.is_empty() {
+            matrix
+        } else {
+            Node::Delimited { left: left.to_string(), body: vec![matrix], right: right.to_string() }
+        })
+    }
+}
+
+fn symbol(text: &str, class: Class, font: Font) -> Node {
+    Node::Symbol { text: text.to_string(), class, font }
+}
+
+fn char_symbol(c: char) -> Node {
+    let (text, class) = match c {
+        '+' => ("+", Class::Bin),
+        '-' => ("−", Class::Bin),
+        '*' => ("∗", Class::Bin),
+        '=' | '<' | '>' | ':' => return symbol(&c.to_string(), Class::Rel, Font::Upright),
+        ',' | ';' => return symbol(&c.to_string(), Class::Punct, Font::Upright),
+        '(' | '[' => return symbol(&c.to_string(), Class::Open, Font::Upright),
+        ')' | ']' | '!' | '?' => return symbol(&c.to_string(), Class::Close, Font::Upright),
+        '~' => return Node::Space(0.25),
+        c if c.is_alphabetic() => return symbol(&c.to_string(), Class::Ord, Font::Italic),
+        c => return symbol(&c.to_string(), Class::Ord, Font::Upright),
+    };
+    symbol(text, class, Font::Upright)
+}
+
+fn is_greek_upper(c: char) -> bool {
+    ('Α'..='Ω').contains(&c)
+}
+
+/// Apply a font to every symbol in `nodes`.
+fn restyle(nodes: Vec<Node>, font: Font) -> Node {
+    fn apply(node: Node, font: Font) -> Node {
+        match node {
+            Node::Symbol { text, class, .. } => Node::Symbol { text, class, font },
+            Node::Group(nodes) => Node::Group(nodes.into_iter().map(|n| apply(n, font)).collect()),
+            Node::Scripts { base, sup, sub } => Node::Scripts { base: Box::new(apply(*base, font)), sup, sub },
+            other => other,
+        }
+    }
+    // Multi-letter names such as \operatorname{sgn} read as one word
+    let nodes: Vec<Node> = nodes.into_iter().map(|n| apply(n, font)).collect();
+    let word: Option<String> = nodes.iter()
+        .map(|n| match n {
+            Node::Symbol { text, class: Class::Ord, .. } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    match word {
+        Some(word) if nodes.len() > 1 => symbol(&word, Class::Ord, font),
+        _ => Node::Group(nodes),
+    }
+}
+
+/// Named operators typeset upright; the flag says whether they take limits.
+fn operator_name(name: &str) -> Option<bool> {
+    match name {
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" | "argmax" | "argmin" => Some(true),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh" | "cosh"
+        | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "ker" | "dim" | "deg" | "arg" | "hom" => Some(false),
+        _ => None,
+    }
+}
+
+/// Single-symbol commands: Greek letters, operators, relations and arrows.
+fn command_symbol(name: &str) -> Option<(&'static str, Class)> {
+    use Class::*;
+    Some(match name {
+        "alpha" => ("α", Ord), "beta" => ("β", Ord), "gamma" => ("γ", Ord), "delta" => ("δ", Ord),
+        "epsilon" => ("ϵ", Ord), "varepsilon" => ("ε", Ord), "zeta" => ("ζ", Ord), "eta" => ("η", Ord),
+        "theta" => ("θ", Ord), "vartheta" => ("ϑ", Ord), "iota" => ("ι", Ord), "kappa" => ("κ", Ord),
+        "lambda" => ("λ", Ord), "mu" => ("μ", Ord), "nu" => ("ν", Ord), "xi" => ("ξ", Ord),
+        "pi" => ("π", Ord), "varpi" => ("ϖ", Ord), "rho" => ("ρ", Ord), "varrho" => ("ϱ", Ord),
+        "sigma" => ("σ", Ord), "varsigma" => ("ς", Ord), "tau" => ("τ", Ord), "upsilon" => ("υ", Ord),
+        "phi" => ("ϕ", Ord), "varphi" => ("φ", Ord), "chi" => ("χ", Ord), "psi" => ("ψ", Ord),
+        "omega" => ("ω", Ord),
+        "Gamma" => ("Γ", Ord), "Delta" => ("Δ", Ord), "Theta" => ("Θ", Ord), "Lambda" => ("Λ", Ord),
+        "Xi" => ("Ξ", Ord), "Pi" => ("Π", Ord), "Sigma" => ("Σ", Ord), "Upsilon" => ("Υ", Ord),
+        "Phi" => ("Φ", Ord), "Psi" => ("Ψ", Ord), "Omega" => ("Ω", Ord),
+        "infty" => ("∞", Ord), "partial" => ("∂", Ord), "nabla" => ("∇", Ord), "emptyset" | "varnothing" => ("∅", Ord),
+        "ell" => ("ℓ", Ord), "hbar" => ("ℏ", Ord), "Re" => ("ℜ", Ord), "Im" => ("ℑ", Ord), "aleph" => ("ℵ", Ord),
+        "forall" => ("∀", Ord), "exists" => ("∃", Ord), "nexists" => ("∄", Ord), "neg" | "lnot" => ("¬", Ord),
+        "prime" => ("′", Ord), "angle" => ("∠", Ord), "triangle" => ("△", Ord), "degree" => ("°", Ord),
+        "ldots" | "dots" => ("…", Ord), "cdots" => ("⋯", Ord), "vdots" => ("⋮", Ord), "ddots" => ("⋱", Ord),
+        "|" => ("‖", Ord), "vert" => ("|", Ord), "Vert" => ("‖", Ord), "backslash" => ("∖", Ord),
+        "%" => ("%", Ord), "$" => ("$", Ord), "#" => ("#", Ord), "&" => ("&", Ord), "_" => ("_", Ord),
+        "sum" => ("∑", Op { limits: true }), "prod" => ("∏", Op { limits: true }), "coprod" => ("∐", Op { limits: true }),
+        "bigcup" => ("⋃", Op { limits: true }), "bigcap" => ("⋂", Op { limits: true }),
+        "bigoplus" => ("⨁", Op { limits: true }), "bigotimes" => ("⨂", Op { limits: true }),
+        "int" => ("∫", Op { limits: false }), "iint" => ("∬", Op { limits: false }),
+        "iiint" => ("∭", Op { limits: false }), "oint" => ("∮", Op { limits: false }),
+        "pm" => ("±", Bin), "mp" => ("∓", Bin), "times" => ("×", Bin), "div" => ("÷", Bin),
+        "cdot" => ("⋅", Bin), "ast" => ("∗", Bin), "star" => ("⋆", Bin), "circ" => ("∘", Bin),
+        "bullet" => ("∙", Bin), "oplus" => ("⊕", Bin), "ominus" => ("⊖", Bin), "otimes" => ("⊗", Bin),
+        "cup" => ("∪", Bin), "cap" => ("∩", Bin), "setminus" => ("∖", Bin), "wedge" | "land" => ("∧", Bin),
+        "vee" | "lor" => ("∨", Bin),
+        "leq" | "le" => ("≤", Rel), "geq" | "ge" => ("≥", Rel), "neq" | "ne" => ("≠", Rel),
+        "ll" => ("≪", Rel), "gg" => ("≫", Rel), "approx" => ("≈", Rel), "equiv" => ("≡", Rel),
+        "sim" => ("∼", Rel), "simeq" => ("≃", Rel), "cong" => ("≅", Rel), "propto" => ("∝", Rel),
+        "in" => ("∈", Rel), "notin" => ("∉", Rel), "ni" => ("∋", Rel), "subset" => ("⊂", Rel),
+        "supset" => ("⊃", Rel), "subseteq" => ("⊆", Rel), "supseteq" => ("⊇", Rel),
+        "perp" => ("⊥", Rel), "parallel" => ("∥", Rel), "mid" => ("∣", Rel), "models" => ("⊨", Rel),
+        "vdash" => ("⊢", Rel), "coloneqq" => ("≔", Rel),
+        "to" | "rightarrow" => ("→", Rel), "leftarrow" | "gets" => ("←", Rel), "leftrightarrow" => ("↔", Rel),
+        "Rightarrow" | "implies" => ("⇒", Rel), "Leftarrow" => ("⇐", Rel), "Leftrightarrow" | "iff" => ("⇔", Rel),
+        "mapsto" => ("↦", Rel), "longrightarrow" => ("⟶", Rel), "longleftarrow" => ("⟵", Rel),
+        "uparrow" => ("↑", Rel), "downarrow" => ("↓", Rel),
+        "{" | "lbrace" => ("{", Open), "}" | "rbrace" => ("}", Close),
+        "langle" => ("⟨", Open), "rangle" => ("⟩", Close),
+        "lfloor" => ("⌊", Open), "rfloor" => ("⌋", Close), "lceil" => ("⌈", Open), "rceil" => ("⌉", Close),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scripts_fractions_and_environments() {
+        let nodes = parse(r"x_i^2 + \frac{a}{b}").unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(matches!(&nodes[0], Node::Scripts { sup: Some(_), sub: Some(_), .. }));
+        assert!(matches!(&nodes[1], Node::Symbol { class: Class::Bin, .. }));
+        assert!(matches!(&nodes[2], Node::Frac { .. }));
+
+        let nodes = parse(r"\begin{pmatrix} 1 & 2 \\ 3 & 4 \\ \end{pmatrix}").unwrap();
+        let Node::Delimited { body, .. } = &nodes[0] else { panic!("expected delimiters") };
+        let Node::Matrix(rows) = &body[0] else { panic!("expected a matrix") };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].len(), 2);
+
+        assert_eq!(parse(r"\mathrm{d}").unwrap(), vec![Node::Group(vec![symbol("d", Class::Ord, Font::Upright)])]);
+        assert_eq!(parse(r"\text{if } x").unwrap()[0], Node::Text("if ".to_string()));
+    }
+
+    #[test]
+    fn parse_reports_unsupported_and_malformed_input() {
+        assert_eq!(parse(r"\foo x").unwrap_err(), "unsupported command \\foo");
+        assert!(parse("{x").is_err());
+        assert!(parse("x}").is_err());
+        assert!(parse("x^1^2").is_err());
+        assert!(parse(r"\left( x").is_err());
+        assert!(parse(r"\begin{pmatrix} 1 \end{bmatrix}").is_err());
+    }
+
+    #[test]
+    fn parse_rejects_tex_outside_the_subset() {
+        assert_eq!(parse(r"\hat{x}").unwrap_err(), "unsupported command \\hat");
+        assert_eq!(parse(r"\begin{cases} 1 \end{cases}").unwrap_err(), "unsupported environment 'cases'");
+        assert_eq!(parse(r"\displaystyle x").unwrap_err(), "unsupported command \\displaystyle");
+        assert!(parse(r"\text{if $x$}").is_err());
+        assert!(parse("x \\").is_err());
+    }
+
+    #[test]
+    fn parse_refuses_formulas_nested_too_deeply() {
+        let deep = |open: &str, close: &str, n: usize| format!("{}x{}", open.repeat(n), close.repeat(n));
+        assert!(parse(&deep("{", "}", 20)).is_ok());
+        assert!(parse(&deep(r"\frac{1}{", "}", 10)).is_ok());
+        for tex in [
+            deep("{", "}", 10_000),
+            deep(r"\sqrt{", "}", 10_000),
+            deep(r"\sqrt", "", 10_000),
+            deep("x^{", "}", 10_000),
+            deep(r"\left(", r"\right)", 10_000),
+        ] {
+            assert_eq!(parse(&tex).unwrap_err(), "formula nested too deeply");
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Plain-text approximation of math for terminals: Unicode super- and
+//! subscripts, `√`, `a/b` and `(a b; c d)` for matrices.
+
+use super::parse::{parse, Class, Node};
+
+/// Render TeX math as a single line of Unicode text, or return the source
+/// unchanged if it cannot be parsed.
+pub fn to_unicode(tex: &str) -> String {
+    match parse(tex) {
+        Ok(nodes) => list(&nodes, false),
+        Err(_) => tex.trim().to_string(),
+    }
+}
+
+/// Convert a list of atoms. `compact` drops the spaces around operators, for
+/// scripts and fraction parts.
+fn list(nodes: &[Node], compact: bool) -> String {
+    let mut out = String::new();
+    let mut prev: Option<Class> = None;
+    for (i, node) in nodes.iter().enumerate() {
+        let text = node_text(node, compact);
+        let class = match node {
+            Node::Symbol { class, .. } => Some(*class),
+            Node::Scripts { base, .. } => match base.as_ref() {
+                Node::Symbol { class, .. } => Some(*class),
+                _ => Some(Class::Ord),
+            },
+            Node::Space(_) => None,
+            _ => Some(Class::Ord),
+        };
+        let unary = matches!(prev, None | Some(Class::Bin | Class::Rel | Class::Open | Class::Punct | Class::Op { .. }));
+        match class {
+            _ if compact => out.push_str(&text),
+            Some(Class::Bin) if !unary && i + 1 < nodes.len() => out.push_str(&format!(" {} ", text)),
+            Some(Class::Rel) if prev.is_some() => out.push_str(&format!(" {} ", text.trim())),
+            Some(Class::Punct) => out.push_str(&format!("{} ", text)),
+            _ => {
+                // Keep operators apart from their operand: "sin x"
+                let word_before = matches!(prev, Some(Class::Op { .. }))
+                    && out.ends_with(|c: char| c.is_alphanumeric())
+                    && text.starts_with(|c: char| c.is_alphanumeric());
+                if word_before {
+                    out.push(' ');
+                }
+                out.push_str(&text);
+            }
+        }
+        if class.is_some() {
+            prev = class;
+        }
+    }
+    if compact { out } else { out.trim().to_string() }
+}
+
+fn node_text(node: &Node, compact: bool) -> String {
+    match node {
+        Node::Symbol { text, .. } => text.clone(),
+        Node::Text(text) => text.clone(),
+        Node::Group(nodes) => list(nodes, compact),
+        Node::Scripts { base, sup, sub } => {
+            let mut out = node_text(base, compact);
+            if let Some(sub) = sub {
+                out.push_str(&script(&list(sub, true), subscript_char, '_'));
+            }
+            if let Some(sup) = sup {
+                out.push_str(&script(&list(sup, true), superscript_char, '^'));
+            }
+            out
+        }
+        Node::Frac { num, den } => {
+            let (num, den) = (list(num, true), list(den, true));
+            match (num.as_str(), den.as_str()) {
+                ("1", "2") => "½".to_string(),
+                ("1", "3") => "⅓".to_string(),
+                ("2", "3") => "⅔".to_string(),
+                ("1", "4") => "¼".to_string(),
+                ("3", "4") => "¾".to_string(),
+                _ => format!("{}/{}", wrap(&num), wrap(&den)),
+            }
+        }
+        Node::Sqrt { index, body } => {
+            let sign = match index.as_deref().map(|index| list(index, true)).as_deref() {
+                None => "√".to_string(),
+                Some("3") => "∛".to_string(),
+                Some("4") => "∜".to_string(),
+                Some(index) => format!("{}√", script(index, superscript_char, '^').trim_start_matches('^')),
+            };
+            format!("{}{}", sign, wrap(&list(body, compact)))
+        }
+        Node::Delimited { left, body, right } => format!("{}{}{}", left, list(body, compact), right),
+        Node::Space(width) if *width >= 2.0 => "   ".to_string(),
+        Node::Space(width) if *width >= 1.0 => "  ".to_string(),
+        Node::Space(width) if *width >= 0.25 => " ".to_string(),
+        Node::Space(_) => String::new(),
+        Node::Matrix(rows) => {
+            let rows: Vec<String> = rows.iter()
+                .map(|row| {
+                    let cells: Vec<String> = row.iter().map(|cell| list(cell, compact)).collect();
+                    cells.join(" ")
+                })
+                .collect();
+            rows.join("; ")
+        }
+    }
+}
+
+/// Parenthesize `text` unless it is a single number or symbol.
+fn wrap(text: &str) -> String {
+    if text.chars().count() <= 1 || text.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        text.to_string()
+    } else {
+        format!("({})", text)
+    }
+}
+
+/// Map every character to its script form, or fall back to `^x` / `^(...)`.
+fn script(text: &str, map: fn(char) -> Option<char>, marker: char) -> String {
+    match text.chars().map(map).collect::<Option<String>>() {
+        Some(mapped) => mapped,
+        None if text.chars().count() == 1 => format!("{}{}", marker, text),
+        None => format!("{}({})", marker, text),
+    }
+}
+
+fn superscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴',
+        '5' => '⁵', '6' => '⁶', '7' => '⁷', '8' => '⁸', '9' => '⁹',
+        '+' => '⁺', '−' => '⁻', '=' => '⁼', '(' => '⁽', ')' => '⁾',
+        'a' => 'ᵃ', 'b' => 'ᵇ', 'c' => 'ᶜ', 'd' => 'ᵈ', 'e' => 'ᵉ', 'f' => 'ᶠ', 'g' => 'ᵍ',
+        'h' => 'ʰ', 'i' => 'ⁱ', 'j' => 'ʲ', 'k' => 'ᵏ', 'l' => 'ˡ', 'm' => 'ᵐ', 'n' => 'ⁿ',
+        'o' => 'ᵒ', 'p' => 'ᵖ', 'r' => 'ʳ', 's' => 'ˢ', 't' => 'ᵗ', 'u' => 'ᵘ', 'v' => 'ᵛ',
+        'w' => 'ʷ', 'x' => 'ˣ', 'y' => 'ʸ', 'z' => 'ᶻ', 'T' => 'ᵀ',
+        'β' => 'ᵝ', 'γ' => 'ᵞ', 'δ' => 'ᵟ', 'θ' => 'ᶿ', 'φ' | 'ϕ' => 'ᵠ', 'χ' => 'ᵡ',
+        '′' => '′',
+        _ => return None,
+    })
+}
+
+fn subscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀', '1' => '₁', '2' => '₂', '3' => '₃', '4' => '₄',
+        '5' => '₅', '6' => '₆', '7' => '₇', '8' => '₈', '9' => '₉',
+        '+' => '₊', '−' => '₋', '=' => '₌', '(' => '₍', ')' => '₎',
+        'a' => 'ₐ', 'e' => 'ₑ', 'h' => 'ₕ', 'i' => 'ᵢ', 'j' => 'ⱼ', 'k' => 'ₖ', 'l' => 'ₗ',
+        'm' => 'ₘ', 'n' => 'ₙ', 'o' => 'ₒ', 'p' => 'ₚ', 'r' => 'ᵣ', 's' => 'ₛ', 't' => 'ₜ',
+        'u' => 'ᵤ', 'v' => 'ᵥ', 'x' => 'ₓ',
+        'β' => 'ᵦ', 'γ' => 'ᵧ', 'ρ' => 'ᵨ', 'φ' | 'ϕ' => 'ᵩ', 'χ' => 'ᵪ',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_unicode_approximates_common_notation() {
+        assert_eq!(to_unicode(r"x^2 + \frac{1}{2}"), "x² + ½");
+        assert_eq!(to_unicode(r"\sum_{i=1}^{n} i"), "∑ᵢ₌₁ⁿ i");
+        assert_eq!(to_unicode(r"\alpha \leq \beta"), "α ≤ β");
+        assert_eq!(to_unicode(r"\sqrt{x+1}"), "√(x + 1)");
+        assert_eq!(to_unicode(r"\frac{a+b}{c}"), "(a+b)/c");
+        assert_eq!(to_unicode(r"e^{i\pi} = -1"), "e^(iπ) = −1");
+        assert_eq!(to_unicode(r"\sin x"), "sin x");
+        assert_eq!(to_unicode(r"\begin{pmatrix} 1 & 0 \\ 0 & 1 \end{pmatrix}"), "(1 0; 0 1)");
+    }
+
+    #[test]
+    fn to_unicode_handles_scripts_roots_and_matrices() {
+        assert_eq!(to_unicode(r"x_i^2"), "xᵢ²");
+        assert_eq!(to_unicode(r"a_{n+1}"), "aₙ₊₁");
+        assert_eq!(to_unicode(r"x_{max}"), "xₘₐₓ");
+        assert_eq!(to_unicode(r"\sqrt[3]{x}"), "∛x");
+        assert_eq!(to_unicode(r"\frac{1}{x}"), "1/x");
+        assert_eq!(to_unicode(r"\begin{bmatrix} a & b \\ c & d \end{bmatrix}"), "[a b; c d]");
+        assert_eq!(to_unicode(r"\begin{vmatrix} a & b \\ c & d \end{vmatrix}"), "|a b; c d|");
+    }
+
+    #[test]
+    fn to_unicode_falls_back_to_source() {
+        assert_eq!(to_unicode(r" \unknown{x} "), r"\unknown{x}");
+        assert_eq!(to_unicode(r"\mathbb{R}^n"), r"\mathbb{R}^n");
+        assert_eq!(to_unicode(r"\begin{cases} 1 \end{cases}"), r"\begin{cases} 1 \end{cases}");
+    }
+}
@@ -0,0 +1,296 @@
+//! Syntax checks for Mermaid sources. mermaid-rs-renderer accepts nearly any
+//! input and draws an empty or garbled diagram for what it does not
+//! understand, so mistakes are caught here instead, with their position.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use comrak::nodes::NodeValue;
+use regex::Regex;
+
+use crate::core::ast::Node;
+
+/// A problem in a diagram. `line` and `column` are 1-based, relative to the
+/// diagram source, and `None` when the problem has no position (such as a
+/// renderer crash).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MermaidError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl MermaidError {
+    fn at(line: usize, column: usize, message: impl Into<String>) -> Self {
+        MermaidError { message: message.into(), line: Some(line), column: Some(column) }
+    }
+
+    /// A `^` under the offending column, to show below the offending line.
+    pub fn caret(&self) -> Option<String> {
+        Some(format!("{}^", " ".repeat(self.column?.saturating_sub(1))))
+    }
+}
+
+impl fmt::Display for MermaidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            (Some(line), None) => write!(f, "line {}: {}", line, self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Keywords a diagram may start with (the diagram types mermaid.js knows).
+const DIAGRAM_TYPES: &[&str] = &[
+    "graph", "flowchart", "sequenceDiagram", "classDiagram", "classDiagram-v2", "stateDiagram",
+    "stateDiagram-v2", "erDiagram", "gantt", "pie", "journey", "gitGraph", "mindmap", "timeline",
+    "quadrantChart", "requirementDiagram", "C4Context", "C4Container", "C4Component", "C4Dynamic",
+    "C4Deployment", "xychart-beta", "sankey-beta", "block-beta", "packet-beta", "architecture-beta",
+    "kanban", "radar-beta",
+];
+
+/// Sequence diagram statements that open a block closed by `end`.
+const SEQUENCE_BLOCKS: &[&str] = &["loop", "alt", "opt", "par", "critical", "break", "rect", "box"];
+
+/// Check `source` for mistakes that would make the diagram render wrong or
+/// not at all. Only definite errors are reported, so a diagram that passes
+/// may still use syntax the renderer does not support.
+pub fn check(source: &str) -> Result<(), MermaidError> {
+    let mut statements = statements(source)?.into_iter();
+    let Some((line, header)) = statements.next() else {
+        return Err(MermaidError { message: "empty diagram".to_string(), line: None, column: None });
+    };
+    let keyword = header.trim_start().split(|c: char| c.is_whitespace() || c == ';').next().unwrap_or("");
+    if !DIAGRAM_TYPES.contains(&keyword) {
+        return Err(MermaidError::at(line, column_of(header, header.trim_start()), format!(
+            "unknown diagram type `{}` (expected one such as flowchart, sequenceDiagram, classDiagram, stateDiagram-v2, erDiagram, gantt or pie)",
+            keyword
+        )));
+    }
+    let body: Vec<(usize, &str)> = statements.collect();
+    match keyword {
+        "graph" | "flowchart" => check_flowchart(&body),
+        "sequenceDiagram" => check_ends(&body, SEQUENCE_BLOCKS),
+        "classDiagram" | "classDiagram-v2" | "stateDiagram" | "stateDiagram-v2" | "erDiagram" => check_braces(&body),
+        "pie" => check_pie(&body),
+        _ => Ok(()),
+    }
+}
+
+/// The diagram's lines with their 1-based numbers, without blank lines,
+/// `%%` comments and directives, and a leading `---` front matter block.
+fn statements(source: &str) -> Result<Vec<(usize, &str)>, MermaidError> {
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with("%%"))
+        .peekable();
+    if let Some(&(start, line)) = lines.peek() {
+        if line.trim() == "---" {
+            lines.next();
+            if !lines.any(|(_, line)| line.trim() == "---") {
+                return Err(MermaidError::at(start, 1, "front matter is never closed with `---`"));
+            }
+        }
+    }
+    Ok(lines.collect())
+}
+
+/// 1-based character column at which `part` (a subslice of `line`) starts.
+fn column_of(line: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    line[..offset].chars().count() + 1
+}
+
+fn check_flowchart(body: &[(usize, &str)]) -> Result<(), MermaidError> {
+    static DANGLING: OnceLock<Regex> = OnceLock::new();
+    // An arrow (with an optional |label|) and nothing after it
+    let dangling = DANGLING.get_or_init(|| {
+        Regex::new(r"(?:<?-+[->ox]|<?=+[=>]|<?-\.+-?>?)\s*(?:\|[^|]*\|)?\s*;?\s*$").unwrap()
+    });
+    for &(line, text) in body {
+        check_brackets(line, text)?;
+        if let Some(arrow) = dangling.find(text) {
+            // `A-- text --- B`-style labels end in dashes too, but never at the end of a line
+            return Err(MermaidError::at(line, column_of(text, &text[arrow.start()..]), "edge has no target node"));
+        }
+    }
+    check_ends(body, &["subgraph"])
+}
+
+/// Check that brackets and quotes on a flowchart line are balanced. `|edge
+/// labels|` and `"strings"` may contain anything.
+fn check_brackets(line: usize, text: &str) -> Result<(), MermaidError> {
+    let mut open: Vec<(char, usize)> = Vec::new();
+    let mut quote: Option<(char, usize)> = None;
+    let mut prev = ' ';
+    for (i, c) in text.chars().enumerate() {
+        let column = i + 1;
+        if let Some((end, _)) = quote {
+            if c == end {
+                quote = None;
+            }
+        } else {
+            match c {
+                '"' => quote = Some(('"', column)),
+                '|' if open.is_empty() => quote = Some(('|', column)),
+                '(' | '[' | '{' => open.push((c, column)),
+                // `id>text]` is the flag shape; in arrows `>` follows `-`, `=` or `.`
+                '>' if open.is_empty() && (prev.is_alphanumeric() || prev == '_') => open.push((c, column)),
+                ')' | ']' | '}' => {
+                    let Some((opener, at)) = open.pop() else {
+                        return Err(MermaidError::at(line, column, format!("unexpected `{}`", c)));
+                    };
+                    let close = match opener {
+                        '(' => ')',
+                        '{' => '}',
+                        _ => ']',
+                    };
+                    if c != close {
+                        return Err(MermaidError::at(line, column, format!(
+                            "expected `{}` to close `{}` from column {}, found `{}`", close, opener, at, c
+                        )));
+                    }
+                }
+                _ => {}
+            }
+        }
+        prev = c;
+    }
+    if let Some((c, column)) = quote {
+        return Err(MermaidError::at(line, column, format!("`{}` is never closed", c)));
+    }
+    match open.pop() {
+        Some((c, column)) => Err(MermaidError::at(line, column, format!("`{}` is never closed", c))),
+        None => Ok(()),
+    }
+}
+
+/// Check that every statement starting with one of `openers` is closed by an
+/// `end` line, and that there is no `end` too many.
+fn check_ends(body: &[(usize, &str)], openers: &[&str]) -> Result<(), MermaidError> {
+    let mut open: Vec<(usize, &str, usize)> = Vec::new();
+    for &(line, text) in body {
+        let word = text.split_whitespace().next().unwrap_or("");
+        if openers.contains(&word) {
+            open.push((line, word, column_of(text, text.trim_start())));
+        } else if word.trim_end_matches(';') == "end" && open.pop().is_none() {
+            let hint = if openers == ["subgraph"] { " (a node cannot be called `end`; capitalise it)" } else { "" };
+            return Err(MermaidError::at(line, column_of(text, text.trim_start()), format!(
+                "`end` without a matching `{}`{}", openers.join("`/`"), hint
+            )));
+        }
+    }
+    match open.pop() {
+        Some((line, word, column)) => Err(MermaidError::at(line, column, format!("`{}` is never closed with `end`", word))),
+        None => Ok(()),
+    }
+}
+
+/// Check `{ ... }` member blocks in class, state and ER diagrams, which open
+/// at the end of a line and close on a line of their own.
+fn check_braces(body: &[(usize, &str)]) -> Result<(), MermaidError> {
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    for &(line, text) in body {
+        let trimmed = text.trim();
+        if trimmed.starts_with('}') {
+            if open.pop().is_none() {
+                return Err(MermaidError::at(line, column_of(text, text.trim_start()), "unexpected `}`"));
+            }
+        } else if trimmed.ends_with('{') {
+            open.push((line, column_of(text, text.trim_end()) + text.trim_end().chars().count() - 1));
+        }
+    }
+    match open.pop() {
+        Some((line, column)) => Err(MermaidError::at(line, column, "`{` is never closed")),
+        None => Ok(()),
+    }
+}
+
+/// Check `"label" : value` slices of a pie chart.
+fn check_pie(body: &[(usize, &str)]) -> Result<(), MermaidError> {
+    for &(line, text) in body {
+        let trimmed = text.trim();
+        if ["title", "showData", "accTitle", "accDescr"].iter().any(|keyword| trimmed.starts_with(keyword)) {
+            continue;
+        }
+        let Some(label) = trimmed.strip_prefix('"') else {
+            return Err(MermaidError::at(line, column_of(text, trimmed), "pie slice labels must be in double quotes"));
+        };
+        let Some(end) = label.find('"') else {
+            return Err(MermaidError::at(line, column_of(text, trimmed), "`\"` is never closed"));
+        };
+        let rest = &label[end + 1..];
+        let Some(value) = rest.trim_start().strip_prefix(':') else {
+            return Err(MermaidError::at(line, column_of(text, rest.trim_start()), "expected `:` after the slice label"));
+        };
+        let value = value.trim();
+        if value.parse::<f64>().is_err() {
+            return Err(MermaidError::at(line, column_of(text, value), format!("slice value `{}` is not a number", value)));
+        }
+    }
+    Ok(())
+}
+
+/// Fenced ```mermaid blocks in a parsed document: the 1-based line of each block's
+/// first source line, and its source.
+pub fn diagrams(root: &Node) -> Vec<(usize, String)> {
+    root.descendants()
+        .filter_map(|node| match node.value() {
+            NodeValue::CodeBlock(block) if block.fenced && block.info.split_whitespace().next() == Some("mermaid") => {
+                Some((node.sourcepos().start.line + 1, block.literal.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        check(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_diagrams_pass() {
+        for source in [
+            "graph LR\n  A-->B\n  B-- label -->C\n  C-->|yes| D{Decision}\n  D --> E((Circle)) & F>Flag]\n",
+            "%%{init: {'theme': 'dark'}}%%\nflowchart TD\n  subgraph one [One (a)]\n    A[\"quoted ] bracket\"] --> B\n  end\n",
+            "---\ntitle: Demo\n---\nsequenceDiagram\n  loop Every minute\n    Alice->>Bob: Hi\n  end\n",
+            "classDiagram\n  class Animal {\n    +String name\n  }\n  Animal <|-- Duck\n",
+            "erDiagram\n  CUSTOMER ||--o{ ORDER : places\n  ORDER {\n    string id\n  }\n",
+            "pie title Pets\n  \"Dogs\" : 386\n  \"Cats\" : 85.5\n",
+            "gantt\n  title x\n  section s\n  task :a1, 2014-01-01, 30d\n",
+        ] {
+            assert_eq!(check(source), Ok(()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(check("\n%% nothing\n").unwrap_err().message, "empty diagram");
+        assert!(error("not valid %%% !@#").starts_with("line 1, column 1: unknown diagram type `not`"));
+        assert_eq!(error("graph LR\n  A[Start --> B"), "line 2, column 4: `[` is never closed");
+        assert_eq!(error("graph LR\n  A(Start] --> B"), "line 2, column 10: expected `)` to close `(` from column 4, found `]`");
+        assert_eq!(error("graph LR\n  A --> B\n  B -->"), "line 3, column 5: edge has no target node");
+        assert_eq!(error("graph LR\n  A -->|label|"), "line 2, column 5: edge has no target node");
+        assert_eq!(error("flowchart TD\n  subgraph one\n    A --> B\n"), "line 2, column 3: `subgraph` is never closed with `end`");
+        assert!(error("graph TD\n  A --> end\n  end\n").starts_with("line 3, column 3: `end` without a matching `subgraph`"));
+        assert_eq!(error("sequenceDiagram\n  alt ok\n    A->>B: hi\n"), "line 2, column 3: `alt` is never closed with `end`");
+        assert_eq!(error("classDiagram\n  class A {\n    +x\n"), "line 2, column 11: `{` is never closed");
+        assert_eq!(error("pie\n  \"Dogs\" : many"), "line 2, column 12: slice value `many` is not a number");
+    }
+
+    #[test]
+    fn caret_marks_the_column() {
+        let err = check("graph LR\n  A[Start --> B").unwrap_err();
+        assert_eq!(err.caret().as_deref(), Some("   ^"));
+    }
+
+    #[test]
+    fn diagrams_are_found_with_their_lines() {
+        let md = "# Title\n\n```mermaid\ngraph LR\n  A-->B\n```\n\n```rust\nfn main() {}\n```\n\n```mermaid\npie\n```\n";
+        assert_eq!(diagrams(&Node::parse(md)), vec![(4, "graph LR\n  A-->B\n".to_string()), (13, "pie\n".to_string())]);
+    }
+}
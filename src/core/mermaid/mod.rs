@@ -0,0 +1,883 @@
+use regex::Regex;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, Once};
+use std::time::{Duration, Instant};
+
+use crate::core::cache::{self, Failure};
+use crate::vlog;
+
+mod check;
+mod theme;
+
+pub use check::{check, diagrams, MermaidError};
+pub use theme::Theme;
+
+/// Preprocess mermaid source to fix known incompatibilities with mermaid-rs-renderer.
+/// This increases the success rate of the native Rust renderer across all backends.
+fn preprocess_mermaid_source(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    for line in source.lines() {
+        let processed = line
+            // Replace HTML line breaks in node labels with spaces
+            .replace("<br/>", " ")
+            .replace("<br>", " ")
+            .replace("<br />", " ")
+            // Replace bidirectional arrows (not supported) with unidirectional
+            .replace("<-->", "---")
+            .replace("x--x", "---")
+            .replace("o--o", "---");
+        result.push_str(&processed);
+        result.push('\n');
+    }
+    result
+}
+
+/// Longest a single diagram may take to render before it is shown as failed,
+/// so one pathological graph cannot hold up the document.
+const RENDER_BUDGET: Duration = Duration::from_secs(5);
+
+/// How long a diagram whose background render failed transiently (it timed
+/// out, say) is shown as failed before it is queued again. The failure is
+/// not cached, so without this every redraw would queue it straight back.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Render a single mermaid diagram source to SVG in the colours of `theme`
+/// (unless the diagram names its own), or return the cached result if this
+/// diagram was rendered before (see [`cache::get_or_render`]).
+pub fn render_mermaid_to_svg(source: &str, theme: Theme) -> Result<String, String> {
+    let svg = Output::Svg(theme).render(source)?;
+    Ok(String::from_utf8_lossy(&svg).into_owned())
+}
+
+/// Render a mermaid diagram to PNG bytes at `scale`, cached like its SVG.
+pub fn render_mermaid_to_png(source: &str, scale: f32, theme: Theme) -> Result<Arc<[u8]>, String> {
+    cache::get_or_render("mermaid-png", &png_key(source, scale, theme), || {
+        let svg = render_mermaid_to_svg(source, theme).map_err(|message| Failure {
+            // The SVG cache keeps real failures but not transient ones
+            transient: Output::Svg(theme).cached(source).is_none(),
+            message,
+        })?;
+        crate::core::raster::svg_to_png(&svg, scale).map_err(|e| Failure::from(format!("SVG to PNG conversion failed: {}", e)))
+    })
+}
+
+/// Form a backend shows diagrams in: SVG for HTML, PNG at 1x for egui and the
+/// TUI, each in the colours of a theme.
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Svg(Theme),
+    #[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+    Png(Theme),
+}
+
+impl Output {
+    fn render(self, source: &str) -> cache::Rendered {
+        match self {
+            Output::Svg(theme) => cache::get_or_render("mermaid-svg", &svg_key(source, theme), || {
+                render_with_budget(source, theme).map(String::into_bytes)
+            }),
+            #[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+            Output::Png(theme) => render_mermaid_to_png(source, 1.0, theme),
+        }
+    }
+
+    /// The rendered diagram if it is already cached; never renders.
+    fn cached(self, source: &str) -> Option<cache::Rendered> {
+        match self {
+            Output::Svg(theme) => cache::get("mermaid-svg", &svg_key(source, theme)),
+            #[cfg(any(feature = "egui-backend", feature = "tui-backend"))]
+            Output::Png(theme) => cache::get("mermaid-png", &png_key(source, 1.0, theme)),
+        }
+    }
+
+    /// With `pending`, the cached diagram, or `None` after queueing it to be
+    /// rendered later. Without, the diagram rendered now.
+    fn lookup(self, source: &str, pending: &mut Option<&mut Pending>) -> Option<cache::Rendered> {
+        match pending {
+            Some(pending) => self.cached(source).or_else(|| {
+                let job = (source.to_string(), self);
+                if let Some(error) = retry_later(&job) {
+                    return Some(Err(error));
+                }
+                pending.sources.push(job);
+                None
+            }),
+            None => Some(self.render(source)),
+        }
+    }
+}
+
+/// A diagram's source and the form to render it in.
+type Job = (String, Output);
+
+fn svg_key(source: &str, theme: Theme) -> String {
+    cache::key(&[env!("CARGO_PKG_VERSION").as_bytes(), theme.name().as_bytes(), source.as_bytes()])
+}
+
+fn png_key(source: &str, scale: f32, theme: Theme) -> String {
+    cache::key(&[env!("CARGO_PKG_VERSION").as_bytes(), theme.name().as_bytes(), source.as_bytes(), &scale.to_le_bytes()])
+}
+
+/// Diagrams shown as a "rendering…" placeholder because they were not in the
+/// cache yet. [`Pending::spawn`] renders them in the background.
+#[derive(Default)]
+pub struct Pending {
+    sources: Vec<(String, Output)>,
+}
+
+impl Pending {
+    #[cfg(feature = "egui-backend")]
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Render the pending diagrams on a pool of worker threads and call `done`
+    /// as each one lands in the cache, so the caller can redraw with it swapped
+    /// in. Diagrams already being rendered by an earlier call are skipped.
+    #[cfg(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn spawn(self, done: impl Fn() + Send + 'static) {
+        static IN_FLIGHT: Mutex<Vec<(String, Output)>> = Mutex::new(Vec::new());
+        let mut jobs = Vec::new();
+        {
+            let mut in_flight = IN_FLIGHT.lock().unwrap();
+            for job in self.sources {
+                if !in_flight.contains(&job) && !jobs.contains(&job) {
+                    in_flight.push(job.clone());
+                    jobs.push(job);
+                }
+            }
+        }
+        if jobs.is_empty() {
+            return;
+        }
+        vlog!("mermaid: rendering {} diagram(s) in the background", jobs.len());
+        std::thread::spawn(move || {
+            let (tx, rx) = mpsc::channel();
+            std::thread::scope(|scope| {
+                scope.spawn(move || render_all(&jobs, &|job| {
+                    let _ = tx.send(job.clone());
+                }));
+                for job in rx {
+                    IN_FLIGHT.lock().unwrap().retain(|j| *j != job);
+                    done();
+                }
+            });
+        });
+    }
+}
+
+/// Background renders that failed transiently: the job, its error and when
+/// it may be queued again.
+static RETRIES: Mutex<Vec<(Job, String, Instant)>> = Mutex::new(Vec::new());
+
+/// The error `job` failed with, if it failed transiently less than
+/// [`RETRY_AFTER`] ago.
+fn retry_later(job: &Job) -> Option<String> {
+    let mut retries = RETRIES.lock().unwrap();
+    let now = Instant::now();
+    retries.retain(|(_, _, at)| *at > now);
+    retries.iter().find(|(j, _, _)| j == job).map(|(_, error, _)| error.clone())
+}
+
+/// Render `jobs` on up to one worker thread per core, calling `done` after each.
+fn render_all(jobs: &[(String, Output)], done: &(dyn Fn(&(String, Output)) + Sync)) {
+    let next = AtomicUsize::new(0);
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get()).min(jobs.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(job) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(error) = job.1.render(&job.0) {
+                        // Real failures are cached; anything else is worth another try later
+                        if job.1.cached(&job.0).is_none() {
+                            RETRIES.lock().unwrap().push((job.clone(), error, Instant::now() + RETRY_AFTER));
+                        }
+                    }
+                    done(job);
+                }
+            });
+        }
+    });
+}
+
+/// Render every diagram in `sources` that is not cached yet in each of
+/// `outputs`, in parallel, so the sequential pass that follows only hits the cache.
+fn prerender(sources: impl Iterator<Item = String>, outputs: &[Output]) {
+    let mut jobs: Vec<(String, Output)> = Vec::new();
+    for source in sources {
+        for &output in outputs {
+            if output.cached(&source).is_none() && !jobs.iter().any(|(s, o)| *s == source && *o == output) {
+                jobs.push((source.clone(), output));
+            }
+        }
+    }
+    if jobs.len() > 1 {
+        render_all(&jobs, &|_| {});
+    }
+}
+
+fn render_with_budget(source: &str, theme: Theme) -> Result<String, Failure> {
+    let owned = source.to_string();
+    run_with_budget(RENDER_BUDGET, move || render_uncached(&owned, theme))
+}
+
+/// Run `render` on a renderer thread and give up after `budget`. A runaway
+/// render keeps its thread, and its slot among [`max_render_threads`], until
+/// it finishes, but nobody waits for it. Running out of time or threads is a
+/// transient failure, so the diagram is tried again later.
+fn run_with_budget(budget: Duration, render: impl FnOnce() -> Result<String, String> + Send + 'static) -> Result<String, Failure> {
+    let abandoned = Arc::new(AtomicBool::new(false));
+    acquire_render_thread().map_err(Failure::transient)?;
+    let (tx, rx) = mpsc::channel();
+    let flag = Arc::clone(&abandoned);
+    std::thread::spawn(move || {
+        install_quiet_panic_hook();
+        QUIET_PANICS.with(|quiet| quiet.set(true));
+        let _ = tx.send(render());
+        release_render_thread(flag.load(Ordering::Relaxed));
+    });
+    match rx.recv_timeout(budget) {
+        Ok(result) => Ok(result?),
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            // Decide under the lock the thread releases its slot with, so it
+            // sees whether it was abandoned
+            let mut threads = RENDER_THREADS.0.lock().unwrap();
+            if let Ok(result) = rx.try_recv() {
+                return Ok(result?);
+            }
+            abandoned.store(true, Ordering::Relaxed);
+            threads.abandoned += 1;
+            vlog!("mermaid: gave up on a diagram after {:?} ({} still running)", budget, threads.abandoned);
+            Err(Failure::transient(format!("diagram took longer than {}s to render", budget.as_secs())))
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(Failure::from("mermaid renderer crashed".to_string())),
+    }
+}
+
+/// Most renderer threads alive at once, including ones given up on.
+fn max_render_threads() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get()).max(2)
+}
+
+/// Renderer threads alive, and how many of them were given up on.
+struct RenderThreads {
+    live: usize,
+    abandoned: usize,
+}
+
+static RENDER_THREADS: (Mutex<RenderThreads>, Condvar) = (Mutex::new(RenderThreads { live: 0, abandoned: 0 }), Condvar::new());
+
+/// Wait for a free renderer thread slot. Fails straight away if all but one
+/// are held by renders that ran over budget, rather than piling up threads
+/// behind diagrams that may never finish.
+fn acquire_render_thread() -> Result<(), String> {
+    let max = max_render_threads();
+    let (lock, freed) = &RENDER_THREADS;
+    let mut threads = lock.lock().unwrap();
+    loop {
+        if threads.abandoned >= max - 1 {
+            return Err(format!("{} diagrams that timed out are still rendering", threads.abandoned));
+        }
+        if threads.live < max {
+            threads.live += 1;
+            return Ok(());
+        }
+        threads = freed.wait(threads).unwrap();
+    }
+}
+
+fn release_render_thread(abandoned: bool) {
+    let (lock, freed) = &RENDER_THREADS;
+    let mut threads = lock.lock().unwrap();
+    threads.live -= 1;
+    if abandoned {
+        threads.abandoned -= 1;
+        vlog!("mermaid: a diagram that timed out finished rendering");
+    }
+    freed.notify_all();
+}
+
+thread_local! {
+    /// Set on renderer threads, whose panics are caught and reported as errors.
+    static QUIET_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keep panics in mermaid-rs-renderer, which are caught, from printing to
+/// stderr (and over the TUI). Only renderer threads are affected: panics
+/// anywhere else still reach the previous hook.
+fn install_quiet_panic_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if QUIET_PANICS.with(Cell::get) {
+                vlog!("mermaid: renderer panicked: {}", info);
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Render a single mermaid diagram source to SVG.
+/// First checks the source for syntax errors, which the renderer would draw as
+/// an empty diagram, and preprocesses it to fix common incompatibilities,
+/// then catches panics from mermaid-rs-renderer (which can panic on some inputs).
+fn render_uncached(source: &str, theme: Theme) -> Result<String, String> {
+    check(source).map_err(|e| e.to_string())?;
+    // Try with preprocessed source first (fixes common syntax issues)
+    let preprocessed = preprocess_mermaid_source(source);
+    let preprocessed_clone = preprocessed.clone();
+    match std::panic::catch_unwind(|| render_themed(&preprocessed_clone, theme)) {
+        Ok(Ok(svg)) => return Ok(svg),
+        _ => {}
+    }
+    // Fall back to original source (in case preprocessing made things worse)
+    let source = source.to_string();
+    match std::panic::catch_unwind(|| render_themed(&source, theme)) {
+        Ok(Ok(svg)) => Ok(svg),
+        Ok(Err(e)) => Err(e),
+        Err(panic) => {
+            let detail = panic.downcast_ref::<&str>().copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("no message");
+            Err(format!("mermaid renderer panicked (unsupported diagram syntax): {}", detail))
+        }
+    }
+}
+
+/// The renderer's pipeline, with the palette chosen from `theme` and the
+/// diagram's `%%{init: ...}%%` directive, which `mermaid_rs_renderer::render` ignores.
+fn render_themed(source: &str, theme: Theme) -> Result<String, String> {
+    let parsed = mermaid_rs_renderer::parse_mermaid(source).map_err(|e| e.to_string())?;
+    let palette = theme::palette(theme, parsed.init_config.as_ref());
+    let layout_config = mermaid_rs_renderer::LayoutConfig::default();
+    let layout = mermaid_rs_renderer::compute_layout(&parsed.graph, &palette, &layout_config);
+    Ok(mermaid_rs_renderer::render_svg(&layout, &palette, &layout_config))
+}
+
+/// Explain why `source` failed to render with `error`: the syntax error with
+/// its position if [`check`] finds one, otherwise the renderer's message.
+pub fn diagnose(source: &str, error: &str) -> MermaidError {
+    check(source).err().unwrap_or_else(|| MermaidError { message: error.to_string(), line: None, column: None })
+}
+
+/// Process HTML from comrak: find mermaid code blocks and replace with rendered SVG.
+/// Mermaid blocks appear as: <pre><code class="language-mermaid">...</code></pre>
+/// Each diagram is rendered in both themes, for the page to show the one that
+/// matches its colour scheme (see [`diagram_html`]).
+/// Without `pending`, diagrams are rendered before returning; with it, those
+/// not rendered yet are shown as a placeholder and added to `pending`.
+pub fn process_mermaid_blocks(html: &str, mut pending: Option<&mut Pending>) -> String {
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r#"<pre><code class="language-mermaid">([\s\S]*?)</code></pre>"#).unwrap());
+
+    let outputs = [Output::Svg(Theme::Light), Output::Svg(Theme::Dark)];
+    if pending.is_none() {
+        prerender(re.captures_iter(html).map(|caps| html_decode(&caps[1])), &outputs);
+    }
+    let mut index = 0;
+    re.replace_all(html, |caps: &regex::Captures| {
+        let source = html_decode(&caps[1]);
+        index += 1;
+        let [light, dark] = outputs.map(|output| output.lookup(&source, &mut pending));
+        match (light, dark) {
+            (Some(Ok(light)), Some(Ok(dark))) => diagram_html(index, &light, &dark),
+            (Some(Err(_)), _) | (_, Some(Err(_))) => match check(&source) {
+                Err(error) => error_html(&source, &error),
+                // Nothing wrong that we can see, so mermaid.js may manage where the renderer could not
+                Ok(()) => format!(
+                    r#"<pre class="mermaid">{}</pre>"#,
+                    html_encode(&source)
+                ),
+            },
+            _ => format!(
+                r#"<div class="mermaid-fallback mermaid-pending"><div class="mermaid-fallback-header"><span class="mermaid-icon">◇</span>Mermaid diagram (rendering…)</div><pre><code>{}</code></pre></div>"#,
+                html_encode(&source)
+            ),
+        }
+    })
+    .to_string()
+}
+
+/// The `index`th diagram on the page (counting from 1, and numbered so in
+/// `data-diagram` for saving it), drawn in both themes: the stylesheet shows
+/// the one matching the page's colour scheme. A diagram whose init
+/// directive names a theme looks the same in both, so appears once. Element ids
+/// are made unique to the copy, as the renderer numbers them from zero in
+/// every SVG and a reference would otherwise pick another diagram's markers.
+fn diagram_html(index: usize, light: &[u8], dark: &[u8]) -> String {
+    let scoped = |svg: &[u8], theme: Theme| {
+        let prefix = format!("mermaid-{}-{}-", index, theme.name());
+        String::from_utf8_lossy(svg)
+            .replace(" id=\"", &format!(" id=\"{}", prefix))
+            .replace("url(#", &format!("url(#{}", prefix))
+    };
+    if light == dark {
+        return format!(r#"<div class="mermaid-diagram" data-diagram="{}">{}</div>"#, index, scoped(light, Theme::Light));
+    }
+    format!(
+        r#"<div class="mermaid-diagram" data-diagram="{}"><div class="mermaid-light">{}</div><div class="mermaid-dark">{}</div></div>"#,
+        index,
+        scoped(light, Theme::Light),
+        scoped(dark, Theme::Dark)
+    )
+}
+
+/// An error box with the diagram source, its offending line marked.
+fn error_html(source: &str, error: &MermaidError) -> String {
+    let mut code = String::new();
+    for (i, line) in source.lines().enumerate() {
+        if error.line == Some(i + 1) {
+            code.push_str(&format!("<span class=\"mermaid-error-line\">{}</span>\n", html_encode(line)));
+            if let Some(caret) = error.caret() {
+                code.push_str(&format!("<span class=\"mermaid-error-caret\">{}</span>\n", caret));
+            }
+        } else {
+            code.push_str(&html_encode(line));
+            code.push('\n');
+        }
+    }
+    format!(
+        r#"<div class="mermaid-error"><strong>Mermaid error:</strong> {}<pre><code>{}</code></pre></div>"#,
+        html_encode(&error.to_string()),
+        code
+    )
+}
+
+/// Pre-process markdown for egui: replace each ```mermaid block with an HTML
+/// block naming the diagram, which the viewer draws from the cache (see
+/// [`egui_diagram`] and [`egui_diagram_png`]). The blocks do not depend on
+/// whether or how a diagram has rendered, so the markdown stays the same as
+/// diagrams finish in the background or the theme changes.
+#[cfg(feature = "egui-backend")]
+pub fn preprocess_mermaid_for_egui(markdown: &str) -> String {
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"```mermaid\n([\s\S]*?)```").unwrap());
+
+    let mut number = 0;
+    re.replace_all(markdown, |caps: &regex::Captures| {
+        number += 1;
+        format!(
+            "\n<div class=\"mermaid-diagram\" data-number=\"{}\" data-source=\"{}\"></div>\n\n",
+            number,
+            html_encode(&caps[1]).replace('\n', "&#10;")
+        )
+    })
+    .to_string()
+}
+
+/// The egui viewer's PNG for `source` in the colours of `theme`, or why it
+/// could not be rendered. `None` while it is rendering, after adding it to
+/// `pending` if it is not cached (or was evicted from memory).
+#[cfg(feature = "egui-backend")]
+pub fn egui_diagram_png(source: &str, theme: Theme, pending: &mut Pending) -> Option<Result<Arc<[u8]>, MermaidError>> {
+    let png = Output::Png(theme).lookup(source, &mut Some(pending))?;
+    Some(png.map_err(|e| match Output::Svg(theme).cached(source) {
+        // The SVG rendered, so converting it to PNG is what failed
+        Some(Ok(_)) => MermaidError { message: "SVG to PNG conversion failed".to_string(), line: None, column: None },
+        _ => diagnose(source, &e),
+    }))
+}
+
+/// The number (counting from 1) and source of a diagram from the HTML block
+/// [`preprocess_mermaid_for_egui`] put in its place; `None` for other HTML.
+#[cfg(feature = "egui-backend")]
+pub fn egui_diagram(html: &str) -> Option<(usize, String)> {
+    use std::sync::OnceLock;
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r#"^<div class="mermaid-diagram" data-number="(\d+)" data-source="([^"]*)"></div>$"#).unwrap());
+    let caps = re.captures(html.trim())?;
+    Some((caps[1].parse().ok()?, html_decode(&caps[2].replace("&#10;", "\n"))))
+}
+
+/// `source` with a `^` line under the offending column, for a code block.
+#[cfg(feature = "egui-backend")]
+pub fn with_caret(source: &str, error: &MermaidError) -> String {
+    let mut text = String::new();
+    for (i, line) in source.lines().enumerate() {
+        text.push_str(line);
+        text.push('\n');
+        if error.line == Some(i + 1) {
+            if let Some(caret) = error.caret() {
+                text.push_str(&caret);
+                text.push('\n');
+            }
+        }
+    }
+    text
+}
+
+/// Render the TUI's diagram for `source` as PNG in the colours of `theme`;
+/// `pending` works as in [`process_mermaid_blocks`], with `None` returned for
+/// a placeholder.
+#[cfg(feature = "tui-backend")]
+pub fn mermaid_png(source: &str, theme: Theme, mut pending: Option<&mut Pending>) -> Option<Result<Arc<[u8]>, String>> {
+    Output::Png(theme).lookup(source, &mut pending)
+}
+
+pub fn html_decode(s: &str) -> String {
+    s.replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+}
+
+pub fn html_encode(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // --- html_decode tests ---
+
+    #[test]
+    fn html_decode_all_entities() {
+        assert_eq!(html_decode("&amp;&lt;&gt;&quot;&#39;"), "&<>\"'");
+    }
+
+    #[test]
+    fn html_decode_no_entities() {
+        assert_eq!(html_decode("plain text"), "plain text");
+    }
+
+    #[test]
+    fn html_decode_mixed() {
+        assert_eq!(html_decode("A &amp; B &lt; C"), "A & B < C");
+    }
+
+    // --- html_encode tests ---
+
+    #[test]
+    fn html_encode_special_chars() {
+        assert_eq!(html_encode("A & B < C > D"), "A &amp; B &lt; C &gt; D");
+    }
+
+    #[test]
+    fn html_encode_no_special_chars() {
+        assert_eq!(html_encode("plain text"), "plain text");
+    }
+
+    #[test]
+    fn html_encode_decode_roundtrip() {
+        let original = "graph LR; A-->B";
+        let encoded = html_encode(original);
+        let decoded = html_decode(&encoded);
+        assert_eq!(decoded, original);
+    }
+
+    // --- preprocess_mermaid_source tests ---
+
+    #[test]
+    fn preprocess_removes_html_breaks() {
+        let source = "graph LR\n  A[Line 1<br/>Line 2]-->B";
+        let result = preprocess_mermaid_source(source);
+        assert!(!result.contains("<br/>"));
+        assert!(result.contains("Line 1 Line 2"));
+    }
+
+    #[test]
+    fn preprocess_converts_bidirectional_arrows() {
+        let source = "graph LR\n  A<-->B";
+        let result = preprocess_mermaid_source(source);
+        assert!(!result.contains("<-->"));
+        assert!(result.contains("A---B"));
+    }
+
+    #[test]
+    fn preprocess_leaves_valid_syntax_unchanged() {
+        let source = "graph LR\n  A-->B\n  B-->C";
+        let result = preprocess_mermaid_source(source);
+        assert!(result.contains("A-->B"));
+        assert!(result.contains("B-->C"));
+    }
+
+    // --- render_mermaid_to_svg tests ---
+
+    #[test]
+    fn render_mermaid_valid_diagram() {
+        let source = "graph LR\n  A-->B";
+        let result = render_mermaid_to_svg(source, Theme::Light);
+        // Should either succeed with SVG or fail with a descriptive error
+        // (depends on mermaid-rs-renderer capabilities at runtime)
+        match result {
+            Ok(svg) => {
+                assert!(svg.contains("<svg") || svg.contains("<SVG"),
+                    "Expected SVG output, got: {}", svg);
+            }
+            Err(e) => {
+                // If it errors, the error should be descriptive
+                assert!(!e.is_empty(), "Error message should not be empty");
+            }
+        }
+    }
+
+    #[test]
+    fn render_mermaid_empty_input() {
+        let result = render_mermaid_to_svg("", Theme::Light);
+        // Empty input should produce an error, not panic
+        assert!(result.is_err() || result.is_ok());
+    }
+
+    #[test]
+    fn render_mermaid_invalid_syntax() {
+        let result = render_mermaid_to_svg("this is not valid mermaid syntax at all %%% !@#", Theme::Light);
+        // Should not panic - catch_unwind protects us
+        // Result can be Ok or Err but must not panic
+        match result {
+            Ok(_) => {} // Some renderers may be lenient
+            Err(e) => assert!(!e.is_empty()),
+        }
+    }
+
+    #[test]
+    fn render_mermaid_panic_safety() {
+        // Test that catch_unwind works - even bizarre input doesn't crash
+        let result = render_mermaid_to_svg("\0\0\0", Theme::Light);
+        // Must not panic
+        let _ = result;
+    }
+
+    #[test]
+    fn diagrams_are_drawn_in_the_requested_theme() {
+        let source = "graph LR\n  Themed-->Diagram";
+        let light = render_mermaid_to_svg(source, Theme::Light).unwrap();
+        let dark = render_mermaid_to_svg(source, Theme::Dark).unwrap();
+        assert!(!light.contains("#0D1117"));
+        assert!(dark.contains(r##"fill="#0D1117""##), "got: {}", dark);
+    }
+
+    #[test]
+    fn init_directives_choose_the_theme() {
+        let source = "%%{init: {\"theme\": \"dark\"}}%%\ngraph LR\n  Directive-->Dark";
+        let light = render_mermaid_to_svg(source, Theme::Light).unwrap();
+        assert!(light.contains(r##"fill="#0D1117""##), "got: {}", light);
+        let source = "%%{init: {'themeVariables': {'primaryColor': '#ff8800'}}}%%\ngraph LR\n  Custom-->Colour";
+        assert!(render_mermaid_to_svg(source, Theme::Dark).unwrap().contains("#ff8800"));
+    }
+
+    // --- process_mermaid_blocks tests ---
+
+    #[test]
+    fn process_mermaid_blocks_no_mermaid() {
+        let html = "<p>Hello</p><pre><code class=\"language-rust\">fn main() {}</code></pre>";
+        let result = process_mermaid_blocks(html, None);
+        assert_eq!(result, html);
+    }
+
+    #[test]
+    fn process_mermaid_blocks_replaces_mermaid_code() {
+        let html = r#"<p>Before</p><pre><code class="language-mermaid">graph LR
+  A--&gt;B</code></pre><p>After</p>"#;
+        let result = process_mermaid_blocks(html, None);
+        // The mermaid code block should be replaced
+        assert!(!result.contains(r#"class="language-mermaid""#),
+            "Mermaid code block should be replaced, got: {}", result);
+        // Should contain either a rendered diagram or an error
+        assert!(
+            result.contains("mermaid-diagram") || result.contains("mermaid-error") || result.contains("mermaid-fallback"),
+            "Should contain diagram or fallback div, got: {}",
+            result
+        );
+        // Surrounding content should be preserved
+        assert!(result.contains("<p>Before</p>"));
+        assert!(result.contains("<p>After</p>"));
+    }
+
+    #[test]
+    fn process_mermaid_blocks_preserves_non_mermaid_content() {
+        let html = "<h1>Title</h1><p>Content</p>";
+        let result = process_mermaid_blocks(html, None);
+        assert_eq!(result, html);
+    }
+
+    #[test]
+    fn process_mermaid_blocks_error_contains_source() {
+        // Use obviously invalid mermaid that will produce an error
+        let html = r#"<pre><code class="language-mermaid">not valid %%% !@#</code></pre>"#;
+        let result = process_mermaid_blocks(html, None);
+        if result.contains("mermaid-fallback") {
+            // Fallback div should contain the original source
+            assert!(result.contains("Mermaid Diagram"));
+        } else if result.contains("mermaid-error") {
+            assert!(result.contains("Mermaid error:"));
+        }
+        // If it somehow renders successfully, that's also fine
+    }
+
+    #[test]
+    fn runaway_renders_hold_their_thread_until_they_finish() {
+        let (finish, finished) = mpsc::channel::<()>();
+        let result = run_with_budget(Duration::from_millis(50), move || {
+            let _ = finished.recv();
+            Ok("late".to_string())
+        });
+        let failure = result.unwrap_err();
+        assert_eq!(failure.message, "diagram took longer than 0s to render");
+        assert!(failure.transient, "a timeout is not remembered as the diagram's result");
+        assert!(RENDER_THREADS.0.lock().unwrap().abandoned >= 1);
+
+        finish.send(()).unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while RENDER_THREADS.0.lock().unwrap().abandoned > 0 {
+            assert!(std::time::Instant::now() < deadline, "the runaway thread should give its slot back");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn transient_failures_are_queued_again_after_a_while() {
+        // A source no other test uses, so it is not cached
+        let source = "graph LR\n  Timed-->Out\n";
+        let job = (source.to_string(), Output::Svg(Theme::Dark));
+        RETRIES.lock().unwrap().push((job.clone(), "diagram took longer than 5s to render".to_string(), Instant::now() + Duration::from_millis(50)));
+        let mut pending = Pending::default();
+        let shown = Output::Svg(Theme::Dark).lookup(source, &mut Some(&mut pending));
+        assert_eq!(shown.unwrap().unwrap_err(), "diagram took longer than 5s to render");
+        assert!(pending.sources.is_empty(), "not queued again straight away");
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(Output::Svg(Theme::Dark).lookup(source, &mut Some(&mut pending)).is_none());
+        assert!(pending.sources == [job]);
+    }
+
+    #[test]
+    fn renderer_panics_are_errors_and_stay_off_stderr() {
+        let result = run_with_budget(Duration::from_secs(5), || {
+            assert!(QUIET_PANICS.with(Cell::get), "renderer threads keep panics quiet");
+            std::panic::catch_unwind(|| panic!("bad diagram")).map_err(|_| "caught".to_string())
+        });
+        let failure = result.unwrap_err();
+        assert_eq!(failure.message, "caught");
+        assert!(!failure.transient);
+        assert!(!QUIET_PANICS.with(Cell::get), "other threads still report panics");
+    }
+
+    #[test]
+    #[cfg(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    fn deferred_diagrams_show_a_placeholder_until_rendered() {
+        // A source no other test uses, so it is not cached yet
+        let html = r#"<pre><code class="language-mermaid">graph LR
+  Deferred--&gt;Placeholder</code></pre>"#;
+        let mut pending = Pending::default();
+        let result = process_mermaid_blocks(html, Some(&mut pending));
+        assert!(result.contains("mermaid-pending"), "got: {}", result);
+        assert!(result.contains("Deferred--&gt;Placeholder"));
+        assert!(!pending.sources.is_empty());
+
+        let (tx, rx) = mpsc::channel();
+        pending.spawn(move || {
+            let _ = tx.send(());
+        });
+        // Once for each theme
+        for _ in 0..2 {
+            rx.recv_timeout(Duration::from_secs(30)).expect("background render should finish");
+        }
+
+        let mut pending = Pending::default();
+        let result = process_mermaid_blocks(html, Some(&mut pending));
+        assert!(pending.sources.is_empty(), "the diagram should be cached now");
+        assert!(!result.contains("mermaid-pending"));
+    }
+
+    #[test]
+    fn syntax_errors_are_shown_in_place_with_the_line_marked() {
+        let html = r#"<pre><code class="language-mermaid">graph LR
+  A[Start --&gt; B</code></pre>"#;
+        let result = process_mermaid_blocks(html, None);
+        assert!(result.contains("<strong>Mermaid error:</strong> line 2, column 4: `[` is never closed"), "got: {}", result);
+        assert!(result.contains(r#"<span class="mermaid-error-line">  A[Start --&gt; B</span>"#));
+        assert!(result.contains(r#"<span class="mermaid-error-caret">   ^</span>"#));
+        assert!(!result.contains(r#"class="mermaid""#), "no mermaid.js fallback for syntax errors");
+    }
+
+    #[test]
+    fn pages_get_both_themes_with_their_own_ids() {
+        let html = r#"<pre><code class="language-mermaid">graph LR
+  Both--&gt;Themes</code></pre><pre><code class="language-mermaid">%%{init: {"theme": "forest"}}%%
+graph LR
+  One--&gt;Theme</code></pre>"#;
+        let result = process_mermaid_blocks(html, None);
+        assert_eq!(result.matches(r#"<div class="mermaid-light">"#).count(), 1, "got: {}", result);
+        assert_eq!(result.matches(r#"<div class="mermaid-dark">"#).count(), 1);
+        assert!(result.contains(r#"id="mermaid-1-light-arrow-0""#));
+        assert!(result.contains(r#"url(#mermaid-1-dark-arrow-0)"#));
+        assert!(result.contains(r#"id="mermaid-2-light-arrow-0""#));
+        assert!(!result.contains(r#"id="arrow-0""#));
+        assert!(result.contains(r#"<div class="mermaid-diagram" data-diagram="2">"#));
+    }
+
+    // --- egui-specific tests ---
+
+    #[cfg(feature = "egui-backend")]
+    mod egui_tests {
+        use super::super::*;
+
+        #[test]
+        fn preprocess_mermaid_for_egui_no_mermaid() {
+            let md = "# Title\n\nSome text\n\n```rust\nfn main() {}\n```";
+            let result = preprocess_mermaid_for_egui(md);
+            assert_eq!(result, md);
+        }
+
+        #[test]
+        fn preprocess_mermaid_for_egui_replaces_block() {
+            let md = "Before\n\n```mermaid\ngraph LR\n  A-->B\n```\n\nAfter";
+            let result = preprocess_mermaid_for_egui(md);
+            // The mermaid block should be replaced with an HTML block for the viewer
+            assert!(!result.contains("```mermaid"),
+                "Mermaid block should be replaced, got: {}", result);
+            assert!(result.contains("Before"));
+            assert!(result.contains("After"));
+        }
+
+        #[test]
+        fn rendered_diagrams_can_be_found_from_their_html() {
+            let md = "```mermaid\nnot valid mermaid\n```\n\n```mermaid\ngraph LR\n  A[\"a & b\"]-->B\n```\nAfter";
+            let result = preprocess_mermaid_for_egui(md);
+            let html = result.lines().filter(|line| line.starts_with("<div")).nth(1).expect("an HTML block for each diagram");
+            assert_eq!(egui_diagram(html), Some((2, "graph LR\n  A[\"a & b\"]-->B\n".to_string())));
+            assert!(result.contains("</div>\n\n\nAfter"), "the HTML block should end before the text, got: {}", result);
+            assert_eq!(egui_diagram("<div>other</div>"), None);
+        }
+
+        #[test]
+        fn preprocess_mermaid_for_egui_error_shows_source() {
+            let md = "```mermaid\nnot valid mermaid\n```";
+            let result = preprocess_mermaid_for_egui(md);
+            assert!(result.contains("not valid mermaid"));
+        }
+
+        #[test]
+        fn egui_diagram_png_marks_the_error() {
+            let source = "graph LR\n  A --> B\n  B -->\n";
+            let mut pending = Pending::default();
+            assert!(egui_diagram_png(source, Theme::Dark, &mut pending).is_none(), "rendered in the background first");
+            assert_eq!(pending.sources.len(), 1);
+            let _ = Output::Png(Theme::Dark).render(source);
+            let error = egui_diagram_png(source, Theme::Dark, &mut Pending::default()).unwrap().unwrap_err();
+            assert_eq!(error.to_string(), "line 3, column 5: edge has no target node");
+            assert!(with_caret(source, &error).ends_with("  B -->\n    ^\n"));
+        }
+
+        #[test]
+        fn egui_diagram_png_is_cached_once_rendered() {
+            let source = "graph LR\n  Egui-->Swapped\n";
+            let _ = Output::Png(Theme::Light).render(source);
+            let mut pending = Pending::default();
+            let png = egui_diagram_png(source, Theme::Light, &mut pending).unwrap().unwrap();
+            assert!(png.starts_with(b"\x89PNG"));
+            assert!(pending.is_empty());
+        }
+    }
+}
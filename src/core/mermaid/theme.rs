@@ -0,0 +1,202 @@
+//! Colours for natively rendered diagrams: mdr's light and dark schemes, the
+//! named Mermaid themes an `%%{init: {"theme": ...}}%%` directive can ask for,
+//! and the `themeVariables` it can override.
+
+use mermaid_rs_renderer::Theme as Palette;
+use serde_json::Value;
+
+use crate::vlog;
+
+/// The mdr colour scheme a diagram is drawn to match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+/// The palette for a diagram drawn in `theme`, unless its init directive
+/// (`init`) names a Mermaid theme of its own, with any `themeVariables` applied.
+pub(super) fn palette(theme: Theme, init: Option<&Value>) -> Palette {
+    let named = init.and_then(|init| init.get("theme")).and_then(Value::as_str);
+    let mut palette = match named {
+        None => mdr(theme),
+        Some("default" | "base" | "mermaid") => Palette::mermaid_default(),
+        Some("modern") => Palette::modern(),
+        Some("dark") => mdr(Theme::Dark),
+        Some("forest") => forest(),
+        Some("neutral") => neutral(),
+        Some(other) => {
+            vlog!("mermaid: unknown theme '{}', using mdr's {} theme", other, theme.name());
+            mdr(theme)
+        }
+    };
+    if let Some(variables) = init.and_then(|init| init.get("themeVariables")) {
+        apply_variables(&mut palette, variables);
+    }
+    palette
+}
+
+/// mdr's own palettes: the renderer's modern theme, and a dark one in the
+/// colours of the dark page stylesheet.
+fn mdr(theme: Theme) -> Palette {
+    let mut palette = Palette::modern();
+    if theme == Theme::Light {
+        return palette;
+    }
+    let set = |field: &mut String, value: &str| *field = value.to_string();
+    set(&mut palette.primary_color, "#161B22");
+    set(&mut palette.primary_text_color, "#E6EDF3");
+    set(&mut palette.primary_border_color, "#3D444D");
+    set(&mut palette.line_color, "#8B949E");
+    set(&mut palette.secondary_color, "#21262D");
+    set(&mut palette.tertiary_color, "#0D1117");
+    set(&mut palette.edge_label_background, "#0D1117");
+    set(&mut palette.cluster_background, "#11161D");
+    set(&mut palette.cluster_border, "#30363D");
+    set(&mut palette.background, "#0D1117");
+    set(&mut palette.sequence_actor_fill, "#161B22");
+    set(&mut palette.sequence_actor_border, "#3D444D");
+    set(&mut palette.sequence_actor_line, "#8B949E");
+    set(&mut palette.sequence_note_fill, "#2A2414");
+    set(&mut palette.sequence_note_border, "#6E5A1E");
+    set(&mut palette.sequence_activation_fill, "#21262D");
+    set(&mut palette.sequence_activation_border, "#8B949E");
+    set(&mut palette.text_color, "#E6EDF3");
+    set(&mut palette.pie_title_text_color, "#E6EDF3");
+    set(&mut palette.pie_section_text_color, "#E6EDF3");
+    set(&mut palette.pie_legend_text_color, "#E6EDF3");
+    set(&mut palette.pie_stroke_color, "#0D1117");
+    set(&mut palette.pie_outer_stroke_color, "#30363D");
+    palette.pie_colors = [
+        "#1F6FEB", "#238636", "#8957E5", "#BF8700", "#DA3633", "#1B7C83",
+        "#BF4B8A", "#6E7681", "#388BFD", "#2EA043", "#A371F7", "#D29922",
+    ]
+    .map(String::from);
+    palette
+}
+
+/// An approximation of Mermaid's green "forest" theme.
+fn forest() -> Palette {
+    let mut palette = Palette::mermaid_default();
+    palette.primary_color = "#CDE498".to_string();
+    palette.primary_border_color = "#13540C".to_string();
+    palette.line_color = "#008000".to_string();
+    palette.secondary_color = "#CDFFB2".to_string();
+    palette.tertiary_color = "#F0F8E6".to_string();
+    palette.cluster_background = "#CDFFB2".to_string();
+    palette.cluster_border = "#6EAA49".to_string();
+    palette.sequence_actor_fill = "#CDE498".to_string();
+    palette.sequence_actor_border = "#13540C".to_string();
+    palette
+}
+
+/// An approximation of Mermaid's greyscale "neutral" theme.
+fn neutral() -> Palette {
+    let mut palette = Palette::mermaid_default();
+    palette.primary_color = "#EEEEEE".to_string();
+    palette.primary_border_color = "#999999".to_string();
+    palette.line_color = "#666666".to_string();
+    palette.secondary_color = "#F4F4F4".to_string();
+    palette.tertiary_color = "#FFFFFF".to_string();
+    palette.cluster_background = "#F4F4F4".to_string();
+    palette.cluster_border = "#999999".to_string();
+    palette.sequence_actor_fill = "#EEEEEE".to_string();
+    palette.sequence_actor_border = "#999999".to_string();
+    palette
+}
+
+/// The palette field a theme variable sets.
+type Field = fn(&mut Palette) -> &mut String;
+
+/// The `themeVariables` an init directive can set, and the field each sets.
+const VARIABLES: &[(&str, Field)] = &[
+    ("primaryColor", |p| &mut p.primary_color),
+    ("primaryTextColor", |p| &mut p.primary_text_color),
+    ("primaryBorderColor", |p| &mut p.primary_border_color),
+    ("lineColor", |p| &mut p.line_color),
+    ("secondaryColor", |p| &mut p.secondary_color),
+    ("tertiaryColor", |p| &mut p.tertiary_color),
+    ("textColor", |p| &mut p.text_color),
+    ("edgeLabelBackground", |p| &mut p.edge_label_background),
+    ("clusterBkg", |p| &mut p.cluster_background),
+    ("clusterBorder", |p| &mut p.cluster_border),
+    ("background", |p| &mut p.background),
+    ("actorBkg", |p| &mut p.sequence_actor_fill),
+    ("actorBorder", |p| &mut p.sequence_actor_border),
+    ("actorLine", |p| &mut p.sequence_actor_line),
+    ("noteBkgColor", |p| &mut p.sequence_note_fill),
+    ("noteBorderColor", |p| &mut p.sequence_note_border),
+    ("activationBkgColor", |p| &mut p.sequence_activation_fill),
+    ("activationBorderColor", |p| &mut p.sequence_activation_border),
+    ("pieTitleTextColor", |p| &mut p.pie_title_text_color),
+    ("pieSectionTextColor", |p| &mut p.pie_section_text_color),
+    ("pieLegendTextColor", |p| &mut p.pie_legend_text_color),
+    ("pieStrokeColor", |p| &mut p.pie_stroke_color),
+    ("pieOuterStrokeColor", |p| &mut p.pie_outer_stroke_color),
+    ("fontFamily", |p| &mut p.font_family),
+];
+
+fn apply_variables(palette: &mut Palette, variables: &Value) {
+    for (name, field) in VARIABLES {
+        if let Some(value) = variables.get(name).and_then(Value::as_str) {
+            *field(palette) = value.to_string();
+        }
+    }
+    for (i, color) in palette.pie_colors.iter_mut().enumerate() {
+        if let Some(value) = variables.get(format!("pie{}", i + 1)).and_then(Value::as_str) {
+            *color = value.to_string();
+        }
+    }
+    // Given as a number or as CSS ("16px")
+    let font_size = variables.get("fontSize").and_then(|size| match size {
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        Value::String(s) => s.trim_end_matches("px").trim().parse().ok(),
+        _ => None,
+    });
+    if let Some(size) = font_size.filter(|size| *size > 0.0) {
+        palette.font_size = size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn follows_the_mdr_theme_without_a_directive() {
+        assert_eq!(palette(Theme::Light, None).background, Palette::modern().background);
+        assert_eq!(palette(Theme::Dark, None).background, "#0D1117");
+        assert_eq!(palette(Theme::Dark, Some(&json!({ "flowchart": {} }))).background, "#0D1117");
+    }
+
+    #[test]
+    fn a_named_theme_wins_over_the_mdr_theme() {
+        let init = json!({ "theme": "default" });
+        assert_eq!(palette(Theme::Dark, Some(&init)).primary_color, Palette::mermaid_default().primary_color);
+        let init = json!({ "theme": "dark" });
+        assert_eq!(palette(Theme::Light, Some(&init)).background, "#0D1117");
+        let init = json!({ "theme": "no-such-theme" });
+        assert_eq!(palette(Theme::Dark, Some(&init)).background, "#0D1117");
+    }
+
+    #[test]
+    fn theme_variables_override_the_palette() {
+        let init = json!({ "theme": "forest", "themeVariables": { "primaryColor": "#ff0000", "pie2": "#00ff00", "fontSize": "18px" } });
+        let palette = palette(Theme::Light, Some(&init));
+        assert_eq!(palette.primary_color, "#ff0000");
+        assert_eq!(palette.primary_border_color, "#13540C");
+        assert_eq!(palette.pie_colors[1], "#00ff00");
+        assert_eq!(palette.font_size, 18.0);
+    }
+}
@@ -1,9 +1,25 @@
+pub mod assets;
+pub mod ast;
+pub mod cache;
+pub mod diff;
+pub mod document;
+pub mod export;
+#[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+pub mod history;
+pub mod html;
+#[cfg(any(feature = "egui-backend", feature = "webview-backend"))]
 pub mod icon;
 pub mod markdown;
+pub mod math;
 pub mod mermaid;
+pub mod raster;
+#[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
 pub mod search;
+pub mod serve;
+pub mod stdin;
 pub mod toc;
 pub mod watcher;
+pub mod workspace;
 
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use base64::Engine;
+
+/// Largest width or height, in pixels, of a rasterized SVG. Keeps well under
+/// GPU texture limits (16384) and bounds memory use.
+pub const MAX_DIM: f32 = 8192.0;
+
+/// System fonts for SVG text, loaded once and shared by every rasterization.
+fn fontdb() -> Arc<usvg::fontdb::Database> {
+    static FONTDB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    Arc::clone(FONTDB.get_or_init(|| {
+        let mut db = usvg::fontdb::Database::new();
+        db.load_system_fonts();
+        Arc::new(db)
+    }))
+}
+
+/// Rasterize SVG markup at `scale`, shrinking it further if needed so that
+/// neither side exceeds [`MAX_DIM`].
+pub fn rasterize_svg(svg: &str, scale: f32) -> Result<tiny_skia::Pixmap, Box<dyn std::error::Error>> {
+    let options = usvg::Options { fontdb: fontdb(), ..usvg::Options::default() };
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let size = tree.size();
+    let (svg_w, svg_h) = (size.width(), size.height());
+    if svg_w <= 0.0 || svg_h <= 0.0 {
+        return Err("SVG has zero dimensions".into());
+    }
+
+    let scale = scale.min(MAX_DIM / svg_w).min(MAX_DIM / svg_h);
+    let width = (svg_w * scale) as u32;
+    let height = (svg_h * scale) as u32;
+    if width == 0 || height == 0 {
+        return Err("SVG dimensions too small after scaling".into());
+    }
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or("Failed to create pixmap")?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+/// Rasterize SVG markup to PNG bytes (see [`rasterize_svg`]).
+pub fn svg_to_png(svg: &str, scale: f32) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(rasterize_svg(svg, scale)?.encode_png()?)
+}
+
+/// Rasterize an SVG file to PNG and return it as a base64 data URI, at 2x for
+/// high-DPI screens.
+/// This is safer than inlining SVG because SVG can contain scripts, links, and styles
+/// that would execute in the page context and cause unwanted navigation/requests.
+/// Returns Err if the file is not a valid SVG (e.g., an HTML page saved with .svg extension).
+pub fn svg_file_to_png_data_uri(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let svg_data = std::fs::read_to_string(path)?;
+
+    // Reject files that aren't actually SVG (e.g. HTML pages saved with .svg extension)
+    let trimmed = svg_data.trim_start();
+    if (!trimmed.starts_with('<') || trimmed.starts_with("<!DOCTYPE html") || trimmed.starts_with("<html"))
+        && !trimmed.contains("<svg")
+    {
+        return Err("File is not a valid SVG (possibly an HTML page)".into());
+    }
+
+    let png_data = svg_to_png(&svg_data, 2.0)?;
+    Ok(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(&png_data)))
+}
+
+/// Convert a local file to a base64 data URI string.
+pub fn file_to_data_uri(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let mime = match ext.to_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        _ => "application/octet-stream",
+    };
+    let data = std::fs::read(path)?;
+    let b64 = base64::engine::general_purpose::STANDARD.encode(&data);
+    Ok(format!("data:{};base64,{}", mime, b64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CIRCLE: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="50" height="20"><circle cx="10" cy="10" r="8" fill="blue"/></svg>"#;
+
+    #[test]
+    fn rasterize_svg_scales_and_caps_size() {
+        let pixmap = rasterize_svg(CIRCLE, 2.0).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (100, 40));
+
+        let huge = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20000" height="100"></svg>"#;
+        let pixmap = rasterize_svg(huge, 1.0).unwrap();
+        assert_eq!(pixmap.width(), MAX_DIM as u32);
+
+        assert!(rasterize_svg("<html>not svg</html>", 1.0).is_err());
+    }
+
+    #[test]
+    fn svg_file_to_png_data_uri_rejects_html() {
+        let dir = std::env::temp_dir().join("mdr_test_raster_html");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("page.svg"), "<!DOCTYPE html><html><body>404</body></html>").unwrap();
+        std::fs::write(dir.join("ok.svg"), CIRCLE).unwrap();
+
+        assert!(svg_file_to_png_data_uri(&dir.join("page.svg")).is_err());
+        assert!(svg_file_to_png_data_uri(&dir.join("ok.svg")).unwrap().starts_with("data:image/png;base64,"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
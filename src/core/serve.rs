@@ -0,0 +1,265 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::core::document::Document;
+use crate::core::export::render_html;
+use crate::core::html::enable_follow;
+use crate::core::watcher::FileWatcher;
+use crate::vlog;
+
+/// Address used by `--serve` when none is given.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// How often idle event streams get a comment line, so proxies and port
+/// forwards do not time them out and dead clients are noticed.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// How long a client may take to send each part of its request before the
+/// connection, and the thread serving it, is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest request line and headers accepted; longer requests get a 431.
+const MAX_HEAD_BYTES: u64 = 8 * 1024;
+
+/// Re-fetches the page when the server announces a change and morphs in the
+/// new content and TOC with `mdrUpdate`, which keeps the reader's place.
+const LIVE_RELOAD_JS: &str = r#"<script>
+(function() {
+    var source = new EventSource('/events');
+    source.addEventListener('reload', function() {
+        fetch('/', { cache: 'no-store' })
+            .then(function(r) { return r.text(); })
+            .then(function(html) {
+                var doc = new DOMParser().parseFromString(html, 'text/html');
+                window.mdrUpdate(doc.querySelector('.content').innerHTML, doc.querySelector('.sidebar > ul').innerHTML);
+                document.title = doc.title;
+            });
+    });
+})();
+</script>"#;
+
+/// Parse a `--serve` address. A bare port binds to localhost.
+pub fn parse_addr(addr: &str) -> Result<SocketAddr, String> {
+    let addr = if addr.chars().all(|c| c.is_ascii_digit()) {
+        format!("127.0.0.1:{}", addr)
+    } else {
+        addr.to_string()
+    };
+    addr.to_socket_addrs()
+        .map_err(|e| format!("invalid address '{}': {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("invalid address '{}'", addr))
+}
+
+/// Serve the rendered document over HTTP until the process is killed.
+/// The page is rendered once and again whenever the file or an image it shows
+/// changes, and browsers connected to `/events` are then told to reload.
+/// With `follow`, pages start in follow mode.
+pub fn serve(file_path: &Path, addr: SocketAddr, follow: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(addr)?;
+    let (mut watcher, watcher_rx) = FileWatcher::new(file_path)?;
+    let mut document = Document::open(file_path)?;
+    watcher.watch_assets(document.asset_paths());
+    let page = Arc::new(RwLock::new(render_page(&document, follow)));
+    let clients: Arc<Mutex<Vec<Sender<()>>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let broadcast = Arc::clone(&clients);
+    let rendered = Arc::clone(&page);
+    std::thread::spawn(move || {
+        while watcher_rx.recv().is_ok() {
+            // On error the previous version keeps being served
+            if document.reload().is_ok() {
+                watcher.watch_assets(document.asset_paths());
+                let html = render_page(&document, follow);
+                if let Ok(mut page) = rendered.write() {
+                    *page = html;
+                }
+            }
+            if let Ok(mut clients) = broadcast.lock() {
+                vlog!("serve: file changed, notifying {} client(s)", clients.len());
+                clients.retain(|client| client.send(()).is_ok());
+            }
+        }
+    });
+
+    eprintln!("Serving {} at http://{}", file_path.display(), listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                vlog!("serve: accept failed: {}", e);
+                continue;
+            }
+        };
+        let page = Arc::clone(&page);
+        let clients = Arc::clone(&clients);
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &page, &clients) {
+                vlog!("serve: connection error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// The page served at `/`: the exported HTML with the live reload client.
+fn render_page(document: &Document, follow: bool) -> String {
+    let html = render_html(document);
+    let html = if follow { enable_follow(&html) } else { html };
+    inject_live_reload(&html)
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    page: &RwLock<String>,
+    clients: &Mutex<Vec<Sender<()>>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut head = BufReader::new(stream.try_clone()?).take(MAX_HEAD_BYTES);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    // Drain the headers; nothing in them changes the response
+    let mut header = request_line.clone();
+    while header.ends_with('\n') && !header.trim_end().is_empty() {
+        header.clear();
+        head.read_line(&mut header)?;
+    }
+    // A line without its newline was cut off by the limit or the client hanging up
+    if !header.ends_with('\n') {
+        return if head.limit() == 0 {
+            respond(&mut stream, "431 Request Header Fields Too Large", "text/plain", b"Request header too large\n")
+        } else {
+            respond(&mut stream, "400 Bad Request", "text/plain", b"Incomplete request\n")
+        };
+    }
+
+    let Some(path) = request_path(&request_line) else {
+        return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"Method not allowed\n");
+    };
+    vlog!("serve: GET {}", path);
+    match path {
+        "/" | "/index.html" => {
+            // Clone rather than hold the lock while writing to a slow client
+            let html = page.read().map(|page| page.clone()).unwrap_or_default();
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", html.as_bytes())
+        }
+        "/events" => {
+            let (tx, rx) = mpsc::channel();
+            if let Ok(mut clients) = clients.lock() {
+                clients.push(tx);
+            }
+            stream_events(&mut stream, rx)
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"Not found\n"),
+    }
+}
+
+/// The path of a GET request, without any query string.
+fn request_path(request_line: &str) -> Option<&str> {
+    let mut parts = request_line.split_whitespace();
+    if parts.next()? != "GET" {
+        return None;
+    }
+    let target = parts.next()?;
+    Some(target.split(['?', '#']).next().unwrap_or(target))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Hold a Server-Sent Events stream open, sending a `reload` event per change.
+/// Returns once the client disconnects (detected on the next write).
+fn stream_events(stream: &mut TcpStream, rx: Receiver<()>) -> Result<(), Box<dyn std::error::Error>> {
+    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\nConnection: keep-alive\r\n\r\n")?;
+    stream.write_all(b": connected\n\n")?;
+    stream.flush()?;
+    loop {
+        let message: &[u8] = match rx.recv_timeout(KEEPALIVE) {
+            Ok(()) => b"event: reload\ndata: \n\n",
+            Err(RecvTimeoutError::Timeout) => b": keepalive\n\n",
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        stream.write_all(message)?;
+        stream.flush()?;
+    }
+}
+
+/// Add the live reload client just before `</body>`, and let the page's
+/// Content-Security-Policy allow it to connect back to this server.
+fn inject_live_reload(html: &str) -> String {
+    let html = html.replacen("default-src 'none';", "default-src 'none'; connect-src 'self';", 1);
+    match html.rfind("</body>") {
+        Some(pos) => format!("{}{}\n{}", &html[..pos], LIVE_RELOAD_JS, &html[pos..]),
+        None => html + LIVE_RELOAD_JS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addr_accepts_port_or_address() {
+        assert_eq!(parse_addr("9000").unwrap(), "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(parse_addr("0.0.0.0:8080").unwrap(), "0.0.0.0:8080".parse().unwrap());
+        assert!(parse_addr("not an address").is_err());
+    }
+
+    #[test]
+    fn request_path_strips_query() {
+        assert_eq!(request_path("GET /?t=1 HTTP/1.1\r\n"), Some("/"));
+        assert_eq!(request_path("GET /events HTTP/1.1\r\n"), Some("/events"));
+        assert_eq!(request_path("POST / HTTP/1.1\r\n"), None);
+        assert_eq!(request_path(""), None);
+    }
+
+    /// Send `request` to [`handle_connection`] and return the response.
+    fn exchange(request: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let handler = std::thread::spawn(move || {
+            let _ = handle_connection(server, &RwLock::new("<p>page</p>".to_string()), &Mutex::new(Vec::new()));
+        });
+        // The server may answer and close before reading everything
+        let _ = client.write_all(request);
+        let _ = client.shutdown(std::net::Shutdown::Write);
+        let mut response = String::new();
+        let _ = client.read_to_string(&mut response);
+        handler.join().unwrap();
+        response
+    }
+
+    #[test]
+    fn oversized_or_incomplete_requests_are_refused() {
+        let ok = exchange(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(ok.starts_with("HTTP/1.1 200 OK") && ok.ends_with("<p>page</p>"), "{}", ok);
+
+        let mut long = b"GET / HTTP/1.1\r\nX-Padding: ".to_vec();
+        long.extend(std::iter::repeat_n(b'a', 16 * 1024));
+        assert!(exchange(&long).starts_with("HTTP/1.1 431 "));
+        assert!(exchange(&[b'a'; 16 * 1024]).starts_with("HTTP/1.1 431 "));
+
+        assert!(exchange(b"GET / HTTP/1.1\r\nHost: local").starts_with("HTTP/1.1 400 "));
+    }
+
+    #[test]
+    fn live_reload_script_goes_before_body_end() {
+        let html = inject_live_reload(r#"<meta content="default-src 'none'; img-src data:;"><body><p>hi</p></body></html>"#);
+        assert!(html.contains("default-src 'none'; connect-src 'self'; img-src data:;"));
+        assert!(html.contains("new EventSource('/events')"));
+        assert!(html.ends_with("</script>\n</body></html>"));
+    }
+}
@@ -0,0 +1,178 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::vlog;
+
+/// Path that stands for piped stdin wherever a document path is expected.
+pub const STDIN_PATH: &str = "-";
+
+/// How long to gather incoming chunks before viewers re-render. Streams that
+/// never pause (token-by-token output) still re-render at this rate.
+const THROTTLE: Duration = Duration::from_millis(150);
+
+/// Markdown read from stdin so far, shared with the backends.
+struct Stream {
+    state: Mutex<StreamState>,
+    finished: Condvar,
+    subscribers: Mutex<Vec<Sender<()>>>,
+}
+
+struct StreamState {
+    content: String,
+    done: bool,
+}
+
+static STREAM: OnceLock<Stream> = OnceLock::new();
+
+/// Whether `path` refers to the stdin stream rather than a file.
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+}
+
+/// Start reading stdin into memory on a background thread and return the
+/// path backends should open. Subscribers are signalled as chunks arrive.
+pub fn start() -> PathBuf {
+    let stream = STREAM.get_or_init(|| Stream {
+        state: Mutex::new(StreamState { content: String::new(), done: false }),
+        finished: Condvar::new(),
+        subscribers: Mutex::new(Vec::new()),
+    });
+
+    let (chunk_tx, chunk_rx) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0u8; 8192];
+        let mut pending = Vec::new();
+        loop {
+            let n = match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    vlog!("stdin: read failed: {}", e);
+                    break;
+                }
+            };
+            pending.extend_from_slice(&buf[..n]);
+            let text = decode_utf8(&mut pending);
+            if let Ok(mut state) = stream.state.lock() {
+                state.content.push_str(&text);
+            }
+            let _ = chunk_tx.send(());
+        }
+        if let Ok(mut state) = stream.state.lock() {
+            // A truncated multi-byte sequence at EOF is kept as U+FFFD
+            state.content.push_str(&String::from_utf8_lossy(&pending));
+            state.done = true;
+            vlog!("stdin: reached EOF after {} bytes", state.content.len());
+        }
+        stream.finished.notify_all();
+        let _ = chunk_tx.send(());
+    });
+
+    // Coalesce chunk signals so a fast stream does not re-render on every read
+    std::thread::spawn(move || {
+        while chunk_rx.recv().is_ok() {
+            std::thread::sleep(THROTTLE);
+            while chunk_rx.try_recv().is_ok() {}
+            if let Ok(mut subscribers) = stream.subscribers.lock() {
+                subscribers.retain(|tx| tx.send(()).is_ok());
+            }
+        }
+    });
+
+    PathBuf::from(STDIN_PATH)
+}
+
+/// Get a signal each time more of stdin has been read, or `None` when stdin
+/// is not being streamed.
+pub fn subscribe() -> Option<Receiver<()>> {
+    let stream = STREAM.get()?;
+    let (tx, rx) = mpsc::channel();
+    stream.subscribers.lock().ok()?.push(tx);
+    Some(rx)
+}
+
+/// Block until stdin has been read to the end, for one-shot output modes.
+pub fn wait_for_eof() {
+    let Some(stream) = STREAM.get() else { return };
+    let Ok(mut state) = stream.state.lock() else { return };
+    while !state.done {
+        state = match stream.finished.wait(state) {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+    }
+}
+
+/// Read a document's Markdown: the stdin buffer for [`STDIN_PATH`], otherwise
+/// the file at `path`.
+pub fn read_markdown(path: &Path) -> std::io::Result<String> {
+    match STREAM.get() {
+        Some(stream) if is_stdin(path) => Ok(stream.state.lock()
+            .map(|state| state.content.clone())
+            .unwrap_or_default()),
+        _ => std::fs::read_to_string(path),
+    }
+}
+
+/// Take the longest valid UTF-8 prefix out of `pending`, leaving an
+/// incomplete trailing sequence for the next chunk. Invalid bytes are replaced.
+fn decode_utf8(pending: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut start = 0;
+    while start < pending.len() {
+        match std::str::from_utf8(&pending[start..]) {
+            Ok(rest) => {
+                text.push_str(rest);
+                start = pending.len();
+            }
+            Err(e) => {
+                let valid = start + e.valid_up_to();
+                text.push_str(std::str::from_utf8(&pending[start..valid]).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        start = valid + len;
+                    }
+                    None => {
+                        start = valid;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    pending.drain(..start);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_utf8_keeps_split_sequences_for_next_chunk() {
+        let bytes = "é→".as_bytes();
+        let mut pending = bytes[..3].to_vec();
+        assert_eq!(decode_utf8(&mut pending), "é");
+        assert_eq!(pending, &bytes[2..3]);
+        pending.extend_from_slice(&bytes[3..]);
+        assert_eq!(decode_utf8(&mut pending), "→");
+        assert!(pending.is_empty());
+
+        let mut pending = b"a\xffb".to_vec();
+        assert_eq!(decode_utf8(&mut pending), "a\u{fffd}b");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn stdin_path_is_recognised() {
+        assert!(is_stdin(Path::new("-")));
+        assert!(!is_stdin(Path::new("./-")));
+        assert!(!is_stdin(Path::new("README.md")));
+    }
+}
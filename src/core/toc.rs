@@ -1,6 +1,7 @@
-use comrak::{parse_document, Arena, Options};
 use comrak::nodes::NodeValue;
 
+use crate::core::ast::Node;
+
 #[derive(Debug, Clone)]
 pub struct TocEntry {
     pub level: u8,
@@ -8,21 +9,12 @@ pub struct TocEntry {
     pub anchor: String,
 }
 
-/// Extract table of contents entries from markdown content.
-pub fn extract_toc(content: &str) -> Vec<TocEntry> {
-    let arena = Arena::new();
-    let mut options = Options::default();
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.autolink = true;
-    options.extension.tasklist = true;
-    options.extension.footnotes = true;
-
-    let root = parse_document(&arena, content, &options);
+/// Extract table of contents entries from a parsed document.
+pub fn extract_toc(root: &Node) -> Vec<TocEntry> {
     let mut entries = Vec::new();
 
     for node in root.descendants() {
-        if let NodeValue::Heading(heading) = &node.data.borrow().value {
+        if let NodeValue::Heading(heading) = node.value() {
             let level = heading.level;
             let text = collect_text(node);
             let anchor = slugify(&text);
@@ -34,13 +26,13 @@ pub fn extract_toc(content: &str) -> Vec<TocEntry> {
 }
 
 /// Collect all text content from a node and its children.
-fn collect_text<'a>(node: &'a comrak::arena_tree::Node<'a, std::cell::RefCell<comrak::nodes::Ast>>) -> String {
+fn collect_text(node: &Node) -> String {
     let mut text = String::new();
     for child in node.descendants() {
-        if let NodeValue::Text(ref t) = child.data.borrow().value {
+        if let NodeValue::Text(t) = child.value() {
             text.push_str(t);
         }
-        if let NodeValue::Code(ref c) = child.data.borrow().value {
+        if let NodeValue::Code(c) = child.value() {
             text.push_str(&c.literal);
         }
     }
@@ -111,21 +103,25 @@ mod tests {
 
     // --- extract_toc tests ---
 
+    fn toc(md: &str) -> Vec<TocEntry> {
+        extract_toc(&Node::parse(md))
+    }
+
     #[test]
     fn extract_toc_empty_input() {
-        let entries = extract_toc("");
+        let entries = toc("");
         assert!(entries.is_empty());
     }
 
     #[test]
     fn extract_toc_no_headings() {
-        let entries = extract_toc("Just some paragraph text.\n\nAnother paragraph.");
+        let entries = toc("Just some paragraph text.\n\nAnother paragraph.");
         assert!(entries.is_empty());
     }
 
     #[test]
     fn extract_toc_single_h1() {
-        let entries = extract_toc("# Hello World");
+        let entries = toc("# Hello World");
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].level, 1);
         assert_eq!(entries[0].text, "Hello World");
@@ -135,7 +131,7 @@ mod tests {
     #[test]
     fn extract_toc_multiple_levels() {
         let md = "# Title\n## Section\n### Subsection\n#### Deep";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries.len(), 4);
         assert_eq!(entries[0].level, 1);
         assert_eq!(entries[1].level, 2);
@@ -146,7 +142,7 @@ mod tests {
     #[test]
     fn extract_toc_heading_with_inline_code() {
         let md = "# The `main` function";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].text, "The main function");
     }
@@ -154,7 +150,7 @@ mod tests {
     #[test]
     fn extract_toc_heading_with_special_chars() {
         let md = "## Hello, World! (2024)";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].anchor, "hello-world-2024");
     }
@@ -162,7 +158,7 @@ mod tests {
     #[test]
     fn extract_toc_mixed_content_and_headings() {
         let md = "Some intro text.\n\n# First\n\nParagraph here.\n\n## Second\n\nMore text.";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries.len(), 2);
         assert_eq!(entries[0].text, "First");
         assert_eq!(entries[1].text, "Second");
@@ -171,7 +167,7 @@ mod tests {
     #[test]
     fn extract_toc_h5_and_h6() {
         let md = "##### Level 5\n###### Level 6";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries.len(), 2);
         assert_eq!(entries[0].level, 5);
         assert_eq!(entries[1].level, 6);
@@ -180,7 +176,7 @@ mod tests {
     #[test]
     fn extract_toc_preserves_order() {
         let md = "## B\n# A\n### C";
-        let entries = extract_toc(md);
+        let entries = toc(md);
         assert_eq!(entries[0].text, "B");
         assert_eq!(entries[1].text, "A");
         assert_eq!(entries[2].text, "C");
@@ -1,31 +1,593 @@
-use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
-use std::path::Path;
-use std::sync::mpsc::{self, Receiver};
+use notify::{PollWatcher, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer_opt, Config, DebounceEventResult, Debouncer};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
-/// Start watching a file for changes with 300ms debounce.
-/// Returns a Receiver that gets a () signal on each change.
-pub fn watch_file(path: &Path) -> Result<Receiver<()>, Box<dyn std::error::Error>> {
-    let (tx, rx) = mpsc::channel();
-    let path = path.canonicalize()?;
-    let watch_path = path.clone();
-
-    let mut debouncer = new_debouncer(Duration::from_millis(300), move |res: Result<Vec<notify_debouncer_mini::DebouncedEvent>, notify::Error>| {
-        if let Ok(events) = res {
-            for event in &events {
-                if event.kind == DebouncedEventKind::Any && event.path == path {
+use crate::core::stdin;
+use crate::vlog;
+
+/// Quiet period before a burst of filesystem events is reported.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often the polling fallback rescans watched directories. Polling compares
+/// modification times, so it sees at most one change per file per second.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Filesystem types where native change notifications miss edits made on
+/// another machine or by a container's host, so polling is used instead.
+const REMOTE_FILESYSTEMS: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "virtiofs", "vboxsf", "drvfs",
+    "fuse.sshfs", "fuse.grpcfuse", "fakeowner",
+];
+
+static FORCE_POLLING: AtomicBool = AtomicBool::new(false);
+
+/// Make every watcher poll instead of using native notifications (`--poll`).
+pub fn set_polling(poll: bool) {
+    FORCE_POLLING.store(poll, Ordering::Relaxed);
+}
+
+type Handler = Arc<dyn Fn(DebounceEventResult) + Send + Sync>;
+
+/// A debouncer over native filesystem events, or over periodic polling where
+/// native events are unavailable or unreliable.
+enum Debounced {
+    Native(Debouncer<RecommendedWatcher>),
+    Poll(Debouncer<PollWatcher>),
+}
+
+impl Debounced {
+    /// Start a debouncer calling `handler`. Native events are preferred unless
+    /// `poll` is set; polling is used if they cannot be set up.
+    fn start(handler: Handler, poll: bool) -> Result<Self, notify::Error> {
+        if !poll {
+            let native = Arc::clone(&handler);
+            match new_debouncer_opt::<_, RecommendedWatcher>(Config::default().with_timeout(DEBOUNCE), move |res| native(res)) {
+                Ok(debouncer) => return Ok(Debounced::Native(debouncer)),
+                Err(e) => vlog!("watcher: native events unavailable ({}), polling instead", e),
+            }
+        }
+        Self::poll(handler)
+    }
+
+    fn poll(handler: Handler) -> Result<Self, notify::Error> {
+        let config = Config::default()
+            .with_timeout(DEBOUNCE)
+            .with_notify_config(notify::Config::default().with_poll_interval(POLL_INTERVAL));
+        Ok(Debounced::Poll(new_debouncer_opt::<_, PollWatcher>(config, move |res| handler(res))?))
+    }
+
+    fn is_polling(&self) -> bool {
+        matches!(self, Debounced::Poll(_))
+    }
+
+    fn watch(&mut self, dir: &Path, mode: RecursiveMode) -> notify::Result<()> {
+        match self {
+            Debounced::Native(debouncer) => debouncer.watcher().watch(dir, mode),
+            Debounced::Poll(debouncer) => debouncer.watcher().watch(dir, mode),
+        }
+    }
+
+    fn unwatch(&mut self, dir: &Path) {
+        let _ = match self {
+            Debounced::Native(debouncer) => debouncer.watcher().unwatch(dir),
+            Debounced::Poll(debouncer) => debouncer.watcher().unwatch(dir),
+        };
+    }
+}
+
+/// Watches the displayed file, and the local assets it renders, for changes
+/// with a 300ms debounce.
+///
+/// Directories are watched rather than files, so saves that replace the file
+/// (write to a temp file, then rename over it) and files deleted then
+/// recreated keep being noticed. A symlinked path is tracked both as opened
+/// and through its current target, and is re-resolved when either changes.
+/// The target can be switched when the user navigates to another document;
+/// change signals keep arriving on the same Receiver. While the target is
+/// [`stdin::STDIN_PATH`], each newly read chunk of stdin counts as a change.
+///
+/// Watching stops on [`FileWatcher::shutdown`] or when the watcher is dropped,
+/// after which the Receiver disconnects.
+pub struct FileWatcher {
+    shared: Arc<Shared>,
+}
+
+/// State shared between the watcher handle and the debouncer's event thread.
+struct Shared {
+    debouncer: Mutex<Option<Debounced>>,
+    state: Mutex<WatchState>,
+    handler: Handler,
+}
+
+struct WatchState {
+    /// Absolute path of the document as opened; may be a symlink.
+    document: PathBuf,
+    /// What `document` currently resolves to.
+    resolved: PathBuf,
+    assets: Vec<PathBuf>,
+    watched_dirs: Vec<PathBuf>,
+    /// Taken on shutdown, which disconnects the Receiver.
+    tx: Option<Sender<()>>,
+}
+
+impl WatchState {
+    fn matches(&self, path: &Path) -> bool {
+        path == self.document || path == self.resolved || self.assets.iter().any(|asset| asset == path)
+    }
+
+    /// Directories to watch, the document's own first.
+    fn dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![watch_dir(&self.document)];
+        if self.resolved != self.document {
+            dirs.push(watch_dir(&self.resolved));
+        }
+        dirs.extend(self.assets.iter().filter_map(|asset| asset.parent().map(Path::to_path_buf)));
+        dirs
+    }
+}
+
+impl FileWatcher {
+    /// Start watching `path`. Returns the watcher and a Receiver that gets a
+    /// () signal on each change. Paths on network and container filesystems,
+    /// and every path after [`set_polling`], are polled.
+    pub fn new(path: &Path) -> Result<(Self, Receiver<()>), Box<dyn std::error::Error>> {
+        let document = absolute(path)?;
+        let poll = FORCE_POLLING.load(Ordering::Relaxed) || on_remote_filesystem(&resolve_target(&document));
+        Self::with_polling(path, poll)
+    }
+
+    fn with_polling(path: &Path, poll: bool) -> Result<(Self, Receiver<()>), Box<dyn std::error::Error>> {
+        let (tx, rx) = mpsc::channel();
+        let document = absolute(path)?;
+        let state = WatchState {
+            resolved: resolve_target(&document),
+            document,
+            assets: Vec::new(),
+            watched_dirs: Vec::new(),
+            tx: Some(tx),
+        };
+        let shared = Arc::new_cyclic(|weak: &Weak<Shared>| {
+            let weak = weak.clone();
+            let handler: Handler = Arc::new(move |res| {
+                if let Some(shared) = weak.upgrade() {
+                    shared.handle(res);
+                }
+            });
+            Shared { debouncer: Mutex::new(None), state: Mutex::new(state), handler }
+        });
+        let debounced = Debounced::start(Arc::clone(&shared.handler), poll)?;
+        if debounced.is_polling() {
+            vlog!("watcher: polling {} every {:?}", path.display(), POLL_INTERVAL);
+        }
+        *shared.debouncer.lock().map_err(|_| "watcher state poisoned")? = Some(debounced);
+        shared.sync_watches()?;
+
+        if let Some(stdin_rx) = stdin::subscribe() {
+            let weak = Arc::downgrade(&shared);
+            std::thread::spawn(move || {
+                while stdin_rx.recv().is_ok() {
+                    let Some(shared) = weak.upgrade() else { break };
+                    let Ok(state) = shared.state.lock() else { break };
+                    let Some(tx) = &state.tx else { break };
+                    if stdin::is_stdin(&state.document) && tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Ok((FileWatcher { shared }, rx))
+    }
+
+    /// Switch to watching a different file, which need not exist yet. Its
+    /// assets are set separately with [`FileWatcher::watch_assets`] once it
+    /// has been read.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn retarget(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let document = absolute(path)?;
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.resolved = resolve_target(&document);
+            state.document = document;
+            state.assets.clear();
+        }
+        self.shared.sync_watches()
+    }
+
+    /// Also reload when any of `assets` (see [`crate::core::assets::local_assets`])
+    /// changes, replacing the assets of the previous version of the document.
+    pub fn watch_assets(&mut self, assets: Vec<PathBuf>) {
+        if let Ok(mut state) = self.shared.state.lock() {
+            if state.assets == assets {
+                return;
+            }
+            vlog!("watcher: tracking {} asset(s) of {}", assets.len(), state.document.display());
+            state.assets = assets;
+        }
+        if let Err(e) = self.shared.sync_watches() {
+            vlog!("watcher: {}", e);
+        }
+    }
+
+    /// Stop watching and disconnect the Receiver. Dropping the watcher does
+    /// the same; calling this more than once is harmless.
+    pub fn shutdown(&mut self) {
+        if let Ok(mut debouncer) = self.shared.debouncer.lock() {
+            debouncer.take();
+        }
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.tx = None;
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl Shared {
+    fn handle(&self, res: DebounceEventResult) {
+        let events = match res {
+            Ok(events) => events,
+            Err(e) => {
+                vlog!("watcher: {}", e);
+                return;
+            }
+        };
+        let relinked = {
+            let Ok(mut state) = self.state.lock() else { return };
+            if !events.iter().any(|event| state.matches(&event.path)) {
+                return;
+            }
+            // A symlink may have been re-pointed, or replaced by a regular file on save
+            let resolved = resolve_target(&state.document);
+            let relinked = resolved != state.resolved;
+            if relinked {
+                vlog!("watcher: {} now resolves to {}", state.document.display(), resolved.display());
+                state.resolved = resolved;
+            }
+            if let Some(tx) = &state.tx {
+                let _ = tx.send(());
+            }
+            relinked
+        };
+        if relinked {
+            if let Err(e) = self.sync_watches() {
+                vlog!("watcher: {}", e);
+            }
+        }
+    }
+
+    /// Watch the directories holding the document, its symlink target and its
+    /// assets, and stop watching directories no longer needed. Only a failure
+    /// to watch the document's own directory is an error; if native watching
+    /// fails there, polling is tried first.
+    fn sync_watches(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut debouncer = self.debouncer.lock().map_err(|_| "watcher state poisoned")?;
+        let Some(debounced) = debouncer.as_mut() else { return Ok(()) };
+        let (wanted, mut watched) = {
+            let mut state = self.state.lock().map_err(|_| "watcher state poisoned")?;
+            (state.dirs(), std::mem::take(&mut state.watched_dirs))
+        };
+
+        let mut result = watch_dirs(debounced, &wanted, &mut watched);
+        if let Err(e) = &result {
+            if !debounced.is_polling() {
+                vlog!("watcher: cannot watch {} natively ({}), polling instead", wanted[0].display(), e);
+                *debounced = Debounced::poll(Arc::clone(&self.handler))?;
+                watched.clear();
+                result = watch_dirs(debounced, &wanted, &mut watched);
+            }
+        }
+
+        if let Ok(mut state) = self.state.lock() {
+            state.watched_dirs = watched;
+        }
+        Ok(result?)
+    }
+}
+
+/// Bring the set of `watched` directories in line with `wanted`. Fails only if
+/// the first wanted directory cannot be watched.
+fn watch_dirs(debounced: &mut Debounced, wanted: &[PathBuf], watched: &mut Vec<PathBuf>) -> notify::Result<()> {
+    watched.retain(|dir| {
+        let keep = wanted.contains(dir);
+        if !keep {
+            debounced.unwatch(dir);
+        }
+        keep
+    });
+    for (i, dir) in wanted.iter().enumerate() {
+        if watched.contains(dir) {
+            continue;
+        }
+        match debounced.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => watched.push(dir.clone()),
+            Err(e) if i == 0 => return Err(e),
+            Err(e) => vlog!("watcher: cannot watch {}: {}", dir.display(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Make a document path absolute without resolving symlinks, leaving the
+/// stdin stream's path as is.
+fn absolute(path: &Path) -> std::io::Result<PathBuf> {
+    if stdin::is_stdin(path) {
+        Ok(path.to_path_buf())
+    } else {
+        std::path::absolute(path)
+    }
+}
+
+/// Where a document path currently leads: its canonical path, the target of
+/// a dangling symlink, or the path itself if it does not exist.
+fn resolve_target(document: &Path) -> PathBuf {
+    if stdin::is_stdin(document) {
+        return document.to_path_buf();
+    }
+    document.canonicalize()
+        .or_else(|_| std::fs::read_link(document).map(|target| match document.parent() {
+            Some(dir) => dir.join(target),
+            None => target,
+        }))
+        .unwrap_or_else(|_| document.to_path_buf())
+}
+
+/// Directory to watch for changes to `path`; stdin has no file, so the
+/// current directory stands in until the viewer navigates to one.
+fn watch_dir(path: &Path) -> PathBuf {
+    if stdin::is_stdin(path) {
+        std::env::current_dir().unwrap_or_default()
+    } else {
+        path.parent().unwrap_or(path).to_path_buf()
+    }
+}
+
+/// Whether `path` lives on a filesystem listed in [`REMOTE_FILESYSTEMS`].
+/// Only known on Linux; elsewhere native events are trusted.
+fn on_remote_filesystem(path: &Path) -> bool {
+    match std::fs::read_to_string("/proc/self/mounts") {
+        Ok(mounts) => mount_fstype(&mounts, path).is_some_and(|fstype| {
+            let remote = REMOTE_FILESYSTEMS.contains(&fstype);
+            if remote {
+                vlog!("watcher: {} is on {}, which needs polling", path.display(), fstype);
+            }
+            remote
+        }),
+        Err(_) => false,
+    }
+}
+
+/// Filesystem type of the mount holding `path`, from `/proc/self/mounts`
+/// contents. The deepest mount point wins, and the latest of equal ones.
+fn mount_fstype<'a>(mounts: &'a str, path: &Path) -> Option<&'a str> {
+    mounts.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?.replace("\\040", " ");
+            Some((mount_point, fields.next()?))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, fstype)| fstype)
+}
+
+/// Watches a workspace directory recursively and signals when Markdown files
+/// or directories may have been added, renamed or removed. Receivers should
+/// rescan the tree; content changes to the open file come from [`FileWatcher`].
+#[cfg(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+pub struct TreeWatcher {
+    _debounced: Debounced,
+}
+
+#[cfg(any(feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+impl TreeWatcher {
+    pub fn new(root: &Path) -> Result<(Self, Receiver<()>), Box<dyn std::error::Error>> {
+        let (tx, rx) = mpsc::channel();
+        let root = root.canonicalize()?;
+
+        let event_root = root.clone();
+        let handler: Handler = Arc::new(move |res| {
+            if let Ok(events) = res {
+                if events.iter().any(|event| affects_tree(&event_root, &event.path)) {
                     let _ = tx.send(());
-                    return;
                 }
             }
+        });
+        let poll = FORCE_POLLING.load(Ordering::Relaxed) || on_remote_filesystem(&root);
+        let mut debounced = Debounced::start(Arc::clone(&handler), poll)?;
+        if let Err(e) = debounced.watch(&root, RecursiveMode::Recursive) {
+            // Large trees can exhaust the inotify watch limit
+            if debounced.is_polling() {
+                return Err(e.into());
+            }
+            vlog!("watcher: cannot watch {} natively ({}), polling instead", root.display(), e);
+            debounced = Debounced::poll(handler)?;
+            debounced.watch(&root, RecursiveMode::Recursive)?;
         }
-    })?;
 
-    let parent = watch_path.parent().unwrap_or(&watch_path);
-    debouncer.watcher().watch(parent, notify::RecursiveMode::NonRecursive)?;
+        Ok((TreeWatcher { _debounced: debounced }, rx))
+    }
+}
+
+/// Whether an event on `path` could change the list of Markdown files under `root`.
+/// Deleted paths can no longer be inspected, so anything missing counts.
+#[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+fn affects_tree(root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    if relative.components().any(|c| c.as_os_str() == ".git") {
+        return false;
+    }
+    crate::core::workspace::is_markdown_path(path)
+        || path.is_dir()
+        || !path.exists()
+        || path.file_name().is_some_and(|name| name == ".gitignore" || name == ".ignore")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNAL_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A fresh, canonical temp directory for one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    /// Wait for a change signal, then swallow any that trail it.
+    fn expect_signal(rx: &Receiver<()>, what: &str) {
+        assert!(rx.recv_timeout(SIGNAL_TIMEOUT).is_ok(), "{} not signalled", what);
+        while rx.recv_timeout(DEBOUNCE * 2).is_ok() {}
+    }
+
+    fn watched_dirs(watcher: &FileWatcher) -> Vec<PathBuf> {
+        watcher.shared.state.lock().unwrap().watched_dirs.clone()
+    }
+
+    #[test]
+    fn tree_events_skip_git_and_unrelated_files() {
+        let root = std::env::temp_dir().join("mdr_test_tree_events");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("notes.txt"), "x").unwrap();
+
+        assert!(affects_tree(&root, &root.join("new.md")));
+        assert!(affects_tree(&root, &root.join("sub")));
+        assert!(affects_tree(&root, &root.join(".gitignore")));
+        assert!(!affects_tree(&root, &root.join("notes.txt")));
+        assert!(!affects_tree(&root, &root.join(".git/index")));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn file_watcher_signals_asset_changes() {
+        let dir = test_dir("mdr_test_watch_assets");
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("doc.md"), "![d](img/d.png)\n").unwrap();
+        std::fs::write(dir.join("img/d.png"), b"old").unwrap();
+
+        let (mut watcher, rx) = FileWatcher::new(&dir.join("doc.md")).unwrap();
+        watcher.watch_assets(vec![dir.join("img/d.png")]);
+        assert_eq!(watched_dirs(&watcher), vec![dir.clone(), dir.join("img")]);
+
+        std::fs::write(dir.join("img/d.png"), b"new").unwrap();
+        expect_signal(&rx, "asset change");
+
+        watcher.retarget(&dir.join("doc.md")).unwrap();
+        assert_eq!(watched_dirs(&watcher), vec![dir.clone()]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn file_watcher_signals_atomic_saves() {
+        let dir = test_dir("mdr_test_watch_atomic_save");
+        std::fs::write(dir.join("doc.md"), "# One\n").unwrap();
+        let (_watcher, rx) = FileWatcher::new(&dir.join("doc.md")).unwrap();
+
+        // Editors write a temp file and rename it over the original
+        std::fs::write(dir.join(".doc.md.swp"), "# Two\n").unwrap();
+        std::fs::rename(dir.join(".doc.md.swp"), dir.join("doc.md")).unwrap();
+        expect_signal(&rx, "rename over the file");
+
+        std::fs::write(dir.join("doc.md"), "# Three\n").unwrap();
+        expect_signal(&rx, "write after the rename");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn file_watcher_signals_deleted_and_recreated_files() {
+        let dir = test_dir("mdr_test_watch_recreate");
+        std::fs::write(dir.join("doc.md"), "# One\n").unwrap();
+        let (_watcher, rx) = FileWatcher::new(&dir.join("doc.md")).unwrap();
+
+        std::fs::remove_file(dir.join("doc.md")).unwrap();
+        expect_signal(&rx, "deletion");
+        std::fs::write(dir.join("doc.md"), "# Two\n").unwrap();
+        expect_signal(&rx, "recreation");
+        std::fs::write(dir.join("doc.md"), "# Three\n").unwrap();
+        expect_signal(&rx, "write to the recreated file");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_watcher_follows_symlink_targets() {
+        let dir = test_dir("mdr_test_watch_symlink");
+        std::fs::create_dir_all(dir.join("real")).unwrap();
+        std::fs::create_dir_all(dir.join("links")).unwrap();
+        std::fs::write(dir.join("real/a.md"), "# A\n").unwrap();
+        std::fs::write(dir.join("real/b.md"), "# B\n").unwrap();
+        let link = dir.join("links/doc.md");
+        std::os::unix::fs::symlink("../real/a.md", &link).unwrap();
+
+        let (watcher, rx) = FileWatcher::new(&link).unwrap();
+        assert_eq!(watched_dirs(&watcher), vec![dir.join("links"), dir.join("real")]);
+        std::fs::write(dir.join("real/a.md"), "# A2\n").unwrap();
+        expect_signal(&rx, "write to the link target");
+
+        // Re-point the link the way `ln -sf` does
+        std::os::unix::fs::symlink("../real/b.md", dir.join("links/.doc.md.new")).unwrap();
+        std::fs::rename(dir.join("links/.doc.md.new"), &link).unwrap();
+        expect_signal(&rx, "re-pointed link");
+        std::fs::write(dir.join("real/b.md"), "# B2\n").unwrap();
+        expect_signal(&rx, "write to the new link target");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn file_watcher_polling_signals_changes() {
+        let dir = test_dir("mdr_test_watch_polling");
+        std::fs::write(dir.join("doc.md"), "# One\n").unwrap();
+        let (watcher, rx) = FileWatcher::with_polling(&dir.join("doc.md"), true).unwrap();
+        assert!(watcher.shared.debouncer.lock().unwrap().as_ref().is_some_and(Debounced::is_polling));
+
+        // Polling compares modification times, which have one-second resolution
+        std::thread::sleep(Duration::from_millis(1100));
+        std::fs::write(dir.join("doc.md"), "# Two\n").unwrap();
+        expect_signal(&rx, "polled change");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn file_watcher_shutdown_disconnects_receiver() {
+        let dir = test_dir("mdr_test_watch_shutdown");
+        std::fs::write(dir.join("doc.md"), "# One\n").unwrap();
+        let (mut watcher, rx) = FileWatcher::new(&dir.join("doc.md")).unwrap();
+
+        watcher.shutdown();
+        watcher.shutdown();
+        std::fs::write(dir.join("doc.md"), "# Two\n").unwrap();
+        assert_eq!(rx.recv_timeout(SIGNAL_TIMEOUT), Err(mpsc::RecvTimeoutError::Disconnected));
 
-    // Leak the debouncer so it lives for the program duration
-    std::mem::forget(debouncer);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 
-    Ok(rx)
+    #[test]
+    fn mount_fstype_picks_deepest_mount() {
+        let mounts = "overlay / overlay rw 0 0\n\
+                      server:/export /mnt/docs nfs4 rw 0 0\n\
+                      /dev/sda1 /mnt/docs/local ext4 rw 0 0\n\
+                      host /mnt/my\\040share 9p rw 0 0\n";
+        assert_eq!(mount_fstype(mounts, Path::new("/home/me/README.md")), Some("overlay"));
+        assert_eq!(mount_fstype(mounts, Path::new("/mnt/docs/guide.md")), Some("nfs4"));
+        assert_eq!(mount_fstype(mounts, Path::new("/mnt/docs/local/a.md")), Some("ext4"));
+        assert_eq!(mount_fstype(mounts, Path::new("/mnt/my share/a.md")), Some("9p"));
+        assert_eq!(mount_fstype(mounts, Path::new("/mnt/docsx/a.md")), Some("overlay"));
+    }
 }
@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+/// A directory of Markdown files opened with `mdr <dir>`.
+/// Files are discovered recursively, skipping hidden entries and anything
+/// excluded by `.gitignore` / `.ignore` files.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    root: PathBuf,
+    /// Absolute paths, sorted by their path relative to `root`.
+    files: Vec<PathBuf>,
+}
+
+/// One row of the file tree sidebar.
+#[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeEntry {
+    pub depth: usize,
+    pub name: String,
+    /// The file to open, or `None` for a directory heading.
+    pub path: Option<PathBuf>,
+}
+
+impl Workspace {
+    pub fn open(root: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let root = root.canonicalize()?;
+        let files = scan(&root);
+        Ok(Workspace { root, files })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Re-read the directory tree. Returns true if the set of files changed.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn rescan(&mut self) -> bool {
+        let files = scan(&self.root);
+        if files == self.files {
+            return false;
+        }
+        self.files = files;
+        true
+    }
+
+    /// The file to show first: the shallowest README, else the first file.
+    pub fn default_file(&self) -> Option<&PathBuf> {
+        self.files.iter()
+            .filter(|path| {
+                path.file_stem()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|s| s.eq_ignore_ascii_case("readme"))
+            })
+            .min_by_key(|path| path.components().count())
+            .or_else(|| self.files.first())
+    }
+
+    /// Path of `file` relative to the workspace root, for display.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn relative<'a>(&self, file: &'a Path) -> &'a Path {
+        file.strip_prefix(&self.root).unwrap_or(file)
+    }
+
+    /// Files and their parent directories as an indented tree, in display order.
+    #[cfg(any(test, feature = "egui-backend", feature = "webview-backend", feature = "tui-backend"))]
+    pub fn tree(&self) -> Vec<TreeEntry> {
+        let mut entries = Vec::new();
+        let mut open_dirs: Vec<String> = Vec::new();
+        for file in &self.files {
+            let relative = self.relative(file);
+            let mut parts: Vec<String> = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let name = parts.pop().unwrap_or_default();
+
+            // Close directories this file is not in, then open new ones
+            let common = open_dirs.iter().zip(&parts).take_while(|(a, b)| a == b).count();
+            open_dirs.truncate(common);
+            for dir in &parts[common..] {
+                entries.push(TreeEntry { depth: open_dirs.len(), name: dir.clone(), path: None });
+                open_dirs.push(dir.clone());
+            }
+            entries.push(TreeEntry { depth: parts.len(), name, path: Some(file.clone()) });
+        }
+        entries
+    }
+}
+
+/// Whether a path has a Markdown file extension.
+pub fn is_markdown_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("md") || e.eq_ignore_ascii_case("markdown"))
+}
+
+fn scan(root: &Path) -> Vec<PathBuf> {
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(true)
+        // Honour .gitignore even when the folder is not (yet) a git repository
+        .require_git(false)
+        .build();
+    let mut files: Vec<PathBuf> = walker
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| entry.into_path())
+        .filter(|path| is_markdown_path(path))
+        .collect();
+    // Files before subdirectories at each level, then alphabetical
+    files.sort_by_key(|path| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let mut key: Vec<(bool, String)> = relative.components()
+            .map(|c| (true, c.as_os_str().to_string_lossy().to_lowercase()))
+            .collect();
+        if let Some(last) = key.last_mut() {
+            last.0 = false;
+        }
+        key
+    });
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "# doc\n").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn scan_finds_markdown_and_respects_gitignore() {
+        let dir = make_tree("mdr_test_workspace_scan", &[
+            "b.md", "a.markdown", "notes.txt", "guide/intro.md", "build/out.md", ".hidden/secret.md",
+        ]);
+        std::fs::write(dir.join(".gitignore"), "build/\n").unwrap();
+
+        let workspace = Workspace::open(&dir).unwrap();
+        let names: Vec<String> = workspace.files().iter()
+            .map(|f| workspace.relative(f).display().to_string())
+            .collect();
+        assert_eq!(names, vec!["a.markdown", "b.md", "guide/intro.md"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn default_file_prefers_shallowest_readme() {
+        let dir = make_tree("mdr_test_workspace_readme", &["a.md", "docs/README.md", "readme.md"]);
+        let workspace = Workspace::open(&dir).unwrap();
+        assert_eq!(workspace.relative(workspace.default_file().unwrap()), Path::new("readme.md"));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let dir = make_tree("mdr_test_workspace_first", &["z.md", "docs/a.md"]);
+        let workspace = Workspace::open(&dir).unwrap();
+        assert_eq!(workspace.relative(workspace.default_file().unwrap()), Path::new("z.md"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tree_lists_directories_before_their_files() {
+        let dir = make_tree("mdr_test_workspace_tree", &["index.md", "guide/a.md", "guide/deep/b.md", "ref/c.md"]);
+        let workspace = Workspace::open(&dir).unwrap();
+        let rows: Vec<(usize, String, bool)> = workspace.tree().into_iter()
+            .map(|e| (e.depth, e.name, e.path.is_some()))
+            .collect();
+        assert_eq!(rows, vec![
+            (0, "index.md".to_string(), true),
+            (0, "guide".to_string(), false),
+            (1, "a.md".to_string(), true),
+            (1, "deep".to_string(), false),
+            (2, "b.md".to_string(), true),
+            (0, "ref".to_string(), false),
+            (1, "c.md".to_string(), true),
+        ]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rescan_reports_added_and_removed_files() {
+        let dir = make_tree("mdr_test_workspace_rescan", &["a.md"]);
+        let mut workspace = Workspace::open(&dir).unwrap();
+        assert!(!workspace.rescan());
+
+        std::fs::write(dir.join("b.md"), "# b\n").unwrap();
+        assert!(workspace.rescan());
+        assert_eq!(workspace.files().len(), 2);
+
+        std::fs::rename(dir.join("a.md"), dir.join("c.md")).unwrap();
+        assert!(workspace.rescan());
+        let names: Vec<_> = workspace.files().iter().map(|f| workspace.relative(f).to_path_buf()).collect();
+        assert_eq!(names, vec![PathBuf::from("b.md"), PathBuf::from("c.md")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -2,14 +2,16 @@ mod backend;
 mod core;
 
 use clap::Parser;
-use std::io::{self, IsTerminal, Read};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process;
 
+use core::export::DiagramFormat;
+
 #[derive(Parser)]
 #[command(name = "mdr", version, about = "Lightweight Markdown viewer with live reload")]
 struct Cli {
-    /// Markdown file to render (use '-' or pipe via stdin)
+    /// Markdown file or directory to render (use '-' or pipe via stdin)
     file: Option<PathBuf>,
 
     /// Rendering backend to use: egui (native GUI), webview (HTML), tui (terminal)
@@ -23,6 +25,54 @@ struct Cli {
     /// List available backends and exit
     #[arg(long)]
     list_backends: bool,
+
+    /// Print the document as ANSI-styled text to stdout and exit (implied for the
+    /// tui backend when stdout is a pipe or a file)
+    #[arg(short, long)]
+    print: bool,
+
+    /// Render to a standalone file without opening a window (formats: html)
+    #[arg(long, value_name = "FORMAT", value_parser = parse_export_format)]
+    export: Option<String>,
+
+    /// Report broken Mermaid diagrams, missing images and invalid front matter
+    /// (in every file, for a directory) and exit non-zero if there are any
+    #[arg(long, conflicts_with_all = ["export", "serve"])]
+    check: bool,
+
+    /// Write every Mermaid diagram to DIR as numbered files (<name>-diagram-<n>.svg)
+    /// and exit
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["export", "serve", "check"])]
+    extract_diagrams: Option<PathBuf>,
+
+    /// File format for --extract-diagrams: svg or png
+    #[arg(long, value_name = "FORMAT", default_value = "svg", value_parser = parse_diagram_format, requires = "extract_diagrams")]
+    diagram_format: DiagramFormat,
+
+    /// Scale factor for PNG diagrams from --extract-diagrams
+    #[arg(long, value_name = "FACTOR", default_value_t = 2.0, value_parser = parse_scale, requires = "extract_diagrams")]
+    scale: f32,
+
+    /// Output path for --export (defaults to stdout)
+    #[arg(short, long, value_name = "PATH", requires = "export")]
+    output: Option<PathBuf>,
+
+    /// Follow the end of the document as it grows, like `tail -f` (toggle with F)
+    #[arg(long)]
+    follow: bool,
+
+    /// Watch files by polling instead of OS notifications (for network filesystems
+    /// and containers; used automatically on NFS, SMB, 9p and similar mounts)
+    #[arg(long)]
+    poll: bool,
+
+    /// Don't read or write rendered diagrams in the on-disk cache (~/.cache/mdr)
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Serve the rendered document over HTTP with live reload (default address: 127.0.0.1:8080)
+    #[arg(long, value_name = "ADDR", num_args = 0..=1, default_missing_value = core::serve::DEFAULT_ADDR, conflicts_with = "export")]
+    serve: Option<String>,
 }
 
 fn print_backends() {
@@ -43,6 +93,47 @@ fn parse_backend(s: &str) -> Result<String, String> {
     }
 }
 
+fn parse_export_format(s: &str) -> Result<String, String> {
+    match s {
+        "html" => Ok(s.to_string()),
+        _ => Err(format!("unknown export format '{}', expected 'html'", s)),
+    }
+}
+
+fn parse_diagram_format(s: &str) -> Result<DiagramFormat, String> {
+    match s {
+        "svg" => Ok(DiagramFormat::Svg),
+        "png" => Ok(DiagramFormat::Png),
+        _ => Err(format!("unknown diagram format '{}', expected 'svg' or 'png'", s)),
+    }
+}
+
+fn parse_scale(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale <= 16.0 => Ok(scale),
+        _ => Err(format!("invalid scale '{}', expected a number between 0 and 16", s)),
+    }
+}
+
+/// Whether stdout is a pipe or a file, as with `mdr f.md | less -R` or git's
+/// pager, which the tui backend prints to instead of drawing. GUI launches
+/// with stdout on /dev/null or a journal socket are neither.
+#[cfg(feature = "tui-backend")]
+fn stdout_is_pipe_or_file() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsFd;
+        use std::os::unix::fs::FileTypeExt;
+        io::stdout().as_fd().try_clone_to_owned()
+            .and_then(|fd| std::fs::File::from(fd).metadata())
+            .is_ok_and(|meta| meta.is_file() || meta.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        !io::stdout().is_terminal()
+    }
+}
+
 /// Auto-detect the best backend for the current environment.
 fn detect_backend() -> &'static str {
     // If no DISPLAY/WAYLAND and we have a TTY → TUI
@@ -83,37 +174,107 @@ fn detect_backend() -> &'static str {
     }
 }
 
-/// Read stdin and write to a temp file, returning its path.
-fn read_stdin_to_tmpfile() -> PathBuf {
-    let mut content = String::new();
-    io::stdin().lock().read_to_string(&mut content).unwrap_or_else(|e| {
-        eprintln!("Error: failed to read from stdin: {}", e);
-        process::exit(1);
-    });
-    let tmp_dir = std::env::temp_dir().join("mdr");
-    std::fs::create_dir_all(&tmp_dir).unwrap_or_else(|e| {
-        eprintln!("Error: failed to create temp directory: {}", e);
-        process::exit(1);
-    });
-    let tmp_file = tmp_dir.join(format!("stdin-{}.md", process::id()));
-    std::fs::write(&tmp_file, &content).unwrap_or_else(|e| {
-        eprintln!("Error: failed to write temp file: {}", e);
-        process::exit(1);
-    });
-    tmp_file
+/// Print the problems `--check` finds in `files`, one `path:line: message`
+/// per line, and return the exit code.
+fn check_files(files: &[PathBuf]) -> i32 {
+    let mut problems = 0;
+    for path in files {
+        match core::document::Document::open(path) {
+            Ok(document) => {
+                for diagnostic in document.check() {
+                    println!("{}:{}: {}", path.display(), diagnostic.line, diagnostic.message);
+                    problems += 1;
+                }
+            }
+            Err(e) => {
+                println!("{}: {}", path.display(), e);
+                problems += 1;
+            }
+        }
+    }
+    match problems {
+        0 => {
+            eprintln!("No problems found in {} file(s)", files.len());
+            0
+        }
+        n => {
+            eprintln!("{} problem(s) found", n);
+            1
+        }
+    }
+}
+
+/// Write the Mermaid diagrams of `files` into `dir`, in subdirectories
+/// mirroring their place under `root` for a directory, print the path of each
+/// file written, and return the exit code.
+fn extract_diagrams(files: &[PathBuf], root: Option<&Path>, dir: &Path, format: DiagramFormat, scale: f32) -> i32 {
+    let (mut written, mut failed) = (0, 0);
+    for path in files {
+        let target = match root.and_then(|root| path.strip_prefix(root).ok()).and_then(Path::parent) {
+            Some(subdir) => dir.join(subdir),
+            None => dir.to_path_buf(),
+        };
+        let document = core::document::Document::open(path).map_err(|e| e.to_string()).and_then(|document| {
+            std::fs::create_dir_all(&target)
+                .map(|()| document)
+                .map_err(|e| format!("cannot create '{}': {}", target.display(), e))
+        });
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        for result in core::export::extract_diagrams(&document, &target, format, scale) {
+            match result {
+                Ok(file) => {
+                    println!("{}", file.display());
+                    written += 1;
+                }
+                Err(problem) => {
+                    eprintln!("{}:{}: {}", path.display(), problem.line, problem.message);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    eprintln!("Wrote {} diagram(s) to {}", written, dir.display());
+    if failed > 0 {
+        eprintln!("{} error(s)", failed);
+        return 1;
+    }
+    0
 }
 
 fn main() {
     let cli = Cli::parse();
     core::set_verbose(cli.verbose);
+    core::watcher::set_polling(cli.poll);
+    core::cache::set_disk_cache(!cli.no_cache);
 
     if cli.list_backends {
         print_backends();
         process::exit(0);
     }
 
+    let mut workspace = None;
     let file = match cli.file {
-        Some(f) if f.as_os_str() == "-" => read_stdin_to_tmpfile(),
+        Some(f) if core::stdin::is_stdin(&f) => core::stdin::start(),
+        Some(f) if f.is_dir() => {
+            let ws = core::workspace::Workspace::open(&f).unwrap_or_else(|e| {
+                eprintln!("Error: failed to open directory '{}': {}", f.display(), e);
+                process::exit(1);
+            });
+            let Some(default) = ws.default_file().cloned() else {
+                eprintln!("Error: no Markdown files found in '{}'", f.display());
+                process::exit(1);
+            };
+            vlog!("Workspace {} has {} Markdown files, opening {}", ws.root().display(), ws.files().len(), default.display());
+            workspace = Some(ws);
+            default
+        }
         Some(f) => {
             if !f.exists() {
                 eprintln!("Error: file '{}' not found", f.display());
@@ -129,46 +290,84 @@ fn main() {
                 eprintln!("Try 'mdr --help' for more information.");
                 process::exit(1);
             }
-            read_stdin_to_tmpfile()
+            core::stdin::start()
         }
     };
 
-    let backend = if cli.backend == "auto" {
-        detect_backend()
-    } else {
-        cli.backend.as_str()
-    };
+    if cli.check {
+        core::stdin::wait_for_eof();
+        let files = match &workspace {
+            Some(ws) => ws.files().to_vec(),
+            None => vec![file],
+        };
+        process::exit(check_files(&files));
+    }
 
-    let result = match backend {
-        #[cfg(feature = "egui-backend")]
-        "egui" => backend::egui::run(file),
+    if let Some(dir) = &cli.extract_diagrams {
+        core::stdin::wait_for_eof();
+        let (files, root) = match &workspace {
+            Some(ws) => (ws.files().to_vec(), Some(ws.root())),
+            None => (vec![file], None),
+        };
+        process::exit(extract_diagrams(&files, root, dir, cli.diagram_format, cli.scale));
+    }
 
-        #[cfg(not(feature = "egui-backend"))]
-        "egui" => {
-            eprintln!("Error: egui backend not compiled. Rebuild with --features egui-backend");
+    if let Some(format) = cli.export.as_deref() {
+        core::stdin::wait_for_eof();
+        let result = match format {
+            "html" => core::export::export_html(&file),
+            _ => unreachable!(),
+        };
+        let written = result.and_then(|doc| {
+            core::export::write_output(&doc, cli.output.as_deref()).map_err(Into::into)
+        });
+        if let Err(e) = written {
+            eprintln!("Error: {}", e);
             process::exit(1);
         }
+        process::exit(0);
+    }
 
-        #[cfg(feature = "webview-backend")]
-        "webview" => backend::webview::run(file),
-
-        #[cfg(not(feature = "webview-backend"))]
-        "webview" => {
-            eprintln!("Error: webview backend not compiled. Rebuild with --features webview-backend");
+    if let Some(addr) = cli.serve.as_deref() {
+        let result = core::serve::parse_addr(addr)
+            .map_err(Into::into)
+            .and_then(|addr| core::serve::serve(&file, addr, cli.follow));
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
             process::exit(1);
         }
+        process::exit(0);
+    }
 
-        #[cfg(feature = "tui-backend")]
-        "tui" => backend::tui::run(file),
+    let backend = if cli.print {
+        "tui"
+    } else if cli.backend == "auto" {
+        detect_backend()
+    } else {
+        cli.backend.as_str()
+    };
 
-        #[cfg(not(feature = "tui-backend"))]
-        "tui" => {
-            eprintln!("Error: tui backend not compiled. Rebuild with --features tui-backend");
+    #[cfg(feature = "tui-backend")]
+    if backend == "tui" && (cli.print || stdout_is_pipe_or_file()) {
+        core::stdin::wait_for_eof();
+        let result = core::document::Document::open(&file)
+            .map_err(Into::into)
+            .and_then(|document| backend::tui::print(&document));
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
             process::exit(1);
         }
+        process::exit(0);
+    }
 
-        _ => unreachable!(),
+    let Some(viewer) = backend::by_name(backend) else {
+        eprintln!("Error: {0} backend not compiled. Rebuild with --features {0}-backend", backend);
+        process::exit(1);
     };
+    let options = backend::ViewOptions { workspace, follow: cli.follow };
+    let result = core::document::Document::open(&file)
+        .map_err(Into::into)
+        .and_then(|document| viewer.run(document, options));
 
     if let Err(e) = result {
         eprintln!("Error: {}", e);
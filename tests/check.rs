@@ -0,0 +1,38 @@
+use std::process::Command;
+
+/// Helper to get the path to the mdr binary built by cargo test.
+fn mdr_bin() -> std::path::PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // remove test binary name
+    path.pop(); // remove "deps"
+    path.push("mdr");
+    path
+}
+
+#[test]
+fn check_reports_broken_diagrams_with_markdown_lines() {
+    let dir = std::env::temp_dir().join("mdr_test_check_cli");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("doc.md");
+    std::fs::write(
+        &path,
+        "# Doc\n\n```mermaid\ngraph LR\n  A --> B\n  B -->\n```\n\n```mermaid\nflowchart TD\n  A-->B\n```\n\n```mermaid\nsequence\n```\n",
+    )
+    .unwrap();
+
+    let output = Command::new(mdr_bin()).arg("--check").arg(&path).output().expect("failed to run mdr");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "got: {}", stdout);
+    assert_eq!(lines[0], format!("{}:6: mermaid: edge has no target node (column 5)", path.display()));
+    assert!(lines[1].starts_with(&format!("{}:15: mermaid: unknown diagram type `sequence`", path.display())));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 problem(s) found"));
+
+    std::fs::write(&path, "# Doc\n\n```mermaid\ngraph LR\n  A --> B\n```\n").unwrap();
+    let output = Command::new(mdr_bin()).arg("--check").arg(&path).output().expect("failed to run mdr");
+    assert!(output.status.success(), "stdout: {}", String::from_utf8_lossy(&output.stdout));
+    assert!(output.stdout.is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,99 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Helper to get the path to the mdr binary built by cargo test.
+fn mdr_bin() -> std::path::PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // remove test binary name
+    path.pop(); // remove "deps"
+    path.push("mdr");
+    path
+}
+
+#[test]
+fn export_html_to_stdout() {
+    let dir = std::env::temp_dir().join("mdr_it_export_stdout");
+    std::fs::create_dir_all(&dir).unwrap();
+    let md_path = dir.join("doc.md");
+    std::fs::write(&md_path, "# Exported\n\nSome *text*.\n").unwrap();
+
+    let output = Command::new(mdr_bin())
+        .arg("--export")
+        .arg("html")
+        .arg(&md_path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run mdr");
+
+    assert!(output.status.success(), "export should succeed, stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("<!DOCTYPE html>"), "stdout should be an HTML document, got: {}", stdout);
+    assert!(stdout.contains(r#"<h1 id="exported">"#));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn export_html_to_output_file_from_stdin() {
+    let dir = std::env::temp_dir().join("mdr_it_export_file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let out_path = dir.join("out.html");
+
+    let mut child = Command::new(mdr_bin())
+        .args(["--export", "html", "-o"])
+        .arg(&out_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(b"# From stdin\n").unwrap();
+    }
+
+    let output = child.wait_with_output().expect("failed to wait");
+    assert!(output.status.success(), "export should succeed, stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty(), "nothing should be written to stdout when -o is given");
+
+    let html = std::fs::read_to_string(&out_path).unwrap();
+    assert!(html.contains(r#"<h1 id="from-stdin">"#), "got: {}", html);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn export_unknown_format_is_rejected() {
+    let output = Command::new(mdr_bin())
+        .args(["--export", "pdf", "README.md"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run mdr");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("unknown export format"), "got stderr: {}", stderr);
+}
+
+#[test]
+fn export_html_hides_front_matter_and_uses_its_title() {
+    let dir = std::env::temp_dir().join("mdr_it_export_front_matter");
+    std::fs::create_dir_all(&dir).unwrap();
+    let md_path = dir.join("post.md");
+    std::fs::write(&md_path, "---\ntitle: Launch Plan\nowner: ops\n---\n# Steps\n").unwrap();
+
+    let output = Command::new(mdr_bin())
+        .args(["--export", "html"])
+        .arg(&md_path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run mdr");
+
+    assert!(output.status.success(), "export should succeed, stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("<title>Launch Plan</title>"), "got: {}", stdout);
+    assert!(stdout.contains("<tr><th>owner</th><td>ops</td></tr>"));
+    assert!(!stdout.contains("<hr"), "front matter must not render as a rule");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,49 @@
+use std::process::Command;
+
+/// Helper to get the path to the mdr binary built by cargo test.
+fn mdr_bin() -> std::path::PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // remove test binary name
+    path.pop(); // remove "deps"
+    path.push("mdr");
+    path
+}
+
+#[test]
+fn extract_diagrams_writes_numbered_files() {
+    let dir = std::env::temp_dir().join("mdr_test_extract_diagrams_cli");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("doc.md");
+    std::fs::write(
+        &path,
+        "# Doc\n\n```mermaid\ngraph LR\n  A --> B\n```\n\n```mermaid\npie\n  \"a\" : 1\n  \"b\" : 2\n```\n",
+    )
+    .unwrap();
+    let out = dir.join("out");
+
+    let output = Command::new(mdr_bin())
+        .args(["--extract-diagrams"])
+        .arg(&out)
+        .args(["--diagram-format", "png", "--scale", "2"])
+        .arg(&path)
+        .output()
+        .expect("failed to run mdr");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines, [out.join("doc-diagram-1.png").display().to_string(), out.join("doc-diagram-2.png").display().to_string()]);
+    for line in lines {
+        let png = std::fs::read(line).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+    }
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Wrote 2 diagram(s)"));
+
+    // A broken diagram is reported with its line and fails the run
+    std::fs::write(&path, "# Doc\n\n```mermaid\ngraph LR\n  A --> B\n  B -->\n```\n").unwrap();
+    let output = Command::new(mdr_bin()).arg("--extract-diagrams").arg(&out).arg(&path).output().expect("failed to run mdr");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("{}:6: mermaid: edge has no target node", path.display())));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
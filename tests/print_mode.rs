@@ -0,0 +1,110 @@
+#![cfg(feature = "tui-backend")]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Helper to get the path to the mdr binary built by cargo test.
+fn mdr_bin() -> std::path::PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // remove test binary name
+    path.pop(); // remove "deps"
+    path.push("mdr");
+    path
+}
+
+fn run_print(markdown: &str, envs: &[(&str, &str)], args: &[&str]) -> std::process::Output {
+    let mut cmd = Command::new(mdr_bin());
+    cmd.args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .env_remove("NO_COLOR");
+    for (key, value) in envs {
+        cmd.env(key, value);
+    }
+    let mut child = cmd.spawn().expect("failed to spawn mdr");
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(markdown.as_bytes()).unwrap();
+    }
+    child.wait_with_output().expect("failed to wait")
+}
+
+#[test]
+fn print_flag_writes_ansi_and_exits() {
+    let output = run_print("# Title\n\nSome **bold** text.\n", &[("COLUMNS", "80")], &["--print"]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Title"));
+    assert!(stdout.contains("\x1b["), "styled output should contain ANSI escapes, got: {:?}", stdout);
+    assert!(!stdout.contains("\x1b[?1049h"), "print mode must not enter the alternate screen");
+}
+
+#[test]
+fn piped_stdout_with_tui_backend_prints() {
+    let output = run_print("Hello from a pipe\n", &[("NO_COLOR", "1")], &["-b", "tui"]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Hello from a pipe"));
+    assert!(!stdout.contains('\x1b'), "NO_COLOR should disable escapes, got: {:?}", stdout);
+}
+
+// macOS and Windows always have a display, so auto picks a GUI there
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[test]
+fn auto_backend_without_a_display_prints_to_a_pipe_or_file() {
+    let dir = std::env::temp_dir().join("mdr_test_print_auto");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("doc.md");
+    std::fs::write(&path, "Hello from git's pager\n").unwrap();
+    let mdr = || {
+        let mut cmd = Command::new(mdr_bin());
+        cmd.arg(&path).env("NO_COLOR", "1").env_remove("DISPLAY").env_remove("WAYLAND_DISPLAY");
+        cmd
+    };
+
+    let output = mdr().stdin(Stdio::null()).output().expect("failed to run mdr");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Hello from git's pager"));
+
+    let out = dir.join("out.txt");
+    let status = mdr().stdin(Stdio::null()).stdout(std::fs::File::create(&out).unwrap()).status().expect("failed to run mdr");
+    assert!(status.success());
+    assert!(std::fs::read_to_string(&out).unwrap().contains("Hello from git's pager"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn print_wraps_to_columns() {
+    let paragraph = "word ".repeat(40);
+    let output = run_print(&paragraph, &[("COLUMNS", "30"), ("NO_COLOR", "1")], &["--print"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().filter(|l| !l.is_empty()).collect();
+    assert!(lines.len() > 1, "long paragraph should wrap, got: {:?}", stdout);
+    for line in lines {
+        assert!(line.chars().count() <= 30, "line exceeds COLUMNS: {:?}", line);
+    }
+}
+
+#[test]
+fn directory_argument_prints_readme() {
+    let dir = std::env::temp_dir().join("mdr_test_print_directory");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("guide")).unwrap();
+    std::fs::write(dir.join("guide/intro.md"), "# Intro\n").unwrap();
+    std::fs::write(dir.join("README.md"), "# Project readme\n").unwrap();
+
+    let output = run_print("", &[("NO_COLOR", "1")], &["--print", dir.to_str().unwrap()]);
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Project readme"));
+
+    let empty = dir.join("guide/empty");
+    std::fs::create_dir_all(&empty).unwrap();
+    let output = run_print("", &[], &["--print", empty.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no Markdown files"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,144 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+/// Helper to get the path to the mdr binary built by cargo test.
+fn mdr_bin() -> std::path::PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // remove test binary name
+    path.pop(); // remove "deps"
+    path.push("mdr");
+    path
+}
+
+/// Kills the server when the test ends, even if it panics.
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn get(addr: &str, path: &str) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).expect("failed to connect to mdr --serve");
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr).unwrap();
+    stream
+}
+
+#[test]
+fn serve_renders_document_and_streams_reloads() {
+    let dir = std::env::temp_dir().join("mdr_test_serve");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("doc.md");
+    std::fs::write(&file, "# Served title\n").unwrap();
+
+    let mut child = Command::new(mdr_bin())
+        .args(["--serve", "127.0.0.1:0"])
+        .arg(&file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+    let stderr = child.stderr.take().unwrap();
+    let _server = Server(child);
+
+    // The bound address is announced on stderr: "Serving <file> at http://<addr>"
+    let mut line = String::new();
+    BufReader::new(stderr).read_line(&mut line).unwrap();
+    let addr = line.trim().rsplit("http://").next().unwrap().to_string();
+
+    let mut page = String::new();
+    get(&addr, "/").read_to_string(&mut page).unwrap();
+    assert!(page.starts_with("HTTP/1.1 200 OK"), "got: {}", page);
+    assert!(page.contains("Served title"));
+    assert!(page.contains("new EventSource('/events')"));
+
+    let mut missing = String::new();
+    get(&addr, "/nope").read_to_string(&mut missing).unwrap();
+    assert!(missing.starts_with("HTTP/1.1 404"));
+
+    let mut events = BufReader::new(get(&addr, "/events"));
+    let mut headers = String::new();
+    line.clear();
+    while events.read_line(&mut line).unwrap() > 0 {
+        headers.push_str(&line);
+        if line == ": connected\n" {
+            break;
+        }
+        line.clear();
+    }
+    assert!(headers.contains("text/event-stream"), "got: {}", headers);
+
+    std::fs::write(&file, "# Edited title\n").unwrap();
+    // Skip blank separators and keepalive comments until the next event
+    loop {
+        line.clear();
+        assert!(events.read_line(&mut line).unwrap() > 0, "event stream closed");
+        if !line.trim().is_empty() && !line.starts_with(':') {
+            break;
+        }
+    }
+    assert_eq!(line, "event: reload\n");
+
+    // The page is re-rendered before clients are told to reload
+    page.clear();
+    get(&addr, "/").read_to_string(&mut page).unwrap();
+    assert!(page.contains("Edited title") && !page.contains("Served title"), "got: {}", page);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Read the next SSE event line, skipping data, blank separators and comments.
+fn next_event(events: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        assert!(events.read_line(&mut line).unwrap() > 0, "event stream closed");
+        if line.starts_with("event:") {
+            return line;
+        }
+    }
+}
+
+#[test]
+fn serve_streams_stdin_as_it_arrives() {
+    let mut child = Command::new(mdr_bin())
+        .args(["--serve", "127.0.0.1:0", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn mdr");
+    let stderr = child.stderr.take().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let _server = Server(child);
+
+    let mut line = String::new();
+    BufReader::new(stderr).read_line(&mut line).unwrap();
+    let addr = line.trim().rsplit("http://").next().unwrap().to_string();
+
+    let mut events = BufReader::new(get(&addr, "/events"));
+    while events.read_line(&mut line).unwrap() > 0 && line != ": connected\n" {
+        line.clear();
+    }
+
+    // The pipe stays open: each chunk is rendered without waiting for EOF
+    stdin.write_all(b"# First chunk\n").unwrap();
+    stdin.flush().unwrap();
+    assert_eq!(next_event(&mut events), "event: reload\n");
+    let mut page = String::new();
+    get(&addr, "/").read_to_string(&mut page).unwrap();
+    assert!(page.contains("First chunk"), "got: {}", page);
+
+    stdin.write_all(b"\nSecond chunk\n").unwrap();
+    stdin.flush().unwrap();
+    assert_eq!(next_event(&mut events), "event: reload\n");
+    page.clear();
+    get(&addr, "/").read_to_string(&mut page).unwrap();
+    assert!(page.contains("First chunk") && page.contains("Second chunk"), "got: {}", page);
+}